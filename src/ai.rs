@@ -1,6 +1,8 @@
 use ollama_rs::{Ollama, generation::completion::request::GenerationRequest};
 use crate::telegram::MessageInfo;
-use log::{debug, error};
+use crate::database::{Feedback, FeedbackKind};
+use crate::taxonomy::{self, Taxonomy};
+use log::{debug, error, warn};
 use serde::{Deserialize, Serialize};
 use anyhow::Result;
 
@@ -8,6 +10,8 @@ use anyhow::Result;
 const CONTEXT_MSG_COUNT: usize = 1000;
 /// Default model to use for Ollama (can be overridden via OLLAMA_MODEL env var).
 const DEFAULT_MODEL: &str = "mistral-small:latest";
+/// Longest message excerpt quoted in a correction example.
+const CORRECTION_EXCERPT_CHARS: usize = 200;
 /// Default embedding model to use (can be overridden via OLLAMA_EMBED_MODEL env var).
 const DEFAULT_EMBED_MODEL: &str = "bge-m3";
 
@@ -39,11 +43,81 @@ pub async fn generate_embedding(text: &str) -> Result<Vec<f32>> {
     Ok(embedding_response.embeddings[0].clone())
 }
 
-/// Analyze a chat's messages using a local LLM via Ollama.
-/// Returns (category, suggested_name, urgent_msg_ids).
-pub async fn analyze_chat(chat_title: &str, messages: &[MessageInfo]) -> Result<(String, String, Vec<i32>), Box<dyn std::error::Error>> {
-    // Determine which model to use
-    let model = std::env::var("OLLAMA_MODEL").unwrap_or_else(|_| DEFAULT_MODEL.to_string());
+/// Compute embeddings for a batch of messages, in order. Once the embedding model fails, the
+/// remaining messages get none: they are stored without one and embedded on a later monitor
+/// cycle, so storing messages (and the rules and watchlists) never waits on the model.
+pub async fn embed_messages(messages: Vec<MessageInfo>) -> Vec<(MessageInfo, Option<Vec<f32>>)> {
+    let mut embedded = Vec::with_capacity(messages.len());
+    let mut available = true;
+    for msg in messages {
+        let embedding = if available {
+            match generate_embedding(&msg.text).await {
+                Ok(embedding) => Some(embedding),
+                Err(e) => {
+                    warn!("Embedding failed, storing messages without embeddings for now: {}", e);
+                    available = false;
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        embedded.push((msg, embedding));
+    }
+    embedded
+}
+
+/// Helper: describe one correction as a line of the prompt (None if there is nothing to show).
+fn correction_example(feedback: &Feedback) -> Option<String> {
+    let excerpt = || {
+        let text: String = feedback.message_text.as_deref()?.replace('\n', " ").chars().take(CORRECTION_EXCERPT_CHARS).collect();
+        Some(text)
+    };
+    match feedback.kind {
+        FeedbackKind::CategoryOverride => {
+            let value = feedback.value.as_deref()?;
+            Some(match feedback.previous.as_deref() {
+                Some(previous) if previous != value => format!(
+                    "Chat \"{}\" has category \"{}\" (not \"{}\").", feedback.chat_name, value, previous),
+                _ => format!("Chat \"{}\" has category \"{}\".", feedback.chat_name, value),
+            })
+        }
+        FeedbackKind::UrgentFalsePositive => Some(format!(
+            "In \"{}\", the message \"{}\" is NOT urgent.", feedback.chat_name, excerpt()?)),
+        FeedbackKind::UrgentFalseNegative => Some(format!(
+            "In \"{}\", the message \"{}\" IS urgent.", feedback.chat_name, excerpt()?)),
+    }
+}
+
+/// A tag proposed by the model, with its confidence (0 to 1).
+#[derive(Debug, Clone)]
+pub struct ProposedTag {
+    pub name: String,
+    pub confidence: f64,
+}
+
+/// Result of analyzing a chat.
+#[derive(Debug, Clone)]
+pub struct ChatAnalysis {
+    pub category: String,
+    pub suggested_name: String,
+    pub urgent_ids: Vec<i32>,
+    pub tags: Vec<ProposedTag>,                // tags for the chat as a whole
+    pub message_tags: Vec<(i32, ProposedTag)>, // (msg_id, tag) for individual messages
+}
+
+/// Model used for chat analysis (OLLAMA_MODEL env var, or the default).
+pub fn analysis_model() -> String {
+    std::env::var("OLLAMA_MODEL").unwrap_or_else(|_| DEFAULT_MODEL.to_string())
+}
+
+/// Analyze a chat's messages using a local LLM via Ollama, with the given model.
+/// The category is chosen from `taxonomy`; an answer outside it is mapped onto the closest
+/// category, or `Other`.
+/// `corrections` are recent user corrections, included in the prompt as examples to follow.
+/// The model also proposes tags, with confidences, for the chat and for individual messages.
+pub async fn analyze_chat(model: &str, chat_title: &str, messages: &[MessageInfo], taxonomy: &Taxonomy, corrections: &[Feedback]) -> Result<ChatAnalysis, Box<dyn std::error::Error>> {
+    let model = model.to_string();
     let ollama = Ollama::default(); // connect to local Ollama at default http://localhost:11434
     // Prepare the prompt with up to CONTEXT_MSG_COUNT latest messages
     let count = messages.len().min(CONTEXT_MSG_COUNT);
@@ -53,14 +127,32 @@ pub async fn analyze_chat(chat_title: &str, messages: &[MessageInfo]) -> Result<
     for (i, msg) in messages.iter().skip(start_index).enumerate() {
         let idx = i + 1;  // 1-indexed in the prompt
         let text = msg.text.replace('\n', " "); // flatten newlines
-        prompt_text += &format!("{}. {}\n", idx, text);
+        // Messages addressed to the user are marked so direct asks weigh more
+        let marker = if msg.mentions_me { "[to me] " } else if msg.outgoing { "[me] " } else { "" };
+        prompt_text += &format!("{}. {}{}\n", idx, marker, text);
+    }
+    // Past corrections act as few-shot examples
+    let examples: Vec<String> = corrections.iter().filter_map(correction_example).collect();
+    if !examples.is_empty() {
+        prompt_text += "\nThe user corrected earlier answers like this; follow the same judgement for similar chats and messages:\n";
+        for example in &examples {
+            prompt_text += &format!("- {}\n", example);
+        }
     }
     // Append instructions for the AI
-    prompt_text += "\nDetermine a short category for the topic of this chat.\n";
-    prompt_text += "Identify if any of the above messages are urgent (needing immediate attention) and list their numbers (or 'None').\n";
+    prompt_text += "\nChoose the category that best fits the topic of this chat from this list, \
+        answering with its exact path (use the most specific one that fits, or \"Other\" if none does):\n";
+    prompt_text += &taxonomy.prompt_section();
+    prompt_text += "Identify if any of the above messages are urgent (needing immediate attention) and list their numbers (or 'None'). \
+        Messages marked [to me] mention the user or reply to them and [me] marks the user's own messages; \
+        a question or request marked [to me] that the user has not answered is more likely urgent.\n";
     prompt_text += "Suggest a concise, standardized name for this chat.\n";
+    prompt_text += "Propose up to 5 short tags for the chat (topics, projects, clients, e.g. \"engineering\" or \"client: acme\"), \
+        each with your confidence from 0 to 1, and tags for individual messages where useful.\n";
     prompt_text += "Provide the answer in the format:\n";
     prompt_text += "Category: <category>\nUrgent: <list of message numbers or None>\nSuggestedName: <suggested name>\n";
+    prompt_text += "Tags: <tag> (<confidence>), <tag> (<confidence>) or None\n";
+    prompt_text += "MessageTags: <message number>: <tag> (<confidence>); <message number>: <tag> (<confidence>) or None\n";
     debug!("AI Prompt:\n{}", prompt_text);
 
     // Send the prompt to the local model
@@ -77,9 +169,33 @@ pub async fn analyze_chat(chat_title: &str, messages: &[MessageInfo]) -> Result<
     let mut category = String::new();
     let mut suggested_name = String::new();
     let mut urgent_list: Vec<i32> = Vec::new();
+    let mut tags = Vec::new();
+    let mut message_tags = Vec::new();
+    // Map a message number from the prompt back to its message ID
+    let msg_id_at = |num: usize| -> Option<i32> {
+        if !(1..=count).contains(&num) {
+            return None;
+        }
+        // We enumerated messages.skip(start_index), so offset index
+        messages.get(start_index + (num - 1)).map(|m| m.msg_id)
+    };
     for line in ai_text.lines() {
         let trimmed = line.trim();
-        if trimmed.to_lowercase().starts_with("category:") {
+        if trimmed.to_lowercase().starts_with("messagetags:") {
+            // e.g. "MessageTags: 3: invoice (0.8); 7: deadline (0.6), client: acme (0.5)"
+            for entry in trimmed["MessageTags:".len()..].split(';') {
+                let Some((num, rest)) = entry.split_once(':') else {
+                    continue;
+                };
+                let Some(msg_id) = num.trim().parse::<usize>().ok().and_then(msg_id_at) else {
+                    continue;
+                };
+                message_tags.extend(parse_tags(rest).into_iter().map(|tag| (msg_id, tag)));
+            }
+        } else if trimmed.to_lowercase().starts_with("tags:") {
+            // e.g. "Tags: engineering (0.9), client: acme (0.7)"
+            tags = parse_tags(&trimmed[5..]);
+        } else if trimmed.to_lowercase().starts_with("category:") {
             // e.g. "Category: Project Discussion"
             category = trimmed[9..].trim().to_string();
         } else if trimmed.to_lowercase().starts_with("suggestedname:") {
@@ -93,27 +209,139 @@ pub async fn analyze_chat(chat_title: &str, messages: &[MessageInfo]) -> Result<
             } else {
                 // Extract numbers from the rest of the line
                 for token in rest.split(|c| c == ',' || c == ';' || c == ' ') {
-                    if let Ok(num) = token.trim().parse::<usize>() {
-                        // Map the prompt message index back to actual message ID
-                        // Prompt enumerated last `count` messages starting at index 1.
-                        // If num is <= count, find that message from the slice we sent.
-                        if num >= 1 && num <= count {
-                            // We enumerated messages.skip(start_index), so offset index
-                            let actual_index = start_index + (num - 1);
-                            if let Some(msg) = messages.get(actual_index) {
-                                urgent_list.push(msg.msg_id);
-                            }
-                        }
+                    if let Some(msg_id) = token.trim().parse::<usize>().ok().and_then(msg_id_at) {
+                        urgent_list.push(msg_id);
                     }
                 }
             }
         }
     }
-    if category.is_empty() {
-        category = "Uncategorized".into();
-    }
+    category = if category.is_empty() {
+        taxonomy::OTHER.to_string()
+    } else {
+        taxonomy.resolve(&category)
+    };
     if suggested_name.is_empty() {
         suggested_name = chat_title.to_string();
     }
-    Ok((category, suggested_name, urgent_list))
-} 
\ No newline at end of file
+    Ok(ChatAnalysis { category, suggested_name, urgent_ids: urgent_list, tags, message_tags })
+}
+
+/// Helper: parse a comma-separated list of `tag (confidence)` items. A missing or
+/// unreadable confidence counts as 0.5; "None" yields no tags.
+fn parse_tags(list: &str) -> Vec<ProposedTag> {
+    let list = list.trim();
+    if list.is_empty() || list.eq_ignore_ascii_case("none") {
+        return Vec::new();
+    }
+    list.split(',')
+        .filter_map(|item| {
+            let item = item.trim();
+            let (name, confidence) = match item.rsplit_once('(') {
+                Some((name, rest)) => (name, rest.trim_end_matches(')').trim().parse::<f64>().unwrap_or(0.5)),
+                None => (item, 0.5),
+            };
+            let name = name.trim().trim_matches(|c| c == '"' || c == '#').trim();
+            (!name.is_empty() && !name.eq_ignore_ascii_case("none"))
+                .then(|| ProposedTag { name: name.to_string(), confidence: confidence.clamp(0.0, 1.0) })
+        })
+        .collect()
+}
+
+/// Summarize a digest's source material (urgent items and recent messages of the most
+/// active chats) using the local LLM.
+/// Returns (summary, action_items).
+pub async fn summarize_digest(model: &str, period_label: &str, material: &str) -> Result<(String, Vec<String>), Box<dyn std::error::Error>> {
+    let ollama = Ollama::default();
+    let mut prompt_text = format!("Below are highlights from Telegram group chats over the {}.\n\n", period_label);
+    prompt_text += material;
+    prompt_text += "\nWrite a short summary (at most 5 sentences) of what happened and what needs attention.\n";
+    prompt_text += "Then list concrete action items for the reader (things they were asked to do or should follow up on), one per line, or 'None'.\n";
+    prompt_text += "Provide the answer in the format:\n";
+    prompt_text += "Summary: <summary>\nActionItems:\n- <action item>\n- <action item>\n";
+    debug!("Digest Prompt:\n{}", prompt_text);
+
+    let request = GenerationRequest::new(model.to_string(), prompt_text);
+    let response = ollama.generate(request).await;
+    if let Err(e) = response {
+        return Err(format!("AI generation failed: {}", e).into());
+    }
+    let ai_text = response.unwrap().response.trim().to_string();
+    debug!("Digest Raw Response:\n{}", ai_text);
+
+    // Parse "Summary:" (possibly spanning several lines) and the bullet list after "ActionItems:"
+    let mut summary = String::new();
+    let mut action_items = Vec::new();
+    let mut in_actions = false;
+    for line in ai_text.lines() {
+        let trimmed = line.trim();
+        let lower = trimmed.to_lowercase();
+        if lower.starts_with("summary:") {
+            summary = trimmed["summary:".len()..].trim().to_string();
+            in_actions = false;
+        } else if lower.starts_with("actionitems:") || lower.starts_with("action items:") {
+            in_actions = true;
+            let rest = trimmed[trimmed.find(':').unwrap_or(0) + 1..].trim();
+            if !rest.is_empty() && !rest.eq_ignore_ascii_case("none") {
+                action_items.push(rest.to_string());
+            }
+        } else if in_actions {
+            let item = trimmed.trim_start_matches(|c: char| c == '-' || c == '*' || c == '•').trim();
+            if !item.is_empty() && !item.eq_ignore_ascii_case("none") {
+                action_items.push(item.to_string());
+            }
+        } else if !trimmed.is_empty() && !summary.is_empty() {
+            summary.push(' ');
+            summary.push_str(trimmed);
+        }
+    }
+    if summary.is_empty() {
+        summary = ai_text.lines().next().unwrap_or("").to_string();
+    }
+    Ok((summary, action_items))
+}
+
+/// Describe a person's topics of interest from a sample of their messages, given as
+/// (chat name, text), using the local LLM.
+/// Returns a short profile: the main topics followed by one or two sentences.
+pub async fn profile_person(model: &str, name: &str, messages: &[(String, String)]) -> Result<String, Box<dyn std::error::Error>> {
+    let ollama = Ollama::default();
+    let mut prompt_text = format!("Below are messages {} wrote in Telegram group chats, with the chat name.\n\n", name);
+    for (chat, text) in messages {
+        prompt_text += &format!("[{}] {}\n", chat, text.replace('\n', " "));
+    }
+    prompt_text += "\nList the topics this person is most interested in or knowledgeable about (at most 6, most prominent first), ";
+    prompt_text += "then describe their interests and role in these chats in one or two sentences. Do not speculate about private matters.\n";
+    prompt_text += "Provide the answer in the format:\n";
+    prompt_text += "Topics: <topic>, <topic>, ...\nProfile: <description>\n";
+    debug!("Person Prompt:\n{}", prompt_text);
+
+    let request = GenerationRequest::new(model.to_string(), prompt_text);
+    let response = ollama.generate(request).await;
+    if let Err(e) = response {
+        return Err(format!("AI generation failed: {}", e).into());
+    }
+    let ai_text = response.unwrap().response.trim().to_string();
+    debug!("Person Raw Response:\n{}", ai_text);
+
+    let mut topics = String::new();
+    let mut profile = String::new();
+    for line in ai_text.lines() {
+        let trimmed = line.trim();
+        let lower = trimmed.to_lowercase();
+        if lower.starts_with("topics:") {
+            topics = trimmed["topics:".len()..].trim().to_string();
+        } else if lower.starts_with("profile:") {
+            profile = trimmed["profile:".len()..].trim().to_string();
+        } else if !trimmed.is_empty() && !profile.is_empty() {
+            profile.push(' ');
+            profile.push_str(trimmed);
+        }
+    }
+    Ok(match (topics.is_empty(), profile.is_empty()) {
+        (true, true) => ai_text,
+        (false, true) => format!("Topics: {}", topics),
+        (true, false) => profile,
+        (false, false) => format!("Topics: {}\n{}", topics, profile),
+    })
+}
@@ -0,0 +1,174 @@
+use std::collections::BTreeMap;
+use log::info;
+use libsql::Connection;
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use crate::database::{self, ChatFilter, DailyStats};
+
+/// Days covered by `stats` when no `--since` is given.
+pub const DEFAULT_DAYS: i64 = 30;
+/// Number of users listed under top posters.
+const TOP_POSTERS: i64 = 10;
+/// Widest sparkline; longer ranges are summed into this many buckets.
+const MAX_SPARK_WIDTH: usize = 60;
+/// Longest chat name shown in the stats table.
+const NAME_CHARS: usize = 28;
+/// Sparkline glyphs from lowest to highest.
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// One chat's totals over the stats window.
+struct ChatTotals<'a> {
+    name: &'a str,
+    messages: i64,
+    own_messages: i64,
+    mentions: i64,
+    first_members: Option<i64>,
+    last_members: Option<i64>,
+    per_day: Vec<i64>,  // one value per day of the window
+}
+
+/// Restrict the filter to the last `days` days (including today) unless it has a start date already.
+pub fn with_default_window(filter: &ChatFilter, days: i64) -> ChatFilter {
+    let mut filter = filter.clone();
+    if filter.since.is_none() {
+        let today = Utc::now().date_naive();
+        let start = today - Duration::days(days.max(1) - 1);
+        filter.since = Some(start.and_time(NaiveTime::MIN).and_utc().timestamp());
+    }
+    filter
+}
+
+/// Print activity statistics for the filter's chats and date range: per-chat message counts
+/// with a daily sparkline and member growth, the busiest hours of the day, and the top posters.
+/// The daily aggregates are refreshed first, so the output includes the latest stored messages.
+pub async fn print_stats(conn: &mut Connection, filter: &ChatFilter) -> Result<(), Box<dyn std::error::Error>> {
+    database::refresh_daily_stats(conn).await?;
+    let days = window_days(filter);
+    let rows = database::get_daily_stats(conn, filter).await?;
+    let (Some(first), Some(last)) = (days.first(), days.last()) else {
+        println!("Empty date range.");
+        return Ok(());
+    };
+    println!("Activity {} to {} (UTC), {} days\n", first, last, days.len());
+
+    let day_index: BTreeMap<String, usize> = days.iter().enumerate().map(|(i, d)| (d.to_string(), i)).collect();
+    let mut chats: BTreeMap<&str, ChatTotals> = BTreeMap::new();
+    let mut total_per_day = vec![0i64; days.len()];
+    for row in &rows {
+        let totals = chats.entry(row.chat_peer.as_str()).or_insert_with(|| ChatTotals {
+            name: &row.chat_name,
+            messages: 0,
+            own_messages: 0,
+            mentions: 0,
+            first_members: None,
+            last_members: None,
+            per_day: vec![0; days.len()],
+        });
+        totals.messages += row.messages;
+        totals.own_messages += row.own_messages;
+        totals.mentions += row.mentions;
+        if row.member_count.is_some() {
+            totals.first_members = totals.first_members.or(row.member_count);
+            totals.last_members = row.member_count;
+        }
+        if let Some(&i) = day_index.get(&row.day) {
+            totals.per_day[i] += row.messages;
+            total_per_day[i] += row.messages;
+        }
+    }
+    let mut chats: Vec<ChatTotals> = chats.into_values().filter(|c| c.messages > 0 || c.last_members.is_some()).collect();
+    if chats.is_empty() {
+        println!("No activity recorded in this range.");
+        return Ok(());
+    }
+    chats.sort_by(|a, b| b.messages.cmp(&a.messages).then_with(|| a.name.to_lowercase().cmp(&b.name.to_lowercase())));
+
+    println!("{:<w$}  {:>7}  {:>6}  {:>5}  {:>6}  {:<14}  {}", "Chat", "Msgs", "/day", "Yours", "To you", "Members", "Messages per day", w = NAME_CHARS);
+    for c in &chats {
+        let name: String = c.name.chars().take(NAME_CHARS).collect();
+        let members = match (c.first_members, c.last_members) {
+            (Some(first), Some(last)) if first != last => format!("{} ({:+})", last, last - first),
+            (_, Some(last)) => last.to_string(),
+            _ => "-".into(),
+        };
+        println!("{:<w$}  {:>7}  {:>6.1}  {:>5}  {:>6}  {:<14}  {}", name, c.messages, c.messages as f64 / days.len() as f64,
+            c.own_messages, c.mentions, members, sparkline(&c.per_day), w = NAME_CHARS);
+    }
+    let total: i64 = total_per_day.iter().sum();
+    println!("{:<w$}  {:>7}  {:>6.1}  {:>5}  {:>6}  {:<14}  {}", "Total", total, total as f64 / days.len() as f64,
+        chats.iter().map(|c| c.own_messages).sum::<i64>(), chats.iter().map(|c| c.mentions).sum::<i64>(), "",
+        sparkline(&total_per_day), w = NAME_CHARS);
+
+    let hours = database::get_hourly_activity(conn, filter).await?;
+    let mut busiest: Vec<usize> = (0..24).filter(|h| hours[*h] > 0).collect();
+    busiest.sort_by_key(|h| std::cmp::Reverse(hours[*h]));
+    println!("\nBusiest hours (UTC)");
+    println!("  {}", sparkline(&hours));
+    println!("  0h{:>22}", "23h");
+    if !busiest.is_empty() {
+        let top: Vec<String> = busiest.iter().take(3).map(|h| format!("{:02}:00 ({})", h, hours[*h])).collect();
+        println!("  Peak: {}", top.join(", "));
+    }
+
+    let posters = database::get_top_posters(conn, filter, TOP_POSTERS).await?;
+    if !posters.is_empty() {
+        println!("\nTop posters");
+        for p in &posters {
+            let name = p.name.clone().unwrap_or_else(|| format!("user {}", p.user_id));
+            let username = p.username.as_deref().map(|u| format!(" (@{})", u)).unwrap_or_default();
+            println!("  {:>6}  {}{} in {} chat(s)", p.messages, name, username, p.chats);
+        }
+    }
+    Ok(())
+}
+
+/// Write the daily aggregates for the filter's chats and date range as CSV, to `out` or stdout.
+pub async fn write_csv(conn: &mut Connection, filter: &ChatFilter, out: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    database::refresh_daily_stats(conn).await?;
+    let rows: Vec<DailyStats> = database::get_daily_stats(conn, filter).await?;
+    match out {
+        Some(path) => {
+            let mut writer = csv::Writer::from_path(path)?;
+            for row in &rows {
+                writer.serialize(row)?;
+            }
+            writer.flush()?;
+            info!("{} daily rows written to {}", rows.len(), path);
+        }
+        None => {
+            let mut writer = csv::Writer::from_writer(std::io::stdout());
+            for row in &rows {
+                writer.serialize(row)?;
+            }
+            writer.flush()?;
+        }
+    }
+    Ok(())
+}
+
+/// Helper: the UTC days covered by the filter's date range (up to today when open-ended).
+fn window_days(filter: &ChatFilter) -> Vec<NaiveDate> {
+    let today = Utc::now().date_naive();
+    let day_of = |ts: i64| DateTime::<Utc>::from_timestamp(ts, 0).map(|dt| dt.date_naive());
+    let start = filter.since.and_then(day_of).unwrap_or(today);
+    // `until` is exclusive
+    let end = filter.until.and_then(|ts| day_of(ts - 1)).unwrap_or(today);
+    start.iter_days().take_while(|d| *d <= end).collect()
+}
+
+/// Helper: render values as a sparkline, summing neighbours when there are more than fit.
+fn sparkline(values: &[i64]) -> String {
+    let buckets: Vec<i64> = if values.len() > MAX_SPARK_WIDTH {
+        let size = values.len().div_ceil(MAX_SPARK_WIDTH);
+        values.chunks(size).map(|chunk| chunk.iter().sum()).collect()
+    } else {
+        values.to_vec()
+    };
+    let max = buckets.iter().copied().max().unwrap_or(0);
+    buckets.iter().map(|v| {
+        if max == 0 || *v == 0 {
+            ' '
+        } else {
+            SPARKS[((*v * (SPARKS.len() as i64 - 1)) / max) as usize]
+        }
+    }).collect()
+}
@@ -0,0 +1,356 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+use log::{error, info, warn};
+use grammers_client::Client;
+use grammers_client::grammers_tl_types as tl;
+use libsql::Connection;
+use crate::{database, telegram};
+use crate::database::ChatFilter;
+use crate::report::format_timestamp;
+use crate::taxonomy::{self, Taxonomy};
+use crate::telegram::ChatInfo;
+
+/// Longest folder title Telegram accepts.
+const MAX_FOLDER_TITLE: usize = 12;
+/// Most chats a folder may include (Telegram's limit without Premium).
+const MAX_FOLDER_CHATS: usize = 100;
+/// Longest chat title Telegram accepts.
+const MAX_CHAT_TITLE: usize = 128;
+/// Telegram folder ids: 0 and 1 are reserved.
+const FIRST_FOLDER_ID: i32 = 2;
+const LAST_FOLDER_ID: i32 = 255;
+
+/// What `apply` should change.
+pub struct ApplyOptions {
+    pub folders: bool,        // organize chats into one folder per top-level category
+    pub renames: bool,        // rename chats we administer to their suggested name
+    pub accepted_only: bool,  // only use suggested names accepted in review
+    pub dry_run: bool,        // print the plan without asking or changing anything
+}
+
+/// A folder to create, update or delete.
+struct FolderChange {
+    id: i32,
+    title: String,
+    before: Option<tl::enums::DialogFilter>,
+    after: Option<tl::enums::DialogFilter>,
+    added: Vec<String>,    // titles of chats moved into the folder
+    removed: Vec<String>,  // titles of chats moved out of it
+}
+
+/// A chat to rename.
+struct RenameChange<'a> {
+    chat: &'a ChatInfo,
+    to: String,
+}
+
+/// Show the folder and rename changes the analysis results call for and, once confirmed,
+/// make them in Telegram. Each change is logged so `undo` can revert the batch.
+pub async fn run_apply(client: &Client, conn: &mut Connection, taxonomy: &Taxonomy, options: &ApplyOptions)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let live = telegram::fetch_dialogs(client).await?;
+    let records = database::get_chat_records(conn, &ChatFilter::default()).await?;
+    let live_by_peer: HashMap<&str, &ChatInfo> = live.iter().map(|c| (c.peer_id.as_str(), c)).collect();
+
+    let folders = if options.folders {
+        plan_folders(&telegram::get_dialog_filters(client).await?, &records, &live_by_peer, taxonomy)
+    } else {
+        Vec::new()
+    };
+    let renames: Vec<RenameChange> = if options.renames {
+        records.iter().filter_map(|record| {
+            let chat = live_by_peer.get(record.peer_id.as_str())?;
+            let to = record.suggested_name.as_deref()?.trim();
+            let decision = record.name_decision.as_deref();
+            let wanted = if options.accepted_only { decision == Some("accepted") } else { decision != Some("rejected") };
+            (wanted && chat.can_change_info && !to.is_empty() && to != chat.title && to.chars().count() <= MAX_CHAT_TITLE)
+                .then(|| RenameChange { chat, to: to.to_string() })
+        }).collect()
+    } else {
+        Vec::new()
+    };
+
+    if folders.is_empty() && renames.is_empty() {
+        println!("Nothing to apply: Telegram already matches the analysis.");
+        return Ok(());
+    }
+    print_plan(&folders, &renames);
+    let total = folders.len() + renames.len();
+    if options.dry_run {
+        println!("\nDry run: {} change(s) not applied.", total);
+        return Ok(());
+    }
+    if !confirm(&format!("\nApply these {} change(s)? [y/N] ", total))? {
+        println!("Nothing changed.");
+        return Ok(());
+    }
+
+    // A failed change is reported and skipped; only the changes that went through are logged
+    // for undo, so the batch can still be reverted exactly
+    let batch = database::next_apply_batch(conn).await?;
+    let mut failed: Vec<String> = Vec::new();
+    for change in &folders {
+        let label = match (&change.before, &change.after) {
+            (None, _) => format!("created folder \"{}\"", change.title),
+            (Some(_), None) => format!("deleted folder \"{}\"", change.title),
+            (Some(_), Some(_)) => format!("updated folder \"{}\"", change.title),
+        };
+        if let Err(e) = telegram::update_dialog_filter(client, change.id, change.after.clone()).await {
+            error!("Failed: {}: {}", label, e);
+            failed.push(label);
+            continue;
+        }
+        database::log_apply(conn, batch, "folder", &change.id.to_string(), &label, None,
+            change.before.as_ref().map(telegram::encode_dialog_filter)).await?;
+        info!("Applied: {}", label);
+    }
+    for change in &renames {
+        let label = format!("renamed \"{}\" to \"{}\"", change.chat.title, change.to);
+        if let Err(e) = telegram::rename_chat(client, change.chat, &change.to).await {
+            error!("Failed: {}: {}", label, e);
+            failed.push(label);
+            continue;
+        }
+        database::log_apply(conn, batch, "rename", &change.chat.peer_id, &label, Some(&change.chat.title), None).await?;
+        database::set_chat_name(conn, &change.chat.peer_id, &change.to).await?;
+        info!("Applied: {}", label);
+    }
+    print_outcome(total, batch, &failed);
+    Ok(())
+}
+
+/// Report how a batch went, listing the changes that failed.
+pub fn print_outcome(total: usize, batch: i64, failed: &[String]) {
+    let applied = total - failed.len();
+    if applied > 0 {
+        println!("Applied {} change(s) as batch {}. Revert with `apply undo`.", applied, batch);
+    }
+    if !failed.is_empty() {
+        println!("{} change(s) failed and were not applied:", failed.len());
+        for label in failed {
+            println!("  {}", label);
+        }
+    }
+}
+
+/// Revert a batch of applied changes (the latest one by default), newest change first.
+/// Covers the chats `cleanup apply` archived or muted as well.
+pub async fn undo(client: &Client, conn: &mut Connection, batch: Option<i64>, dry_run: bool)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let entries = database::get_apply_batch(conn, batch).await?;
+    let Some(first) = entries.first() else {
+        println!("Nothing to undo.");
+        return Ok(());
+    };
+    println!("Batch {} (applied {}):", first.batch, format_timestamp(first.applied_at as i32));
+    for entry in entries.iter().rev() {
+        println!("  revert: {}", entry.label);
+    }
+    if dry_run || !confirm(&format!("\nRevert these {} change(s)? [y/N] ", entries.len()))? {
+        println!("Nothing changed.");
+        return Ok(());
+    }
+    let live = telegram::fetch_dialogs(client).await?;
+    for entry in entries.iter().rev() {
+        match entry.kind.as_str() {
+            "folder" => {
+                let id: i32 = entry.target.parse()?;
+                let before = entry.before_blob.as_deref().map(telegram::decode_dialog_filter).transpose()?;
+                telegram::update_dialog_filter(client, id, before).await?;
+            }
+            "rename" => {
+                let title = entry.before_text.as_deref().ok_or("Undo log entry without the old title")?;
+                let Some(chat) = live.iter().find(|c| c.peer_id == entry.target) else {
+                    warn!("Cannot revert \"{}\": the chat is no longer in the dialog list", entry.label);
+                    continue;
+                };
+                telegram::rename_chat(client, chat, title).await?;
+                database::set_chat_name(conn, &entry.target, title).await?;
+            }
+            "archive" | "mute" => {
+                let Some(chat) = live.iter().find(|c| c.peer_id == entry.target) else {
+                    warn!("Cannot revert \"{}\": the chat is no longer in the dialog list", entry.label);
+                    continue;
+                };
+                if entry.kind == "archive" {
+                    telegram::set_archived(client, chat, false).await?;
+                    database::set_chat_archived(conn, &entry.target, false).await?;
+                } else {
+                    telegram::set_muted(client, chat, false).await?;
+                    database::set_chat_muted(conn, &entry.target, false).await?;
+                }
+            }
+            other => {
+                warn!("Unknown change \"{}\" in the undo log; skipped", other);
+                continue;
+            }
+        }
+        database::mark_apply_undone(conn, entry.id).await?;
+        info!("Reverted: {}", entry.label);
+    }
+    Ok(())
+}
+
+/// Work out the folder changes: one folder per top-level category (except `Other`),
+/// holding that category's chats. Folders are matched to categories by title. Chats the
+/// analysis doesn't place in a folder, and peers such as users or bots, are left where they are.
+fn plan_folders(existing: &[tl::enums::DialogFilter], records: &[database::ChatRecord],
+    live_by_peer: &HashMap<&str, &ChatInfo>, taxonomy: &Taxonomy) -> Vec<FolderChange>
+{
+    // Desired members of each category folder
+    let mut desired: BTreeMap<String, Vec<&ChatInfo>> = taxonomy.entries().iter()
+        .filter(|e| e.depth == 0 && e.path != taxonomy::OTHER)
+        .map(|e| (folder_title(&e.path), Vec::new()))
+        .collect();
+    for record in records {
+        let (Some(category), Some(chat)) = (record.category.as_deref(), live_by_peer.get(record.peer_id.as_str())) else {
+            continue;
+        };
+        if let Some(members) = desired.get_mut(&folder_title(category)) {
+            members.push(chat);
+        }
+    }
+    let managed: HashSet<&str> = desired.values().flatten().map(|c| c.peer_id.as_str()).collect();
+    let title_of = |peer: &str| live_by_peer.get(peer).map(|c| c.title.clone()).unwrap_or_else(|| peer.to_string());
+
+    let mut used_ids: HashSet<i32> = existing.iter().filter_map(|f| match f {
+        tl::enums::DialogFilter::Filter(f) => Some(f.id),
+        tl::enums::DialogFilter::Chatlist(f) => Some(f.id),
+        _ => None,
+    }).collect();
+    let mut changes = Vec::new();
+    for (title, mut chats) in desired {
+        chats.sort_by_key(|c| c.title.to_lowercase());
+        let current = existing.iter().find_map(|f| match f {
+            tl::enums::DialogFilter::Filter(f) if f.title == title => Some(f),
+            _ => None,
+        });
+        let Some(current) = current else {
+            if chats.is_empty() {
+                continue;
+            }
+            let Some(id) = (FIRST_FOLDER_ID..=LAST_FOLDER_ID).find(|id| !used_ids.contains(id)) else {
+                warn!("No free folder id left for \"{}\"; skipped", title);
+                continue;
+            };
+            used_ids.insert(id);
+            if chats.len() > MAX_FOLDER_CHATS {
+                warn!("Folder \"{}\" would hold {} chats; only the first {} are included", title, chats.len(), MAX_FOLDER_CHATS);
+                chats.truncate(MAX_FOLDER_CHATS);
+            }
+            let filter = tl::types::DialogFilter {
+                contacts: false,
+                non_contacts: false,
+                groups: false,
+                broadcasts: false,
+                bots: false,
+                exclude_muted: false,
+                exclude_read: false,
+                exclude_archived: false,
+                id,
+                title: title.clone(),
+                emoticon: None,
+                color: None,
+                pinned_peers: Vec::new(),
+                include_peers: chats.iter().map(|c| telegram::input_peer(c)).collect(),
+                exclude_peers: Vec::new(),
+            };
+            changes.push(FolderChange {
+                id,
+                title,
+                before: None,
+                after: Some(filter.into()),
+                added: chats.iter().map(|c| c.title.clone()).collect(),
+                removed: Vec::new(),
+            });
+            continue;
+        };
+
+        let wanted: HashSet<&str> = chats.iter().map(|c| c.peer_id.as_str()).collect();
+        let is_member = |peer: &tl::enums::InputPeer| telegram::peer_id_of(peer).map_or(false, |p| wanted.contains(p.as_str()));
+        let is_foreign = |peer: &tl::enums::InputPeer| telegram::peer_id_of(peer).map_or(true, |p| !managed.contains(p.as_str()));
+        let before: HashSet<String> = current.include_peers.iter().chain(&current.pinned_peers)
+            .filter_map(telegram::peer_id_of)
+            .collect();
+
+        let mut updated = current.clone();
+        // Keep pinned members and anything we don't organize; chats of other categories move out
+        updated.pinned_peers.retain(|p| is_member(p) || is_foreign(p));
+        updated.include_peers.retain(|p| is_foreign(p));
+        updated.exclude_peers.retain(|p| !is_member(p));
+        let pinned: HashSet<String> = updated.pinned_peers.iter().filter_map(telegram::peer_id_of).collect();
+        let room = MAX_FOLDER_CHATS.saturating_sub(updated.include_peers.len() + updated.pinned_peers.len());
+        let to_include: Vec<&&ChatInfo> = chats.iter().filter(|c| !pinned.contains(&c.peer_id)).collect();
+        if to_include.len() > room {
+            warn!("Folder \"{}\" would hold more than {} chats; {} left out", title, MAX_FOLDER_CHATS, to_include.len() - room);
+        }
+        updated.include_peers.extend(to_include.into_iter().take(room).map(|c| telegram::input_peer(c)));
+
+        let after: HashSet<String> = updated.include_peers.iter().chain(&updated.pinned_peers)
+            .filter_map(telegram::peer_id_of)
+            .collect();
+        let mut added: Vec<String> = after.difference(&before).map(|p| title_of(p)).collect();
+        let mut removed: Vec<String> = before.difference(&after).map(|p| title_of(p)).collect();
+        if added.is_empty() && removed.is_empty() {
+            continue;
+        }
+        added.sort();
+        removed.sort();
+        let empty = updated.include_peers.is_empty() && updated.pinned_peers.is_empty()
+            && !(updated.contacts || updated.non_contacts || updated.groups || updated.broadcasts || updated.bots);
+        changes.push(FolderChange {
+            id: current.id,
+            title,
+            before: Some(tl::enums::DialogFilter::Filter(current.clone())),
+            // Telegram rejects folders without any chats, so an emptied folder is deleted
+            after: if empty { None } else { Some(updated.into()) },
+            added,
+            removed,
+        });
+    }
+    changes
+}
+
+/// Helper: folder title for a category — its top level, cut to Telegram's limit.
+fn folder_title(category: &str) -> String {
+    category.split('/').next().unwrap_or(category).trim().chars().take(MAX_FOLDER_TITLE).collect()
+}
+
+/// Helper: print the planned changes as a diff.
+fn print_plan(folders: &[FolderChange], renames: &[RenameChange]) {
+    if !folders.is_empty() {
+        println!("Folders:");
+        for change in folders {
+            let status = match (&change.before, &change.after) {
+                (None, _) => "+",
+                (Some(_), None) => "-",
+                (Some(_), Some(_)) => "~",
+            };
+            println!("  {} {}", status, change.title);
+            for title in &change.added {
+                println!("      + {}", title);
+            }
+            for title in &change.removed {
+                println!("      - {}", title);
+            }
+        }
+    }
+    if !renames.is_empty() {
+        println!("Renames:");
+        for change in renames {
+            println!("  ~ \"{}\" -> \"{}\"", change.chat.title, change.to);
+        }
+    }
+}
+
+/// Ask a yes/no question on the console; anything but "y"/"yes" means no.
+pub fn confirm(question: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut stdout = io::stdout();
+    write!(stdout, "{}", question)?;
+    stdout.flush()?;
+    let mut line = String::new();
+    io::stdin().lock().read_line(&mut line)?;
+    Ok(matches!(line.trim().to_lowercase().as_str(), "y" | "yes"))
+}
@@ -0,0 +1,106 @@
+use tokio::time::{sleep, Duration};
+use log::{info, error};
+use grammers_client::Client;
+use libsql::Connection;
+use crate::{telegram, database, ai, watch, mentions};
+use crate::shutdown::Shutdown;
+use crate::telegram::{ChatInfo, MessageInfo};
+use crate::watch::Watchlists;
+use crate::mentions::Owner;
+
+/// Messages requested per history page (Telegram's maximum).
+const PAGE_SIZE: i32 = 100;
+/// Pause between history pages to stay clear of flood limits.
+const PAGE_DELAY: Duration = Duration::from_secs(1);
+
+/// Import the full history of one chat (by peer id or title) or, with `None`, of every
+/// group and channel. Walks backwards from the newest message page by page, storing
+/// messages with their embeddings and a per-chat cursor so an interrupted run resumes
+/// where it stopped. With `since` (UNIX timestamp), stops at messages older than the cutoff.
+/// Watchlist matches are recorded as for live messages; old ones are not notified.
+/// Mentions are detected as for live messages, except that a reply to one of our messages on an
+/// older, not yet stored page is not recognised.
+pub async fn run_backfill(client: &Client, conn: &mut Connection, target: Option<&str>, since: Option<i64>, shutdown: &Shutdown)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let chats: Vec<ChatInfo> = telegram::fetch_dialogs(client).await?
+        .into_iter()
+        .filter(|chat| target.map_or(true, |t| chat.peer_id == t || chat.title.eq_ignore_ascii_case(t)))
+        .collect();
+    if chats.is_empty() {
+        return Err(format!("No chat matches \"{}\"", target.unwrap_or("")).into());
+    }
+    let watchlists = Watchlists::from_env()?;
+    let owner = Owner::fetch(client).await?;
+    let total = chats.len();
+    for (i, chat) in chats.iter().enumerate() {
+        if shutdown.is_requested() {
+            info!("Backfill interrupted; progress is saved and will resume on the next run.");
+            break;
+        }
+        database::save_chat(conn, chat).await?;
+        if let Err(e) = backfill_chat(client, conn, chat, since, &owner, &watchlists, shutdown).await {
+            error!("Backfill failed for chat {}: {}", chat.title, e);
+        }
+        info!("Backfill progress: {}/{} chats", i + 1, total);
+    }
+    Ok(())
+}
+
+/// Backfill a single chat from its saved cursor until the start of history or the cutoff.
+async fn backfill_chat(client: &Client, conn: &mut Connection, chat: &ChatInfo, since: Option<i64>, owner: &Owner, watchlists: &Watchlists, shutdown: &Shutdown)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let mut cursor = database::get_backfill_cursor(conn, &chat.peer_id).await?.unwrap_or_default();
+    if cursor.done {
+        // A finished backfill only continues if asked to reach further back than last time
+        let reaches_further = match (since, cursor.since) {
+            (None, Some(_)) => true,
+            (Some(new), Some(old)) => new < old,
+            _ => false,
+        };
+        if !reaches_further {
+            info!("Backfill of \"{}\" already complete ({} messages)", chat.title, cursor.messages_fetched);
+            return Ok(());
+        }
+        cursor.done = false;
+    }
+    cursor.since = since;
+
+    while !shutdown.is_requested() {
+        let page = telegram::fetch_history_page(client, chat, cursor.oldest_msg_id, PAGE_SIZE).await?;
+        let Some(oldest_id) = page.oldest_id else {
+            // Empty page: the start of the history was reached
+            cursor.done = true;
+            database::save_backfill_cursor(conn, &chat.peer_id, &cursor).await?;
+            break;
+        };
+        let reached_cutoff = since.map_or(false, |s| page.messages.iter().any(|m| (m.date as i64) < s));
+        let mut batch: Vec<MessageInfo> = page.messages
+            .into_iter()
+            .filter(|m| since.map_or(true, |s| m.date as i64 >= s))
+            .collect();
+        let count = batch.len();
+        mentions::detect(conn, owner, &chat.peer_id, &mut batch).await?;
+        // Stored even when the embedding model is unavailable; the monitor embeds them later
+        let embedded = ai::embed_messages(batch).await;
+        database::save_messages(conn, &chat.peer_id, &embedded).await?;
+        let stored: Vec<MessageInfo> = embedded.into_iter().map(|(msg, _)| msg).collect();
+        watch::scan_messages(conn, watchlists, &chat.peer_id, &stored).await?;
+
+        // Only move the cursor once the page is stored
+        cursor.oldest_msg_id = oldest_id;
+        cursor.messages_fetched += count as i64;
+        cursor.done = reached_cutoff || oldest_id <= 1;
+        database::save_backfill_cursor(conn, &chat.peer_id, &cursor).await?;
+        info!("Backfill \"{}\": {} messages stored, reached message #{}", chat.title, cursor.messages_fetched, oldest_id);
+        if cursor.done {
+            break;
+        }
+        sleep(PAGE_DELAY).await;
+    }
+    if cursor.done {
+        info!("Backfill of \"{}\" complete ({} messages)", chat.title, cursor.messages_fetched);
+    }
+    Ok(())
+}
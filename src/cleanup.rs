@@ -0,0 +1,234 @@
+use std::collections::HashMap;
+use log::{error, info};
+use grammers_client::Client;
+use libsql::Connection;
+use chrono::Utc;
+use crate::{apply, database, telegram};
+use crate::database::{ChatFilter, ChatMetrics};
+use crate::report::format_timestamp;
+use crate::telegram::ChatInfo;
+
+/// Default number of days the activity rates are computed over (CLEANUP_WINDOW_DAYS).
+const DEFAULT_WINDOW_DAYS: i64 = 90;
+/// Default number of quiet days after which a chat should be archived (CLEANUP_IDLE_DAYS).
+const DEFAULT_IDLE_DAYS: i64 = 30;
+/// Default number of quiet days after which a chat we never wrote in should be left (CLEANUP_DEAD_DAYS).
+const DEFAULT_DEAD_DAYS: i64 = 180;
+/// Default messages per week above which a chat we don't take part in should be muted (CLEANUP_NOISY_PER_WEEK).
+const DEFAULT_NOISY_PER_WEEK: f64 = 50.0;
+/// Longest chat name shown in the report table.
+const NAME_CHARS: usize = 32;
+
+/// What to do with a chat.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Leave,
+    Archive,
+    Mute,
+}
+
+impl Action {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Action::Leave => "leave",
+            Action::Archive => "archive",
+            Action::Mute => "mute",
+        }
+    }
+}
+
+/// A cleanup suggestion with the reason behind it.
+pub struct Suggestion {
+    pub action: Action,
+    pub reason: String,
+    pub fallback: Option<Action>,  // what to do instead while the chat isn't left
+}
+
+/// Thresholds that decide the suggestions. Each can be overridden via the matching environment variable.
+#[derive(Debug, Clone)]
+pub struct CleanupConfig {
+    pub window_days: i64,      // CLEANUP_WINDOW_DAYS
+    pub idle_days: i64,        // CLEANUP_IDLE_DAYS
+    pub dead_days: i64,        // CLEANUP_DEAD_DAYS
+    pub noisy_per_week: f64,   // CLEANUP_NOISY_PER_WEEK
+}
+
+impl CleanupConfig {
+    pub fn from_env() -> Self {
+        CleanupConfig {
+            window_days: env_value("CLEANUP_WINDOW_DAYS", DEFAULT_WINDOW_DAYS),
+            idle_days: env_value("CLEANUP_IDLE_DAYS", DEFAULT_IDLE_DAYS),
+            dead_days: env_value("CLEANUP_DEAD_DAYS", DEFAULT_DEAD_DAYS),
+            noisy_per_week: env_value("CLEANUP_NOISY_PER_WEEK", DEFAULT_NOISY_PER_WEEK),
+        }
+    }
+
+    /// Start of the activity window.
+    fn window_start(&self, now: i64) -> i64 {
+        now - self.window_days * 86400
+    }
+
+    /// Messages per week over the activity window.
+    fn per_week(&self, metrics: &ChatMetrics) -> f64 {
+        metrics.messages as f64 * 7.0 / self.window_days as f64
+    }
+
+    /// Decide what to do with a chat, if anything:
+    /// - leave: quiet for `dead_days` and we haven't written in it for as long; until it is
+    ///   left it should be archived like an idle chat, if it isn't yet
+    /// - archive: quiet for `idle_days` and not archived yet
+    /// - mute: busier than `noisy_per_week`, while we neither wrote in it nor were addressed
+    ///   during the window, and not muted yet
+    ///
+    /// Chats without any known activity get no suggestion.
+    pub fn suggest(&self, metrics: &ChatMetrics, now: i64) -> Option<Suggestion> {
+        // Without any known activity (just joined, history not fetched yet) there is nothing to go on
+        let last_activity = metrics.last_activity?;
+        let idle_days = (now - last_activity as i64) / 86400;
+        let own_idle_days = metrics.last_own_at.map(|ts| (now - ts as i64) / 86400);
+        if idle_days >= self.dead_days && own_idle_days.map_or(true, |d| d >= self.dead_days) {
+            let reason = match own_idle_days {
+                Some(d) => format!("quiet for {} days, you last wrote {} days ago", idle_days, d),
+                None => format!("quiet for {} days, you never wrote", idle_days),
+            };
+            let fallback = (!metrics.archived).then_some(Action::Archive);
+            return Some(Suggestion { action: Action::Leave, reason, fallback });
+        }
+        if idle_days >= self.idle_days && !metrics.archived {
+            return Some(Suggestion { action: Action::Archive, reason: format!("quiet for {} days", idle_days), fallback: None });
+        }
+        let per_week = self.per_week(metrics);
+        if per_week >= self.noisy_per_week && metrics.own_messages == 0 && metrics.mentions == 0 && !metrics.muted {
+            return Some(Suggestion {
+                action: Action::Mute,
+                reason: format!("{:.0} messages a week, none from or to you", per_week),
+                fallback: None,
+            });
+        }
+        None
+    }
+}
+
+/// Helper: read a number from the environment, or use the default.
+fn env_value<T: std::str::FromStr>(key: &str, default: T) -> T {
+    std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Print the cleanup report: activity per chat (messages per week, last activity, our own
+/// messages in the window) with a suggestion to leave, archive or mute it. Only chats with a
+/// suggestion are listed unless `all` is set. Works on stored data; archive and mute states
+/// are as of the last monitor cycle.
+pub async fn print_report(conn: &Connection, filter: &ChatFilter, config: &CleanupConfig, all: bool)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let now = Utc::now().timestamp();
+    let metrics = database::get_chat_metrics(conn, filter, config.window_start(now)).await?;
+    let rows: Vec<(&ChatMetrics, Option<Suggestion>)> = metrics.iter()
+        .map(|m| (m, config.suggest(m, now)))
+        .filter(|(_, s)| all || s.is_some())
+        .collect();
+    if rows.is_empty() {
+        println!("Nothing to clean up.");
+        return Ok(());
+    }
+    println!("{:<w$}  {:>7}  {:>5}  {:<19}  {}", "Chat", "Msgs/wk", "Yours", "Last activity", "Suggestion", w = NAME_CHARS);
+    for (m, suggestion) in &rows {
+        let mut state = Vec::new();
+        if m.archived {
+            state.push("archived");
+        }
+        if m.muted {
+            state.push("muted");
+        }
+        let name: String = m.name.chars().take(NAME_CHARS).collect();
+        let suggestion = suggestion.as_ref()
+            .map(|s| match s.fallback {
+                Some(fallback) => format!("{} ({}), else {}", s.action.as_str(), s.reason, fallback.as_str()),
+                None => format!("{} ({})", s.action.as_str(), s.reason),
+            })
+            .unwrap_or_else(|| "-".into());
+        println!("{:<w$}  {:>7.1}  {:>5}  {:<19}  {}{}", name, config.per_week(m), m.own_messages,
+            m.last_activity.map(format_timestamp).unwrap_or_else(|| "-".into()), suggestion,
+            if state.is_empty() { String::new() } else { format!(" [{}]", state.join(", ")) },
+            w = NAME_CHARS);
+    }
+    let count = |action: Action| rows.iter().filter(|(_, s)| s.as_ref().map_or(false, |s| s.action == action)).count();
+    println!("\nWindow: last {} days. Suggested: {} to leave, {} to archive, {} to mute.",
+        config.window_days, count(Action::Leave), count(Action::Archive), count(Action::Mute));
+    Ok(())
+}
+
+/// Archive and/or mute the chats the report suggests it for, after confirmation. Chats are
+/// refreshed from Telegram first. Leaving is never done automatically; chats suggested for
+/// leaving get their fallback (archiving) instead. Each change is logged so `apply undo` can
+/// revert the batch.
+pub async fn run_cleanup(client: &Client, conn: &mut Connection, filter: &ChatFilter, config: &CleanupConfig,
+    actions: &[Action], dry_run: bool) -> Result<(), Box<dyn std::error::Error>>
+{
+    let live = telegram::fetch_dialogs(client).await?;
+    for chat in &live {
+        database::save_chat(conn, chat).await?;
+    }
+    let live_by_peer: HashMap<&str, &ChatInfo> = live.iter().map(|c| (c.peer_id.as_str(), c)).collect();
+    let now = Utc::now().timestamp();
+    let metrics = database::get_chat_metrics(conn, filter, config.window_start(now)).await?;
+    let mut leave = 0;
+    let mut changes: Vec<(&ChatInfo, Suggestion)> = Vec::new();
+    for m in &metrics {
+        let (Some(suggestion), Some(chat)) = (config.suggest(m, now), live_by_peer.get(m.peer_id.as_str())) else {
+            continue;
+        };
+        if suggestion.action == Action::Leave {
+            leave += 1;
+            if let Some(fallback) = suggestion.fallback.filter(|a| actions.contains(a)) {
+                let reason = format!("{}; leave it yourself", suggestion.reason);
+                changes.push((chat, Suggestion { action: fallback, reason, fallback: None }));
+            }
+        } else if actions.contains(&suggestion.action) {
+            changes.push((chat, suggestion));
+        }
+    }
+    if leave > 0 {
+        println!("{} chat(s) could be left; see `cleanup` and leave them in Telegram yourself.", leave);
+    }
+    if changes.is_empty() {
+        println!("Nothing to archive or mute.");
+        return Ok(());
+    }
+    for (chat, suggestion) in &changes {
+        println!("  {} {} ({})", suggestion.action.as_str(), chat.title, suggestion.reason);
+    }
+    if dry_run {
+        println!("\nDry run: {} change(s) not applied.", changes.len());
+        return Ok(());
+    }
+    if !apply::confirm(&format!("\nApply these {} change(s)? [y/N] ", changes.len()))? {
+        println!("Nothing changed.");
+        return Ok(());
+    }
+
+    // A chat that fails is reported and skipped; each change is logged only once it went through
+    let batch = database::next_apply_batch(conn).await?;
+    let mut failed: Vec<String> = Vec::new();
+    for (chat, suggestion) in &changes {
+        let (result, label) = match suggestion.action {
+            Action::Archive => (telegram::set_archived(client, chat, true).await, format!("archived \"{}\"", chat.title)),
+            Action::Mute => (telegram::set_muted(client, chat, true).await, format!("muted \"{}\"", chat.title)),
+            Action::Leave => continue,
+        };
+        if let Err(e) = result {
+            error!("Failed: {}: {}", label, e);
+            failed.push(label);
+            continue;
+        }
+        match suggestion.action {
+            Action::Archive => database::set_chat_archived(conn, &chat.peer_id, true).await?,
+            Action::Mute => database::set_chat_muted(conn, &chat.peer_id, true).await?,
+            Action::Leave => {}
+        }
+        database::log_apply(conn, batch, suggestion.action.as_str(), &chat.peer_id, &label, None, None).await?;
+        info!("Applied: {}", label);
+    }
+    apply::print_outcome(changes.len(), batch, &failed);
+    Ok(())
+}
@@ -0,0 +1,400 @@
+use std::collections::BTreeMap;
+use axum::Router;
+use axum::extract::{Form, Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{Html, IntoResponse, Redirect, Response};
+use axum::routing::{get, post};
+use serde::Deserialize;
+use crate::{database, ai};
+use crate::database::{ChatFilter, MessageHit, ReportQuery, ReportSort, UrgentState};
+use crate::report::{format_timestamp, html_escape};
+use crate::server::{token_matches, AppState};
+
+/// Cookie holding the API token once the browser has logged in.
+const SESSION_COOKIE: &str = "organizer_token";
+/// Messages shown per page of a chat timeline.
+const TIMELINE_PAGE: i64 = 100;
+/// Urgent messages shown in the inbox.
+const INBOX_LIMIT: i64 = 200;
+/// Results shown per search.
+const SEARCH_LIMIT: i64 = 50;
+
+/// Routes of the server-rendered dashboard. Everything except the login page
+/// requires the session cookie (or the API bearer token).
+pub fn routes(state: AppState) -> Router<AppState> {
+    let pages = Router::new()
+        .route("/", get(chat_list))
+        .route("/chats/:peer", get(chat_detail))
+        .route("/urgent", get(urgent_inbox))
+        .route("/urgent/state", post(change_state))
+        .route("/search", get(search_page))
+        .route("/logout", post(logout))
+        .route_layer(middleware::from_fn_with_state(state, require_session));
+    Router::new()
+        .route("/login", get(login_form).post(login))
+        .merge(pages)
+}
+
+/// Middleware: send browsers without a valid session to the login page.
+async fn require_session(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let headers = request.headers();
+    let from_cookie = headers.get(header::COOKIE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|cookies| cookie_value(cookies, SESSION_COOKIE));
+    let from_header = headers.get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match from_cookie.or(from_header) {
+        Some(token) if token_matches(token, &state.token) => next.run(request).await,
+        _ => Redirect::to("/login").into_response(),
+    }
+}
+
+/// Helper: find a cookie's value in a `Cookie` header.
+fn cookie_value<'a>(cookies: &'a str, name: &str) -> Option<&'a str> {
+    cookies.split(';')
+        .filter_map(|pair| pair.trim().split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value)
+}
+
+/// A failed page render, shown as a plain error page.
+struct PageError(StatusCode, String);
+
+impl IntoResponse for PageError {
+    fn into_response(self) -> Response {
+        (self.0, Html(layout("Error", &format!("<p class=\"error\">{}</p>\n", html_escape(&self.1))))).into_response()
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for PageError {
+    fn from(e: Box<dyn std::error::Error>) -> Self {
+        PageError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    }
+}
+
+type PageResult = Result<Html<String>, PageError>;
+
+/// GET /login
+async fn login_form() -> Html<String> {
+    Html(login_page(None))
+}
+
+#[derive(Deserialize)]
+struct LoginForm {
+    token: String,
+}
+
+/// POST /login — check the token and store it in an HTTP-only, same-site cookie.
+async fn login(State(state): State<AppState>, Form(form): Form<LoginForm>) -> Response {
+    if !token_matches(form.token.trim(), &state.token) {
+        return (StatusCode::UNAUTHORIZED, Html(login_page(Some("Invalid token.")))).into_response();
+    }
+    let cookie = format!("{}={}; Path=/; HttpOnly; SameSite=Strict", SESSION_COOKIE, form.token.trim());
+    ([(header::SET_COOKIE, cookie)], Redirect::to("/")).into_response()
+}
+
+/// POST /logout — drop the session cookie.
+async fn logout() -> Response {
+    let cookie = format!("{}=; Path=/; HttpOnly; SameSite=Strict; Max-Age=0", SESSION_COOKIE);
+    ([(header::SET_COOKIE, cookie)], Redirect::to("/login")).into_response()
+}
+
+/// Helper: the login page, with an optional error message.
+fn login_page(error: Option<&str>) -> String {
+    let mut body = String::from("<h1>Sign in</h1>\n");
+    if let Some(error) = error {
+        body += &format!("<p class=\"error\">{}</p>\n", html_escape(error));
+    }
+    body += concat!(
+        "<form method=\"post\" action=\"/login\">\n",
+        "<label>API token <input type=\"password\" name=\"token\" autofocus></label>\n",
+        "<button type=\"submit\">Sign in</button>\n",
+        "</form>\n",
+    );
+    page_shell("Sign in", &body)
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ChatListParams {
+    category: Option<String>,
+    sort: Option<String>,
+}
+
+/// GET / — all chats with category, suggested name and duplicate badge.
+async fn chat_list(State(state): State<AppState>, Query(params): Query<ChatListParams>) -> PageResult {
+    let all = database::get_report_chats(&state.conn, &ReportQuery::default()).await?;
+    let mut categories: BTreeMap<String, usize> = BTreeMap::new();
+    for c in &all {
+        *categories.entry(c.chat.category.clone().unwrap_or_else(|| "Uncategorized".into())).or_default() += 1;
+    }
+    let sort = match params.sort.as_deref() {
+        Some("activity") => ReportSort::Activity,
+        Some("urgency") => ReportSort::Urgency,
+        _ => ReportSort::Name,
+    };
+    let query = ReportQuery {
+        filter: ChatFilter { category: params.category.clone(), ..Default::default() },
+        sort,
+        ..Default::default()
+    };
+    let chats = database::get_report_chats(&state.conn, &query).await?;
+
+    let mut body = String::from("<h1>Chats</h1>\n<p class=\"filters\">");
+    body += &filter_link("All", &list_url(None, params.sort.as_deref()), params.category.is_none());
+    for (category, count) in &categories {
+        let label = format!("{} ({})", category, count);
+        let selected = params.category.as_deref() == Some(category.as_str());
+        body += &filter_link(&label, &list_url(Some(category), params.sort.as_deref()), selected);
+    }
+    body += "</p>\n<p class=\"filters\">Sort: ";
+    for (key, label) in [("name", "name"), ("activity", "activity"), ("urgency", "urgency")] {
+        let selected = params.sort.as_deref().unwrap_or("name") == key;
+        body += &filter_link(label, &list_url(params.category.as_deref(), Some(key)), selected);
+    }
+    body += "</p>\n<table>\n<tr><th>Chat</th><th>Category</th><th>Suggested name</th><th>Messages</th><th>Urgent</th><th>Last activity</th></tr>\n";
+    for c in &chats {
+        let chat = &c.chat;
+        body += &format!("<tr><td><a href=\"/chats/{}\">{}</a>", url_encode(&chat.peer_id), html_escape(&chat.name));
+        if chat.duplicate {
+            body += "<span class=\"badge dup\">duplicate</span>";
+        }
+        body += &format!("</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+            html_escape(chat.category.as_deref().unwrap_or("Uncategorized")),
+            html_escape(chat.suggested_name.as_deref().unwrap_or("-")),
+            c.message_count,
+            if c.urgent_count > 0 { format!("<span class=\"badge urgent\">{}</span>", c.urgent_count) } else { "0".into() },
+            c.last_activity.map(format_timestamp).unwrap_or_else(|| "-".into()),
+        );
+    }
+    body += "</table>\n";
+    Ok(Html(layout("Chats", &body)))
+}
+
+#[derive(Debug, Deserialize)]
+struct TimelineParams {
+    before: Option<i32>,
+}
+
+/// GET /chats/{peer} — analysis summary, members and message timeline of one chat.
+async fn chat_detail(State(state): State<AppState>, Path(peer): Path<String>, Query(params): Query<TimelineParams>) -> PageResult {
+    let query = ReportQuery {
+        filter: ChatFilter { chat_exact: Some(peer.clone()), ..Default::default() },
+        ..Default::default()
+    };
+    let chat = database::get_report_chats(&state.conn, &query).await?
+        .into_iter()
+        .find(|c| c.chat.peer_id == peer)
+        .ok_or_else(|| PageError(StatusCode::NOT_FOUND, format!("No chat with peer id \"{}\"", peer)))?;
+    let members = database::get_chat_members(&state.conn, &peer).await?;
+    let messages = database::get_message_page(&state.conn, &peer, params.before, TIMELINE_PAGE).await?;
+
+    let mut body = format!("<h1>{}", html_escape(&chat.chat.name));
+    if chat.chat.duplicate {
+        body += "<span class=\"badge dup\">duplicate</span>";
+    }
+    body += "</h1>\n<h2>Summary</h2>\n<table>\n";
+    body += &format!("<tr><th>Category</th><td>{}</td></tr>\n", html_escape(chat.chat.category.as_deref().unwrap_or("Uncategorized")));
+    body += &format!("<tr><th>Suggested name</th><td>{}</td></tr>\n", html_escape(chat.chat.suggested_name.as_deref().unwrap_or("-")));
+    if !chat.chat.tags.is_empty() {
+        let tags: Vec<String> = chat.chat.tags.iter().map(|t| format!("<span class=\"badge\">{}</span>", html_escape(t))).collect();
+        body += &format!("<tr><th>Tags</th><td>{}</td></tr>\n", tags.join(" "));
+    }
+    body += &format!("<tr><th>Type</th><td>{}</td></tr>\n", html_escape(&chat.chat.kind));
+    body += &format!("<tr><th>Messages</th><td>{}</td></tr>\n", chat.message_count);
+    body += &format!("<tr><th>Urgent</th><td>{}</td></tr>\n", chat.urgent_count);
+    body += &format!("<tr><th>Last activity</th><td>{}</td></tr>\n",
+        chat.last_activity.map(format_timestamp).unwrap_or_else(|| "-".into()));
+    body += &format!("<tr><th>Peer id</th><td><code>{}</code></td></tr>\n</table>\n", html_escape(&peer));
+
+    body += &format!("<details>\n<summary>Members ({})</summary>\n<ul>\n", members.len());
+    for (_user_id, name, username, bio) in &members {
+        body += &format!("<li>{}", html_escape(name));
+        if let Some(username) = username {
+            body += &format!(" <span class=\"muted\">@{}</span>", html_escape(username));
+        }
+        if let Some(bio) = bio {
+            body += &format!("<br><small>{}</small>", html_escape(bio));
+        }
+        body += "</li>\n";
+    }
+    body += "</ul>\n</details>\n<h2>Timeline</h2>\n<ul class=\"timeline\">\n";
+    for msg in &messages {
+        let class = if msg.urgent { " class=\"urgent\"" } else { "" };
+        body += &format!("<li{}><code>{}</code> {}</li>\n", class, format_timestamp(msg.date), html_escape(&msg.text));
+    }
+    body += "</ul>\n";
+    if messages.len() as i64 == TIMELINE_PAGE {
+        if let Some(oldest) = messages.last() {
+            body += &format!("<p><a href=\"/chats/{}?before={}\">Older messages</a></p>\n", url_encode(&peer), oldest.msg_id);
+        }
+    }
+    Ok(Html(layout(&chat.chat.name, &body)))
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct InboxParams {
+    #[serde(default)]
+    all: bool,
+}
+
+/// GET /urgent — open urgent messages (new or acknowledged); all states with `?all=true`.
+async fn urgent_inbox(State(state): State<AppState>, Query(params): Query<InboxParams>) -> PageResult {
+    let states: &[UrgentState] = if params.all { &UrgentState::ALL } else { &UrgentState::OPEN };
+    let items = database::get_urgent_messages(&state.conn, &ChatFilter::default(), states, INBOX_LIMIT).await?;
+    let mut body = String::from("<h1>Urgent inbox</h1>\n<p class=\"filters\">");
+    body += &filter_link("Open", "/urgent", !params.all);
+    body += &filter_link("All", "/urgent?all=true", params.all);
+    body += "</p>\n";
+    if items.is_empty() {
+        body += "<p class=\"muted\">Nothing urgent.</p>\n";
+    }
+    body += "<ul class=\"inbox\">\n";
+    for item in &items {
+        let current = item.urgent_state.as_deref().map(UrgentState::parse).transpose()?.unwrap_or(UrgentState::New);
+        body += &format!("<li>{} <span class=\"badge\">{}</span>", hit_html(item), current.as_str().replace('_', " "));
+        if let (Some(by), Some(at)) = (&item.urgent_state_by, item.urgent_state_at) {
+            body += &format!(" <span class=\"muted\">by {} at {}</span>", html_escape(by), format_timestamp(at as i32));
+        }
+        let actions: &[(UrgentState, &str)] = match current {
+            UrgentState::New => &[(UrgentState::Acknowledged, "Acknowledge"), (UrgentState::Resolved, "Resolve"), (UrgentState::FalsePositive, "False positive")],
+            UrgentState::Acknowledged => &[(UrgentState::Resolved, "Resolve"), (UrgentState::FalsePositive, "False positive")],
+            UrgentState::Resolved | UrgentState::FalsePositive => &[(UrgentState::New, "Reopen")],
+        };
+        for (target, label) in actions {
+            body += &format!(concat!(
+                "<form method=\"post\" action=\"/urgent/state\">",
+                "<input type=\"hidden\" name=\"chat_peer\" value=\"{}\">",
+                "<input type=\"hidden\" name=\"msg_id\" value=\"{}\">",
+                "<input type=\"hidden\" name=\"state\" value=\"{}\">",
+                "<button type=\"submit\">{}</button></form>",
+            ), html_escape(&item.message.chat_peer), item.message.msg_id, target.as_str(), label);
+        }
+        body += "</li>\n";
+    }
+    body += "</ul>\n";
+    Ok(Html(layout("Urgent inbox", &body)))
+}
+
+#[derive(Deserialize)]
+struct StateForm {
+    chat_peer: String,
+    msg_id: i32,
+    state: String,
+}
+
+/// POST /urgent/state — change the state of one urgent message and return to the inbox.
+/// Changes are recorded as made by DASHBOARD_USER (default "dashboard").
+async fn change_state(State(state): State<AppState>, Form(form): Form<StateForm>) -> Result<Redirect, PageError> {
+    let target = UrgentState::parse(&form.state).map_err(|e| PageError(StatusCode::BAD_REQUEST, e.to_string()))?;
+    let by = std::env::var("DASHBOARD_USER").unwrap_or_else(|_| "dashboard".to_string());
+    let mut conn = state.conn.clone();
+    database::set_urgent_state(&mut conn, &form.chat_peer, form.msg_id, target, &by).await?;
+    Ok(Redirect::to("/urgent"))
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct SearchParams {
+    q: Option<String>,
+    mode: Option<String>,
+}
+
+/// GET /search — keyword or semantic search over all messages.
+async fn search_page(State(state): State<AppState>, Query(params): Query<SearchParams>) -> PageResult {
+    let q = params.q.unwrap_or_default();
+    let semantic = params.mode.as_deref() == Some("semantic");
+    let mut body = String::from("<h1>Search</h1>\n");
+    body += &format!(concat!(
+        "<form method=\"get\" action=\"/search\">",
+        "<input type=\"search\" name=\"q\" value=\"{}\" autofocus> ",
+        "<select name=\"mode\"><option value=\"keyword\">keyword</option><option value=\"semantic\"{}>semantic</option></select> ",
+        "<button type=\"submit\">Search</button></form>\n",
+    ), html_escape(&q), if semantic { " selected" } else { "" });
+    if q.trim().is_empty() {
+        return Ok(Html(layout("Search", &body)));
+    }
+
+    let hits = if semantic {
+        let embedding = ai::generate_embedding(&q).await
+            .map_err(|e| PageError(StatusCode::BAD_GATEWAY, format!("Embedding the query failed: {}", e)))?;
+        database::semantic_search(&state.conn, &embedding, &ChatFilter::default(), SEARCH_LIMIT).await?
+    } else {
+        database::search_messages(&state.conn, &q, &ChatFilter::default(), SEARCH_LIMIT).await?
+    };
+    body += &format!("<p class=\"muted\">{} results</p>\n<ul>\n", hits.len());
+    for hit in &hits {
+        body += &format!("<li>{}</li>\n", hit_html(hit));
+    }
+    body += "</ul>\n";
+    Ok(Html(layout("Search", &body)))
+}
+
+/// Helper: one message with a link to its chat.
+fn hit_html(hit: &MessageHit) -> String {
+    format!("<a href=\"/chats/{}\">{}</a> <code>{}</code> {}",
+        url_encode(&hit.message.chat_peer), html_escape(&hit.chat_name),
+        format_timestamp(hit.message.date), html_escape(&hit.message.text))
+}
+
+/// Helper: a filter link, highlighted when selected.
+fn filter_link(label: &str, href: &str, selected: bool) -> String {
+    let class = if selected { " class=\"selected\"" } else { "" };
+    format!("<a{} href=\"{}\">{}</a> ", class, html_escape(href), html_escape(label))
+}
+
+/// Helper: URL of the chat list with the given category and sort.
+fn list_url(category: Option<&str>, sort: Option<&str>) -> String {
+    let mut params = Vec::new();
+    if let Some(category) = category {
+        params.push(format!("category={}", url_encode(category)));
+    }
+    if let Some(sort) = sort {
+        params.push(format!("sort={}", url_encode(sort)));
+    }
+    if params.is_empty() { "/".to_string() } else { format!("/?{}", params.join("&")) }
+}
+
+/// Helper: percent-encode a value for use in a URL path segment or query.
+fn url_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b':' => encoded.push(byte as char),
+            _ => encoded += &format!("%{:02X}", byte),
+        }
+    }
+    encoded
+}
+
+/// Helper: a dashboard page with navigation.
+fn layout(title: &str, body: &str) -> String {
+    let nav = concat!(
+        "<nav><a href=\"/\">Chats</a> <a href=\"/urgent\">Urgent</a> ",
+        "<form class=\"inline\" method=\"get\" action=\"/search\"><input type=\"search\" name=\"q\" placeholder=\"Search messages\"></form> ",
+        "<form class=\"inline\" method=\"post\" action=\"/logout\"><button type=\"submit\">Sign out</button></form></nav>\n",
+    );
+    page_shell(title, &format!("{}{}", nav, body))
+}
+
+/// Helper: the HTML document around a page body.
+fn page_shell(title: &str, body: &str) -> String {
+    let mut out = String::from("<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n");
+    out += &format!("<title>{} — Telegram Chat Organizer</title>\n", html_escape(title));
+    out += concat!(
+        "<style>\n",
+        "body { font-family: system-ui, sans-serif; max-width: 70rem; margin: 1rem auto; padding: 0 1rem; color: #222; }\n",
+        "nav { display: flex; gap: 1rem; align-items: center; border-bottom: 1px solid #ddd; padding-bottom: .6rem; }\n",
+        "form.inline { display: inline; } .inbox form { display: inline; margin-left: .5rem; }\n",
+        "table { border-collapse: collapse; } td, th { border: 1px solid #ddd; padding: .2rem .6rem; text-align: left; }\n",
+        ".badge { font-size: .75rem; padding: .1rem .4rem; border-radius: 4px; background: #eee; margin-left: .4rem; }\n",
+        ".dup { background: #ffe7b3; } .urgent { background: #ffd0d0; }\n",
+        ".filters a { margin-right: .4rem; } .filters a.selected { font-weight: 700; }\n",
+        ".timeline li, .inbox li { margin: .3rem 0; }\n",
+        ".muted { color: #777; } .error { color: #b00; }\n",
+        "</style>\n</head>\n<body>\n",
+    );
+    out += body;
+    out += "</body>\n</html>\n";
+    out
+}
@@ -1,7 +1,9 @@
-use libsql::{Builder, Connection};
-use chrono::{Utc, DateTime};
+use libsql::{Builder, Connection, Value};
+use serde::Serialize;
+use chrono::Utc;
 use log::error;
-use crate::telegram::{ChatInfo, MessageInfo};
+use std::collections::HashSet;
+use crate::telegram::{ChatInfo, MemberInfo, MessageInfo, UserProfile};
 
 // Helper: Convert a Vec<f32> to a blob (Vec<u8>) in little-endian format.
 fn embedding_to_blob(embedding: &Vec<f32>) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
@@ -69,15 +71,270 @@ pub async fn init_db(path: &str) -> Result<Connection, Box<dyn std::error::Error
 
         CREATE INDEX IF NOT EXISTS idx_chat_members_user ON chat_members(user_id);
         CREATE INDEX IF NOT EXISTS idx_users_username ON users(username);
+
+        CREATE TABLE IF NOT EXISTS person_topics (
+            user_id    INTEGER PRIMARY KEY,
+            topics     TEXT NOT NULL,
+            topics_at  INTEGER NOT NULL
+        );
+
+        CREATE TABLE IF NOT EXISTS chat_checkpoints (
+            chat_peer             TEXT PRIMARY KEY,
+            last_ingested_msg_id  INTEGER NOT NULL DEFAULT 0,
+            last_analyzed_msg_id  INTEGER NOT NULL DEFAULT 0,
+            updated_at            INTEGER,
+            FOREIGN KEY(chat_peer) REFERENCES chats(peer_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS analysis_retries (
+            chat_peer        TEXT PRIMARY KEY,
+            attempts         INTEGER NOT NULL DEFAULT 0,
+            next_attempt_at  INTEGER NOT NULL,
+            last_error       TEXT,
+            FOREIGN KEY(chat_peer) REFERENCES chats(peer_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS backfill_cursors (
+            chat_peer         TEXT PRIMARY KEY,
+            oldest_msg_id     INTEGER NOT NULL DEFAULT 0,
+            messages_fetched  INTEGER NOT NULL DEFAULT 0,
+            since             INTEGER,
+            done              BOOLEAN DEFAULT 0,
+            updated_at        INTEGER,
+            FOREIGN KEY(chat_peer) REFERENCES chats(peer_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS digests (
+            period_key    TEXT PRIMARY KEY,
+            period_start  INTEGER NOT NULL,
+            period_end    INTEGER NOT NULL,
+            content       TEXT NOT NULL,
+            duplicates    TEXT,
+            sent_at       INTEGER NOT NULL
+        );
+
+        -- Databases created before checkpoints existed: treat stored messages as ingested and analyzed.
+        INSERT OR IGNORE INTO chat_checkpoints (chat_peer, last_ingested_msg_id, last_analyzed_msg_id, updated_at)
+            SELECT chat_peer, MAX(msg_id), MAX(msg_id), strftime('%s', 'now')
+            FROM chat_messages GROUP BY chat_peer;
+
+        CREATE TABLE IF NOT EXISTS notification_log (
+            sink       TEXT NOT NULL,
+            chat_peer  TEXT NOT NULL,
+            msg_id     INTEGER NOT NULL,
+            sent_at    INTEGER NOT NULL,
+            PRIMARY KEY (sink, chat_peer, msg_id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_notification_log_sent ON notification_log(sink, sent_at);
+
+        CREATE TABLE IF NOT EXISTS feedback (
+            id          INTEGER PRIMARY KEY AUTOINCREMENT,
+            chat_peer   TEXT NOT NULL,
+            msg_id      INTEGER,
+            kind        TEXT NOT NULL,
+            value       TEXT,
+            previous    TEXT,
+            created_by  TEXT,
+            created_at  INTEGER NOT NULL,
+            FOREIGN KEY(chat_peer) REFERENCES chats(peer_id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_feedback_created ON feedback(created_at);
+
+        CREATE TABLE IF NOT EXISTS tags (
+            id    INTEGER PRIMARY KEY AUTOINCREMENT,
+            name  TEXT NOT NULL UNIQUE COLLATE NOCASE
+        );
+        CREATE TABLE IF NOT EXISTS chat_tags (
+            chat_peer   TEXT NOT NULL,
+            tag_id      INTEGER NOT NULL,
+            source      TEXT NOT NULL,
+            confidence  REAL,
+            created_at  INTEGER NOT NULL,
+            PRIMARY KEY(chat_peer, tag_id),
+            FOREIGN KEY(chat_peer) REFERENCES chats(peer_id),
+            FOREIGN KEY(tag_id) REFERENCES tags(id)
+        );
+        CREATE TABLE IF NOT EXISTS message_tags (
+            chat_peer   TEXT NOT NULL,
+            msg_id      INTEGER NOT NULL,
+            tag_id      INTEGER NOT NULL,
+            source      TEXT NOT NULL,
+            confidence  REAL,
+            created_at  INTEGER NOT NULL,
+            PRIMARY KEY(chat_peer, msg_id, tag_id),
+            FOREIGN KEY(tag_id) REFERENCES tags(id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_message_tags_tag ON message_tags(tag_id);
+
+        CREATE TABLE IF NOT EXISTS apply_log (
+            id           INTEGER PRIMARY KEY AUTOINCREMENT,
+            batch        INTEGER NOT NULL,
+            applied_at   INTEGER NOT NULL,
+            kind         TEXT NOT NULL,
+            target       TEXT NOT NULL,
+            label        TEXT NOT NULL,
+            before_text  TEXT,
+            before_blob  BLOB,
+            undone_at    INTEGER
+        );
+
+        CREATE TABLE IF NOT EXISTS watch_hits (
+            chat_peer   TEXT NOT NULL,
+            msg_id      INTEGER NOT NULL,
+            watchlist   TEXT NOT NULL,
+            term        TEXT NOT NULL,
+            matched     TEXT NOT NULL,
+            notify      BOOLEAN NOT NULL DEFAULT 1,
+            created_at  INTEGER NOT NULL,
+            PRIMARY KEY(chat_peer, msg_id, watchlist, term),
+            FOREIGN KEY(chat_peer) REFERENCES chats(peer_id)
+        );
+        CREATE INDEX IF NOT EXISTS idx_watch_hits_created ON watch_hits(created_at);
+
+        CREATE TABLE IF NOT EXISTS rule_hits (
+            rule            TEXT PRIMARY KEY,
+            hits            INTEGER NOT NULL DEFAULT 0,
+            last_hit_at     INTEGER,
+            last_chat_peer  TEXT,
+            last_msg_id     INTEGER
+        );
+
+        CREATE TABLE IF NOT EXISTS rule_matches (
+            rule       TEXT NOT NULL,
+            chat_peer  TEXT NOT NULL,
+            msg_id     INTEGER NOT NULL,  -- 0 for chat rules
+            PRIMARY KEY (rule, chat_peer, msg_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS chat_stats_daily (
+            chat_peer     TEXT NOT NULL,
+            day           TEXT NOT NULL,
+            messages      INTEGER NOT NULL DEFAULT 0,
+            senders       INTEGER NOT NULL DEFAULT 0,
+            own_messages  INTEGER NOT NULL DEFAULT 0,
+            mentions      INTEGER NOT NULL DEFAULT 0,
+            member_count  INTEGER,
+            last_row      INTEGER,
+            PRIMARY KEY(chat_peer, day),
+            FOREIGN KEY(chat_peer) REFERENCES chats(peer_id)
+        );
+
+        CREATE TABLE IF NOT EXISTS runs (
+            id               INTEGER PRIMARY KEY AUTOINCREMENT,
+            started_at       INTEGER NOT NULL,
+            finished_at      INTEGER,
+            chats            INTEGER NOT NULL DEFAULT 0,
+            messages_stored  INTEGER NOT NULL DEFAULT 0,
+            chats_analyzed   INTEGER NOT NULL DEFAULT 0,
+            status           TEXT NOT NULL DEFAULT 'running'
+        );
     "#).await?;
+
+    // Columns added after the first release; CREATE TABLE IF NOT EXISTS doesn't add them to old databases
+    add_column_if_missing(&conn, "chat_messages", "urgent_at", "INTEGER").await?;
+    add_column_if_missing(&conn, "chat_messages", "urgent_state", "TEXT").await?;
+    add_column_if_missing(&conn, "chat_messages", "urgent_state_by", "TEXT").await?;
+    add_column_if_missing(&conn, "chat_messages", "urgent_state_at", "INTEGER").await?;
+    add_column_if_missing(&conn, "chat_messages", "urgent_source", "TEXT").await?;
+    // Urgent messages flagged before states existed: those acknowledged in the dashboard
+    // (databases from before the states still have `acknowledged_at`) stay acknowledged,
+    // the rest start out as new
+    if has_column(&conn, "chat_messages", "acknowledged_at").await? {
+        conn.execute(
+            "UPDATE chat_messages SET urgent_state = 'acknowledged', urgent_state_at = acknowledged_at \n             WHERE urgent = 1 AND acknowledged_at IS NOT NULL AND urgent_state IS NULL;",
+            &[],
+        ).await?;
+    }
+    conn.execute("UPDATE chat_messages SET urgent_state = 'new' WHERE urgent = 1 AND urgent_state IS NULL;", &[]).await?;
+    add_column_if_missing(&conn, "chats", "name_decision", "TEXT").await?;
+    add_column_if_missing(&conn, "chats", "category_decision", "TEXT").await?;
+    add_column_if_missing(&conn, "chats", "category_override", "TEXT").await?;
+    add_column_if_missing(&conn, "chats", "rule_category", "TEXT").await?;
+    add_column_if_missing(&conn, "chats", "ai_category", "TEXT").await?;
+    // Before the model's category had its own column, a category that neither the user nor a
+    // rule set was the model's
+    conn.execute(
+        "UPDATE chats SET ai_category = category \n         WHERE ai_category IS NULL AND category_override IS NULL AND rule_category IS NULL;",
+        &[],
+    ).await?;
+    add_column_if_missing(&conn, "chat_messages", "sender_id", "INTEGER").await?;
+    // Per-person counts and the top posters look messages up by sender
+    conn.execute("CREATE INDEX IF NOT EXISTS idx_chat_messages_sender ON chat_messages(sender_id, date);", &[]).await?;
+    add_column_if_missing(&conn, "chat_messages", "outgoing", "BOOLEAN DEFAULT 0").await?;
+    add_column_if_missing(&conn, "chat_messages", "reply_to", "INTEGER").await?;
+    add_column_if_missing(&conn, "chat_messages", "mentions_me", "BOOLEAN DEFAULT 0").await?;
+    add_column_if_missing(&conn, "chats", "last_message_at", "INTEGER").await?;
+    add_column_if_missing(&conn, "chats", "archived", "BOOLEAN DEFAULT 0").await?;
+    add_column_if_missing(&conn, "chats", "muted", "BOOLEAN DEFAULT 0").await?;
+    add_column_if_missing(&conn, "chats", "member_count", "INTEGER").await?;
+    add_column_if_missing(&conn, "users", "access_hash", "INTEGER").await?;
+    add_column_if_missing(&conn, "users", "bot", "BOOLEAN DEFAULT 0").await?;
+    add_column_if_missing(&conn, "users", "verified", "BOOLEAN DEFAULT 0").await?;
+    add_column_if_missing(&conn, "users", "premium", "BOOLEAN DEFAULT 0").await?;
+    add_column_if_missing(&conn, "users", "common_chats_count", "INTEGER").await?;
+    add_column_if_missing(&conn, "users", "profile_fetched_at", "INTEGER").await?;
+    // Interest profiles used to live on users, which gave people only seen as message senders
+    // a placeholder users row named "user <id>"
+    if has_column(&conn, "users", "topics").await? {
+        conn.execute(
+            "INSERT OR IGNORE INTO person_topics (user_id, topics, topics_at) \n             SELECT user_id, topics, COALESCE(topics_at, 0) FROM users WHERE topics IS NOT NULL;",
+            &[],
+        ).await?;
+        conn.execute(
+            "DELETE FROM users \n             WHERE name = 'user ' || user_id AND username IS NULL AND access_hash IS NULL \n               AND NOT EXISTS (SELECT 1 FROM chat_members cm WHERE cm.user_id = users.user_id);",
+            &[],
+        ).await?;
+    }
     
     Ok(conn)
 }
 
+/// Helper: add a column to an existing table unless it is already there.
+async fn add_column_if_missing(conn: &Connection, table: &str, column: &str, decl: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if !has_column(conn, table, column).await? {
+        conn.execute(&format!("ALTER TABLE {} ADD COLUMN {} {};", table, column, decl), &[]).await?;
+    }
+    Ok(())
+}
+
+/// Helper: whether a table has a column.
+async fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({});", table)).await?;
+    let mut rows = stmt.query(&[]).await?;
+    while let Some(row) = rows.next().await? {
+        let name: String = row.get(1)?;
+        if name == column {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
 /// Insert or update chat info in the database (without touching AI fields).
 pub async fn save_chat(conn: &mut Connection, chat: &ChatInfo) -> Result<(), Box<dyn std::error::Error>> {
     conn.execute(
-        "INSERT INTO chats (peer_id, type, tg_id, name, access_hash)\n         VALUES (?1, ?2, ?3, ?4, ?5)\n         ON CONFLICT(peer_id) DO UPDATE SET \n            name = excluded.name, access_hash = excluded.access_hash;",
+        "INSERT INTO chats (peer_id, type, tg_id, name, access_hash, last_message_at, archived, muted, member_count)\n         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)\n         ON CONFLICT(peer_id) DO UPDATE SET \n            name = excluded.name, access_hash = excluded.access_hash, \n            last_message_at = COALESCE(excluded.last_message_at, last_message_at), \n            archived = excluded.archived, muted = excluded.muted, \n            member_count = COALESCE(excluded.member_count, member_count);",
+        &[&chat.peer_id,
+          &match chat.kind {
+              crate::telegram::ChatKind::Group => "Group",
+              crate::telegram::ChatKind::Channel => "Channel",
+          },
+          &chat.tg_id,
+          &chat.title,
+          &chat.access_hash.unwrap_or(0),
+          &chat.last_message_at,
+          &(chat.archived as i32),
+          &(chat.muted as i32),
+          &chat.member_count],
+    ).await?;
+    Ok(())
+}
+
+/// Insert a chat known only from an offline source (e.g. an export) if it isn't stored yet.
+/// Existing rows are left untouched so live data such as the access hash is never clobbered.
+pub async fn ensure_chat(conn: &mut Connection, chat: &ChatInfo) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "INSERT OR IGNORE INTO chats (peer_id, type, tg_id, name, access_hash)\n         VALUES (?1, ?2, ?3, ?4, ?5);",
         &[&chat.peer_id,
           &match chat.kind {
               crate::telegram::ChatKind::Group => "Group",
@@ -85,14 +342,28 @@ pub async fn save_chat(conn: &mut Connection, chat: &ChatInfo) -> Result<(), Box
           },
           &chat.tg_id,
           &chat.title,
-          &chat.access_hash.unwrap_or(0)],
+          &chat.access_hash],
     ).await?;
     Ok(())
 }
 
+/// Get the ids of all stored messages for a chat.
+pub async fn get_message_ids(conn: &Connection, chat_peer: &str) -> Result<HashSet<i32>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT msg_id FROM chat_messages WHERE chat_peer = ?1;"
+    ).await?;
+    let mut ids = HashSet::new();
+    let mut rows = stmt.query(&[&chat_peer]).await?;
+    while let Some(row) = rows.next().await? {
+        ids.insert(row.get(0)?);
+    }
+    Ok(ids)
+}
+
 /// Save a batch of new messages for a chat, including their embeddings.
-/// Each tuple contains a MessageInfo and its corresponding embedding vector.
-pub async fn save_messages(conn: &mut Connection, chat_peer: &str, messages: &[(MessageInfo, Vec<f32>)]) 
+/// Each tuple contains a MessageInfo and its embedding vector, if one could be computed.
+/// The chat's ingest checkpoint is advanced in the same transaction.
+pub async fn save_messages(conn: &mut Connection, chat_peer: &str, messages: &[(MessageInfo, Option<Vec<f32>>)]) 
     -> Result<(), Box<dyn std::error::Error>> 
 {
     if messages.is_empty() {
@@ -100,94 +371,372 @@ pub async fn save_messages(conn: &mut Connection, chat_peer: &str, messages: &[(
     }
     let mut tx = conn.transaction().await?;
     for (msg, embedding) in messages {
-        let emb_blob = embedding_to_blob(embedding)?;
+        let emb_blob = embedding.as_ref().map(embedding_to_blob).transpose()?;
         tx.execute(
-            "INSERT OR IGNORE INTO chat_messages (chat_peer, msg_id, date, text, embedding) \n             VALUES (?1, ?2, ?3, ?4, ?5);",
-            &[&chat_peer, &msg.msg_id, &msg.date, &msg.text, &emb_blob],
+            "INSERT OR IGNORE INTO chat_messages (chat_peer, msg_id, date, text, embedding, sender_id, outgoing, reply_to, mentions_me) \n             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9);",
+            &[&chat_peer, &msg.msg_id, &msg.date, &msg.text, &emb_blob, &msg.sender_id,
+              &(msg.outgoing as i32), &msg.reply_to, &(msg.mentions_me as i32)],
         ).await?;
     }
+    let max_id = messages.iter().map(|(m, _)| m.msg_id).max().unwrap_or(0);
+    tx.execute(
+        "INSERT INTO chat_checkpoints (chat_peer, last_ingested_msg_id, updated_at) \n         VALUES (?1, ?2, ?3) \n         ON CONFLICT(chat_peer) DO UPDATE SET \n            last_ingested_msg_id = MAX(last_ingested_msg_id, excluded.last_ingested_msg_id), \n            updated_at = excluded.updated_at;",
+        &[&chat_peer, &max_id, &(Utc::now().timestamp() as i32)],
+    ).await?;
     tx.commit().await?;
     Ok(())
 }
 
+/// Messages stored without an embedding (while the embedding model was unavailable), oldest first.
+/// Returns (chat_peer, msg_id, text).
+pub async fn get_messages_without_embedding(conn: &Connection, limit: i64) -> Result<Vec<(String, i32, String)>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT chat_peer, msg_id, text FROM chat_messages \n         WHERE embedding IS NULL \n         ORDER BY id ASC \n         LIMIT ?1;"
+    ).await?;
+    let mut rows = stmt.query(&[&limit]).await?;
+    let mut messages = Vec::new();
+    while let Some(row) = rows.next().await? {
+        messages.push((row.get(0)?, row.get(1)?, row.get(2)?));
+    }
+    Ok(messages)
+}
+
+/// Store the embedding of a message that was saved without one.
+pub async fn set_message_embedding(conn: &Connection, chat_peer: &str, msg_id: i32, embedding: &Vec<f32>)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let emb_blob = embedding_to_blob(embedding)?;
+    conn.execute(
+        "UPDATE chat_messages SET embedding = ?1 WHERE chat_peer = ?2 AND msg_id = ?3;",
+        &[&emb_blob, &chat_peer, &msg_id],
+    ).await?;
+    Ok(())
+}
+
+/// Per-chat progress markers: the newest stored message and the newest analyzed message.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Checkpoint {
+    pub last_ingested_msg_id: i32,
+    pub last_analyzed_msg_id: i32,
+}
+
+impl Checkpoint {
+    /// True if stored messages exist that have not been analyzed yet.
+    pub fn analysis_pending(&self) -> bool {
+        self.last_analyzed_msg_id < self.last_ingested_msg_id
+    }
+}
+
+/// Get the checkpoint for a chat, or None if nothing has been ingested for it yet.
+pub async fn get_checkpoint(conn: &Connection, chat_peer: &str) -> Result<Option<Checkpoint>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT last_ingested_msg_id, last_analyzed_msg_id FROM chat_checkpoints WHERE chat_peer = ?1;"
+    ).await?;
+    let mut rows = stmt.query(&[&chat_peer]).await?;
+    if let Some(row) = rows.next().await? {
+        return Ok(Some(Checkpoint {
+            last_ingested_msg_id: row.get(0)?,
+            last_analyzed_msg_id: row.get(1)?,
+        }));
+    }
+    Ok(None)
+}
+
+/// Record that analysis has covered every message up to and including `msg_id`.
+pub async fn set_analyzed_checkpoint(conn: &mut Connection, chat_peer: &str, msg_id: i32) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "INSERT INTO chat_checkpoints (chat_peer, last_analyzed_msg_id, updated_at) \n         VALUES (?1, ?2, ?3) \n         ON CONFLICT(chat_peer) DO UPDATE SET \n            last_analyzed_msg_id = MAX(last_analyzed_msg_id, excluded.last_analyzed_msg_id), \n            updated_at = excluded.updated_at;",
+        &[&chat_peer, &msg_id, &(Utc::now().timestamp() as i32)],
+    ).await?;
+    Ok(())
+}
+
+/// Get the retry state (attempts, next_attempt_at) of a chat whose analysis failed, if any.
+pub async fn get_analysis_retry(conn: &Connection, chat_peer: &str) -> Result<Option<(i32, i64)>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT attempts, next_attempt_at FROM analysis_retries WHERE chat_peer = ?1;"
+    ).await?;
+    let mut rows = stmt.query(&[&chat_peer]).await?;
+    if let Some(row) = rows.next().await? {
+        return Ok(Some((row.get(0)?, row.get(1)?)));
+    }
+    Ok(None)
+}
+
+/// Record a failed analysis attempt and when it may be retried next.
+pub async fn record_analysis_failure(conn: &mut Connection, chat_peer: &str, attempts: i32, next_attempt_at: i64, error_msg: &str)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    conn.execute(
+        "INSERT OR REPLACE INTO analysis_retries (chat_peer, attempts, next_attempt_at, last_error) \n         VALUES (?1, ?2, ?3, ?4);",
+        &[&chat_peer, &attempts, &next_attempt_at, &error_msg],
+    ).await?;
+    Ok(())
+}
+
+/// Remove a chat from the retry queue after a successful analysis.
+pub async fn clear_analysis_retry(conn: &mut Connection, chat_peer: &str) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute("DELETE FROM analysis_retries WHERE chat_peer = ?1;", &[&chat_peer]).await?;
+    Ok(())
+}
+
+/// Resumable progress of a historical backfill for one chat.
+#[derive(Debug, Clone, Default)]
+pub struct BackfillCursor {
+    pub oldest_msg_id: i32,       // Oldest message reached so far (0 = not started)
+    pub messages_fetched: i64,    // Messages stored by the backfill so far
+    pub since: Option<i64>,       // Date cutoff (UNIX timestamp) the backfill ran with
+    pub done: bool,               // Start of history or cutoff reached
+}
+
+/// Get the backfill cursor for a chat, if a backfill was ever started for it.
+pub async fn get_backfill_cursor(conn: &Connection, chat_peer: &str) -> Result<Option<BackfillCursor>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT oldest_msg_id, messages_fetched, since, done FROM backfill_cursors WHERE chat_peer = ?1;"
+    ).await?;
+    let mut rows = stmt.query(&[&chat_peer]).await?;
+    if let Some(row) = rows.next().await? {
+        let done: i32 = row.get(3)?;
+        return Ok(Some(BackfillCursor {
+            oldest_msg_id: row.get(0)?,
+            messages_fetched: row.get(1)?,
+            since: row.get(2)?,
+            done: done != 0,
+        }));
+    }
+    Ok(None)
+}
+
+/// Insert or update the backfill cursor for a chat.
+pub async fn save_backfill_cursor(conn: &mut Connection, chat_peer: &str, cursor: &BackfillCursor) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "INSERT OR REPLACE INTO backfill_cursors (chat_peer, oldest_msg_id, messages_fetched, since, done, updated_at) \n         VALUES (?1, ?2, ?3, ?4, ?5, ?6);",
+        &[&chat_peer, &cursor.oldest_msg_id, &cursor.messages_fetched, &cursor.since, &(cursor.done as i32), &(Utc::now().timestamp() as i32)],
+    ).await?;
+    Ok(())
+}
+
+/// Get (peer_id, name) of every stored chat, ordered by name.
+pub async fn get_chats(conn: &Connection) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT peer_id, name FROM chats ORDER BY name COLLATE NOCASE;"
+    ).await?;
+    let mut chats = Vec::new();
+    let mut rows = stmt.query(&[]).await?;
+    while let Some(row) = rows.next().await? {
+        chats.push((row.get(0)?, row.get(1)?));
+    }
+    Ok(chats)
+}
+
+/// Number of stored messages in a chat.
+pub async fn count_messages(conn: &Connection, chat_peer: &str) -> Result<i64, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("SELECT COUNT(*) FROM chat_messages WHERE chat_peer = ?1;").await?;
+    let mut rows = stmt.query(&[&chat_peer]).await?;
+    match rows.next().await? {
+        Some(row) => Ok(row.get(0)?),
+        None => Ok(0),
+    }
+}
+
+/// Get up to `limit` stored messages newer than `after_msg_id`, oldest first.
+pub async fn get_messages_after(conn: &Connection, chat_peer: &str, after_msg_id: i32, limit: usize)
+    -> Result<Vec<MessageInfo>, Box<dyn std::error::Error>>
+{
+    let mut stmt = conn.prepare(
+        "SELECT msg_id, date, text, sender_id, outgoing, reply_to, mentions_me FROM chat_messages \n         WHERE chat_peer = ?1 AND msg_id > ?2 \n         ORDER BY msg_id ASC \n         LIMIT ?3;"
+    ).await?;
+    let mut rows = stmt.query(&[&chat_peer, &after_msg_id, &(limit as i64)]).await?;
+    let mut messages = Vec::new();
+    while let Some(row) = rows.next().await? {
+        messages.push(MessageInfo {
+            msg_id: row.get(0)?,
+            date: row.get(1)?,
+            text: row.get(2)?,
+            sender_id: row.get(3)?,
+            outgoing: row.get::<i32>(4)? != 0,
+            reply_to: row.get(5)?,
+            mentions_me: row.get::<i32>(6)? != 0,
+        });
+    }
+    Ok(messages)
+}
+
+/// Of the given message ids in a chat, those of stored messages sent by the account owner.
+pub async fn get_outgoing_ids(conn: &Connection, chat_peer: &str, msg_ids: &[i32])
+    -> Result<HashSet<i32>, Box<dyn std::error::Error>>
+{
+    let mut ids = HashSet::new();
+    if msg_ids.is_empty() {
+        return Ok(ids);
+    }
+    let mut params: Vec<Value> = vec![chat_peer.to_string().into()];
+    let placeholders: Vec<String> = msg_ids.iter().map(|id| {
+        params.push((*id as i64).into());
+        format!("?{}", params.len())
+    }).collect();
+    let sql = format!(
+        "SELECT msg_id FROM chat_messages WHERE chat_peer = ?1 AND outgoing = 1 AND msg_id IN ({});",
+        placeholders.join(", ")
+    );
+    let mut stmt = conn.prepare(&sql).await?;
+    let mut rows = stmt.query(params).await?;
+    while let Some(row) = rows.next().await? {
+        ids.insert(row.get(0)?);
+    }
+    Ok(ids)
+}
+
 /// Update chat analysis results (category, suggested name, duplicate flag) for a given chat.
+/// The model's category is kept in `ai_category`; a category set by the user
+/// (`category_override`) always wins over it, and one set by a rule (`rule_category`) does too.
+/// Review decisions are kept only for values that didn't change.
+/// Returns the category the chat ended up with.
 pub async fn update_chat_analysis(conn: &mut Connection, peer_id: &str, category: &str, suggested_name: &str, duplicate: bool) 
-    -> Result<(), Box<dyn std::error::Error>> 
+    -> Result<String, Box<dyn std::error::Error>> 
+{
+    let mut stmt = conn.prepare(
+        "UPDATE chats SET ai_category = ?1, category = COALESCE(category_override, rule_category, ?1), suggested_name = ?2, duplicate = ?3, \n             category_decision = CASE WHEN category IS COALESCE(category_override, rule_category, ?1) THEN category_decision END, \n             name_decision = CASE WHEN suggested_name IS ?2 THEN name_decision END \n         WHERE peer_id = ?4 RETURNING category;"
+    ).await?;
+    let mut rows = stmt.query(&[&category, &suggested_name, &(duplicate as i32), &peer_id]).await?;
+    match rows.next().await? {
+        Some(row) => Ok(row.get(0)?),
+        None => Ok(category.to_string()),
+    }
+}
+
+/// Which analysis result of a chat a review decision applies to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Suggestion {
+    Name,
+    Category,
+}
+
+/// Record whether the user accepted or rejected a chat's suggested name or category
+/// (`None` clears the decision). A decision lasts until the analysis suggests something else.
+pub async fn set_suggestion_decision(conn: &mut Connection, peer_id: &str, suggestion: Suggestion, decision: Option<&str>)
+    -> Result<(), Box<dyn std::error::Error>>
 {
+    let column = match suggestion {
+        Suggestion::Name => "name_decision",
+        Suggestion::Category => "category_decision",
+    };
     conn.execute(
-        "UPDATE chats SET category = ?1, suggested_name = ?2, duplicate = ?3 WHERE peer_id = ?4;",
-        &[&category, &suggested_name, &(duplicate as i32), &peer_id],
+        &format!("UPDATE chats SET {} = ?1 WHERE peer_id = ?2;", column),
+        &[&decision, &peer_id],
     ).await?;
     Ok(())
 }
 
-/// Mark specific messages as urgent in the database.
-pub async fn mark_urgent(conn: &mut Connection, chat_peer: &str, msg_ids: &[i32]) -> Result<(), Box<dyn std::error::Error>> {
+/// Mark specific messages as urgent in the database, recording who flagged them
+/// (`ai` or `rule`).
+/// Returns the ids that were not flagged before, i.e. the newly urgent messages.
+pub async fn mark_urgent(conn: &mut Connection, chat_peer: &str, msg_ids: &[i32], source: &str) -> Result<Vec<i32>, Box<dyn std::error::Error>> {
     if msg_ids.is_empty() {
-        return Ok(());
+        return Ok(Vec::new());
     }
+    let now = Utc::now().timestamp() as i32;
+    let mut newly_flagged = Vec::new();
     let mut tx = conn.transaction().await?;
     for &mid in msg_ids {
-        tx.execute(
-            "UPDATE chat_messages SET urgent = 1 WHERE chat_peer = ?1 AND msg_id = ?2;",
-            &[&chat_peer, &mid],
+        let changed = tx.execute(
+            "UPDATE chat_messages SET urgent = 1, urgent_at = ?1, urgent_state = 'new', urgent_source = ?4 WHERE chat_peer = ?2 AND msg_id = ?3 AND urgent = 0;",
+            &[&now, &chat_peer, &mid, &source],
         ).await?;
+        if changed > 0 {
+            newly_flagged.push(mid);
+        }
     }
     tx.commit().await?;
-    Ok(())
+    Ok(newly_flagged)
 }
 
-/// Generate a report of all chats and any urgent messages, printing to stdout.
-pub async fn print_report(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
-    println!("=== Telegram Chats Report ===");
-    let mut stmt = conn.prepare(
-        "SELECT peer_id, name, category, suggested_name, duplicate FROM chats ORDER BY name COLLATE NOCASE;"
-    ).await?;
-    let mut rows = stmt.query(&[]).await?;
-    while let Some(row) = rows.next().await? {
-        let peer_id: String = row.get(0)?;
-        let name: String = row.get(1)?;
-        let category: Option<String> = row.get(2)?;
-        let suggested: Option<String> = row.get(3)?;
-        let duplicate_flag: i32 = row.get(4)?;
-        let category_str = category.unwrap_or_else(|| "Uncategorized".into());
-        let suggested_str = suggested.unwrap_or_else(|| "-".into());
-        let duplicate_str = if duplicate_flag != 0 { "Yes" } else { "No" };
-        println!("\nChat: {}{}", name, if duplicate_flag != 0 { " (Duplicate Topic)" } else { "" });
-        println!(" - Category: {}", category_str);
-        println!(" - Suggested Name: {}", suggested_str);
-        println!(" - Duplicate: {}", duplicate_str);
-
-        let mut msg_stmt = conn.prepare(
-            "SELECT date, text FROM chat_messages \n             WHERE chat_peer = ?1 AND urgent = 1 ORDER BY date ASC;"
+/// Clear the urgent flag of messages among `msg_ids` that an earlier analysis flagged but
+/// `keep` no longer does. Flags set by rules or by hand, and messages already reviewed
+/// (any state but new), are left alone. Flags from before sources were recorded count as the
+/// analyzer's. Returns the number of cleared flags.
+pub async fn clear_stale_urgent(conn: &mut Connection, chat_peer: &str, msg_ids: &[i32], keep: &[i32])
+    -> Result<u64, Box<dyn std::error::Error>>
+{
+    let mut cleared = 0;
+    let mut tx = conn.transaction().await?;
+    for mid in msg_ids.iter().filter(|id| !keep.contains(id)) {
+        cleared += tx.execute(
+            "UPDATE chat_messages SET urgent = 0, urgent_at = NULL, urgent_state = NULL, urgent_source = NULL \n             WHERE chat_peer = ?1 AND msg_id = ?2 AND urgent = 1 AND urgent_state = 'new' \n               AND urgent_state_by IS NULL AND COALESCE(urgent_source, 'ai') = 'ai';",
+            &[&chat_peer, mid],
         ).await?;
-        let mut msg_rows = msg_stmt.query(&[&peer_id]).await?;
-        while let Some(msg_row) = msg_rows.next().await? {
-            let ts: i32 = msg_row.get(0)?;
-            let text: String = msg_row.get(1)?;
-            let dt = DateTime::<Utc>::from_timestamp(ts as i64, 0);
-            let datetime_str = dt.format("%Y-%m-%d %H:%M:%S").to_string();
-            let snippet = if text.len() > 50 { &text[..50] } else { &text };
-            println!("   * [URGENT @ {}] {}", datetime_str, snippet);
-        }
     }
-    println!("\nEnd of report.");
-    Ok(())
+    tx.commit().await?;
+    Ok(cleared)
 }
 
-/// Save or update member information for a chat.
-pub async fn save_member(conn: &mut Connection, chat_peer: &str, user_id: i64, name: &str, username: Option<&str>, bio: Option<&str>, last_seen: i32) 
+/// Save or update member information for a chat. The bio and the rest of the full profile
+/// are left as they are; those come from `save_user_profile`.
+pub async fn save_member(conn: &mut Connection, chat_peer: &str, member: &MemberInfo, last_seen: i32) 
     -> Result<(), Box<dyn std::error::Error>> 
 {
     let mut tx = conn.transaction().await?;
     tx.execute(
-        "INSERT OR REPLACE INTO users (user_id, name, username, bio, last_seen) \n         VALUES (?1, ?2, ?3, ?4, ?5);",
-        &[&user_id, &name, &username, &bio, &last_seen],
+        "INSERT INTO users (user_id, name, username, access_hash, bot, verified, premium, last_seen) \n         VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8) \n         ON CONFLICT(user_id) DO UPDATE SET \n            name = excluded.name, username = excluded.username, \n            access_hash = COALESCE(excluded.access_hash, access_hash), \n            bot = excluded.bot, verified = excluded.verified, premium = excluded.premium, \n            last_seen = excluded.last_seen;",
+        &[&member.user_id, &member.name, &member.username, &member.access_hash,
+          &(member.bot as i32), &(member.verified as i32), &(member.premium as i32), &last_seen],
     ).await?;
 
     tx.execute(
         "INSERT OR REPLACE INTO chat_members (chat_peer, user_id, joined_at) \n         VALUES (?1, ?2, ?3);",
-        &[&chat_peer, &user_id, &(Utc::now().timestamp() as i32)],
+        &[&chat_peer, &member.user_id, &(Utc::now().timestamp() as i32)],
+    ).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Store a user's full profile and when it was fetched.
+pub async fn save_user_profile(conn: &mut Connection, user_id: i64, profile: &UserProfile) -> Result<(), Box<dyn std::error::Error>> {
+    let now = Utc::now().timestamp();
+    conn.execute(
+        "UPDATE users SET name = ?2, username = ?3, bio = ?4, bot = ?5, verified = ?6, premium = ?7, \n            common_chats_count = ?8, profile_fetched_at = ?9 \n         WHERE user_id = ?1;",
+        &[&user_id, &profile.name, &profile.username, &profile.bio,
+          &(profile.bot as i32), &(profile.verified as i32), &(profile.premium as i32),
+          &profile.common_chats_count, &now],
+    ).await?;
+    Ok(())
+}
+
+/// Record a failed profile lookup as a fetch, so the user waits a full TTL before the next try.
+pub async fn touch_user_profile(conn: &mut Connection, user_id: i64) -> Result<(), Box<dyn std::error::Error>> {
+    let now = Utc::now().timestamp();
+    conn.execute("UPDATE users SET profile_fetched_at = ?2 WHERE user_id = ?1;", &[&user_id, &now]).await?;
+    Ok(())
+}
+
+/// Users whose full profile was never fetched or was fetched before `fetched_before`, never
+/// fetched first, then the stalest. Only users with an access hash can be looked up.
+/// Returns (user_id, access_hash, name).
+pub async fn get_profiles_due(conn: &Connection, fetched_before: i64, limit: i64) -> Result<Vec<(i64, i64, String)>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT user_id, access_hash, name \n         FROM users \n         WHERE access_hash IS NOT NULL AND (profile_fetched_at IS NULL OR profile_fetched_at < ?1) \n         ORDER BY profile_fetched_at IS NOT NULL, profile_fetched_at \n         LIMIT ?2;"
+    ).await?;
+    let mut rows = stmt.query(&[&fetched_before, &limit]).await?;
+    let mut users = Vec::new();
+    while let Some(row) = rows.next().await? {
+        users.push((row.get(0)?, row.get(1)?, row.get(2)?));
+    }
+    Ok(users)
+}
+
+/// Record a chat member known only from an offline source, without overwriting
+/// a stored profile (username, bio) that came from Telegram.
+pub async fn ensure_member(conn: &mut Connection, chat_peer: &str, user_id: i64, name: &str, joined_at: i32)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let mut tx = conn.transaction().await?;
+    tx.execute(
+        "INSERT OR IGNORE INTO users (user_id, name) VALUES (?1, ?2);",
+        &[&user_id, &name],
+    ).await?;
+    tx.execute(
+        "INSERT OR IGNORE INTO chat_members (chat_peer, user_id, joined_at) VALUES (?1, ?2, ?3);",
+        &[&chat_peer, &user_id, &joined_at],
     ).await?;
     tx.commit().await?;
     Ok(())
@@ -217,4 +766,1649 @@ pub async fn get_user_chats(conn: &Connection, user_id: i64) -> Result<Vec<Strin
         result.push(row.get(0)?);
     }
     Ok(result)
-} 
\ No newline at end of file
+}
+
+/// A stored person, with their activity across all chats.
+#[derive(Debug, Clone)]
+pub struct Person {
+    pub user_id: i64,
+    pub name: Option<String>,      // None if only known as a message sender
+    pub username: Option<String>,
+    pub bio: Option<String>,
+    pub messages: i64,
+    pub chats: i64,                // chats they are a member of or wrote in
+    pub last_message_at: Option<i32>,
+    pub topics: Option<String>,    // LLM profile of their interests
+    pub topics_at: Option<i64>,    // when the profile was generated
+    pub bot: bool,
+    pub verified: bool,
+    pub premium: bool,
+    pub common_chats_count: Option<i64>,  // chats shared with us, per the last full profile fetch
+}
+
+/// Helper: SELECT list for `Person` rows, given the user id expression `id`.
+fn person_columns(id: &str) -> String {
+    format!(
+        "{id}, u.name, u.username, u.bio, \n            (SELECT COUNT(*) FROM chat_messages m WHERE m.sender_id = {id}), \n            (SELECT COUNT(*) FROM (SELECT chat_peer FROM chat_members WHERE user_id = {id} \n                                   UNION SELECT chat_peer FROM chat_messages WHERE sender_id = {id})), \n            (SELECT MAX(m.date) FROM chat_messages m WHERE m.sender_id = {id}), \n            t.topics, t.topics_at, COALESCE(u.bot, 0), COALESCE(u.verified, 0), COALESCE(u.premium, 0), u.common_chats_count",
+        id = id
+    )
+}
+
+/// Helper: read a `Person` from a row selected with `person_columns`.
+fn person_from_row(row: &libsql::Row) -> Result<Person, Box<dyn std::error::Error>> {
+    Ok(Person {
+        user_id: row.get(0)?,
+        name: row.get(1)?,
+        username: row.get(2)?,
+        bio: row.get(3)?,
+        messages: row.get(4)?,
+        chats: row.get(5)?,
+        last_message_at: row.get(6)?,
+        topics: row.get(7)?,
+        topics_at: row.get(8)?,
+        bot: row.get::<i32>(9)? != 0,
+        verified: row.get::<i32>(10)? != 0,
+        premium: row.get::<i32>(11)? != 0,
+        common_chats_count: row.get(12)?,
+    })
+}
+
+/// A person by id, if they are a stored user or have sent a stored message.
+pub async fn get_person(conn: &Connection, user_id: i64) -> Result<Option<Person>, Box<dyn std::error::Error>> {
+    let sql = format!(
+        "SELECT {} \n         FROM (SELECT ?1 AS id) p \n         LEFT JOIN users u ON u.user_id = p.id \n         LEFT JOIN person_topics t ON t.user_id = p.id \n         WHERE u.user_id IS NOT NULL OR EXISTS (SELECT 1 FROM chat_messages m WHERE m.sender_id = p.id);",
+        person_columns("p.id")
+    );
+    let mut stmt = conn.prepare(&sql).await?;
+    let mut rows = stmt.query(&[&user_id]).await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(person_from_row(&row)?)),
+        None => Ok(None),
+    }
+}
+
+/// Stored users whose name, username or bio contains `query` (case-insensitive; `*` and `?`
+/// wildcards), or all users without a query, most active first.
+pub async fn search_people(conn: &Connection, query: Option<&str>, limit: i64) -> Result<Vec<Person>, Box<dyn std::error::Error>> {
+    let mut params: Vec<Value> = Vec::new();
+    let mut sql = format!("SELECT {} FROM users u LEFT JOIN person_topics t ON t.user_id = u.user_id", person_columns("u.user_id"));
+    if let Some(query) = query {
+        params.push(format!("%{}%", like_pattern(query.trim_start_matches('@'))).into());
+        sql += " WHERE u.name LIKE ?1 ESCAPE '\\' OR u.username LIKE ?1 ESCAPE '\\' OR u.bio LIKE ?1 ESCAPE '\\'";
+    }
+    params.push(limit.into());
+    sql += &format!(" ORDER BY 5 DESC, u.name COLLATE NOCASE LIMIT ?{};", params.len());
+
+    let mut stmt = conn.prepare(&sql).await?;
+    let mut rows = stmt.query(params).await?;
+    let mut people = Vec::new();
+    while let Some(row) = rows.next().await? {
+        people.push(person_from_row(&row)?);
+    }
+    Ok(people)
+}
+
+/// A person's message count and latest message date per chat they wrote in, busiest first.
+/// Returns (chat_peer, messages, last message date).
+pub async fn get_sender_chats(conn: &Connection, user_id: i64) -> Result<Vec<(String, i64, i32)>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT chat_peer, COUNT(*), MAX(date) \n         FROM chat_messages \n         WHERE sender_id = ?1 \n         GROUP BY chat_peer \n         ORDER BY COUNT(*) DESC;"
+    ).await?;
+    let mut rows = stmt.query(&[&user_id]).await?;
+    let mut chats = Vec::new();
+    while let Some(row) = rows.next().await? {
+        chats.push((row.get(0)?, row.get(1)?, row.get(2)?));
+    }
+    Ok(chats)
+}
+
+/// A person's latest messages across all chats, newest first.
+/// Returns (chat name, date, text).
+pub async fn get_sender_messages(conn: &Connection, user_id: i64, limit: i64) -> Result<Vec<(String, i32, String)>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT c.name, m.date, m.text \n         FROM chat_messages m \n         JOIN chats c ON c.peer_id = m.chat_peer \n         WHERE m.sender_id = ?1 AND m.text != '' \n         ORDER BY m.date DESC \n         LIMIT ?2;"
+    ).await?;
+    let mut rows = stmt.query(&[&user_id, &limit]).await?;
+    let mut messages = Vec::new();
+    while let Some(row) = rows.next().await? {
+        messages.push((row.get(0)?, row.get(1)?, row.get(2)?));
+    }
+    Ok(messages)
+}
+
+/// Store the LLM profile of a person's interests. Kept apart from users so people only seen
+/// as message senders don't need a users row.
+pub async fn set_person_topics(conn: &mut Connection, user_id: i64, topics: &str)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let now = Utc::now().timestamp();
+    conn.execute(
+        "INSERT INTO person_topics (user_id, topics, topics_at) VALUES (?1, ?2, ?3) \n         ON CONFLICT(user_id) DO UPDATE SET topics = excluded.topics, topics_at = excluded.topics_at;",
+        &[&user_id, &topics, &now],
+    ).await?;
+    Ok(())
+}
+
+/// Filters for bulk queries (export, reports). `None` fields don't filter.
+#[derive(Debug, Clone, Default)]
+pub struct ChatFilter {
+    pub chat: Option<String>,      // Chat peer id, or name pattern (case-insensitive, `*` and `?` wildcards)
+    pub chat_exact: Option<String>, // Chat peer id or exact name (case-insensitive)
+    pub category: Option<String>,  // Exact category (case-insensitive)
+    pub tag: Option<String>,       // Tag on the chat or on one of its messages (case-insensitive)
+    pub since: Option<i64>,        // Messages at or after this UNIX timestamp
+    pub until: Option<i64>,        // Messages before this UNIX timestamp
+}
+
+/// A stored chat with its analysis results.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatRecord {
+    pub peer_id: String,
+    pub kind: String,
+    pub name: String,
+    pub category: Option<String>,
+    pub suggested_name: Option<String>,
+    pub duplicate: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name_decision: Option<String>,      // "accepted" or "rejected" once the suggested name was reviewed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub category_decision: Option<String>,  // "accepted" or "rejected" once the category was reviewed
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,                  // AI and manual tags of the chat
+}
+
+/// A stored message. `embedding` holds the raw little-endian f32 blob when requested.
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageRecord {
+    pub chat_peer: String,
+    pub msg_id: i32,
+    pub date: i32,
+    pub text: String,
+    pub urgent: bool,
+    #[serde(skip)]
+    pub embedding: Option<Vec<u8>>,
+}
+
+/// Get stored chats matching the chat and category filters, ordered by name.
+pub async fn get_chat_records(conn: &Connection, filter: &ChatFilter) -> Result<Vec<ChatRecord>, Box<dyn std::error::Error>> {
+    let mut sql = format!(
+        "SELECT peer_id, type, name, category, suggested_name, duplicate, name_decision, category_decision, {} FROM chats WHERE 1 = 1",
+        chat_tags_column(""),
+    );
+    let mut params: Vec<Value> = Vec::new();
+    push_chat_conditions(filter, "", &mut sql, &mut params);
+    sql += " ORDER BY name COLLATE NOCASE;";
+
+    let mut stmt = conn.prepare(&sql).await?;
+    let mut rows = stmt.query(params).await?;
+    let mut chats = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let duplicate: i32 = row.get(5)?;
+        chats.push(ChatRecord {
+            peer_id: row.get(0)?,
+            kind: row.get::<Option<String>>(1)?.unwrap_or_default(),
+            name: row.get(2)?,
+            category: row.get(3)?,
+            suggested_name: row.get(4)?,
+            duplicate: duplicate != 0,
+            name_decision: row.get(6)?,
+            category_decision: row.get(7)?,
+            tags: split_tags(row.get(8)?),
+        });
+    }
+    Ok(chats)
+}
+
+/// Helper: append the filter's chat and category conditions to a query on `chats`
+/// (columns prefixed with `alias`, e.g. "c."), numbering parameters after those already in `params`.
+fn push_chat_conditions(filter: &ChatFilter, alias: &str, sql: &mut String, params: &mut Vec<Value>) {
+    if let Some(chat) = &filter.chat {
+        params.push(chat.clone().into());
+        let peer_param = params.len();
+        params.push(like_pattern(chat).into());
+        *sql += &format!(" AND ({a}peer_id = ?{} OR {a}name LIKE ?{} ESCAPE '\\')", peer_param, params.len(), a = alias);
+    }
+    if let Some(chat) = &filter.chat_exact {
+        params.push(chat.clone().into());
+        *sql += &format!(" AND ({a}peer_id = ?{p} OR {a}name = ?{p} COLLATE NOCASE)", a = alias, p = params.len());
+    }
+    if let Some(category) = &filter.category {
+        params.push(category.clone().into());
+        *sql += &format!(" AND {}category = ?{} COLLATE NOCASE", alias, params.len());
+    }
+    if let Some(tag) = &filter.tag {
+        params.push(tag.clone().into());
+        *sql += &format!(
+            " AND ({a}peer_id IN (SELECT ct.chat_peer FROM chat_tags ct JOIN tags t ON t.id = ct.tag_id \n                 WHERE t.name = ?{p} AND ct.source != 'removed') \n             OR {a}peer_id IN (SELECT mt.chat_peer FROM message_tags mt JOIN tags t ON t.id = mt.tag_id \n                 WHERE t.name = ?{p} AND mt.source != 'removed'))",
+            a = alias, p = params.len(),
+        );
+    }
+}
+
+/// Helper: a column with the chat's tags joined by TAG_SEPARATOR (for `chats` aliased as `alias`).
+fn chat_tags_column(alias: &str) -> String {
+    format!(
+        "(SELECT GROUP_CONCAT(t.name, char(31)) FROM chat_tags ct JOIN tags t ON t.id = ct.tag_id \n          WHERE ct.chat_peer = {}peer_id AND ct.source != 'removed')",
+        alias,
+    )
+}
+
+/// Separator of the tag list built by `chat_tags_column` (ASCII unit separator).
+const TAG_SEPARATOR: char = '\u{1f}';
+
+/// Helper: split a `chat_tags_column` value into sorted tag names.
+fn split_tags(tags: Option<String>) -> Vec<String> {
+    let mut tags: Vec<String> = tags.map(|t| t.split(TAG_SEPARATOR).map(String::from).collect()).unwrap_or_default();
+    tags.sort_by_key(|t| t.to_lowercase());
+    tags
+}
+
+/// Helper: translate a `*`/`?` wildcard pattern into a SQL LIKE pattern.
+fn like_pattern(pattern: &str) -> String {
+    let mut like = String::with_capacity(pattern.len());
+    for c in pattern.chars() {
+        match c {
+            '*' => like.push('%'),
+            '?' => like.push('_'),
+            '%' | '_' | '\\' => {
+                like.push('\\');
+                like.push(c);
+            }
+            _ => like.push(c),
+        }
+    }
+    like
+}
+
+/// Review state of an urgent message. New and acknowledged messages are still open;
+/// resolved ones and false positives no longer show up in reports or notifications.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UrgentState {
+    New,
+    Acknowledged,
+    Resolved,
+    FalsePositive,
+}
+
+impl UrgentState {
+    pub const OPEN: [UrgentState; 2] = [UrgentState::New, UrgentState::Acknowledged];
+    pub const ALL: [UrgentState; 4] = [UrgentState::New, UrgentState::Acknowledged, UrgentState::Resolved, UrgentState::FalsePositive];
+
+    /// Name stored in the database.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UrgentState::New => "new",
+            UrgentState::Acknowledged => "acknowledged",
+            UrgentState::Resolved => "resolved",
+            UrgentState::FalsePositive => "false_positive",
+        }
+    }
+
+    /// Parse a state name as given on the command line or in a request.
+    pub fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        match value.to_lowercase().replace('-', "_").as_str() {
+            "new" => Ok(UrgentState::New),
+            "acknowledged" | "ack" => Ok(UrgentState::Acknowledged),
+            "resolved" => Ok(UrgentState::Resolved),
+            "false_positive" | "fp" => Ok(UrgentState::FalsePositive),
+            other => Err(format!("Unknown urgent state \"{}\" (expected new, acknowledged, resolved or false-positive)", other).into()),
+        }
+    }
+}
+
+/// SQL expression counting a chat's open urgent messages in `get_report_chats`.
+const OPEN_URGENT_COUNT: &str = "COALESCE(SUM(CASE WHEN m.urgent = 1 AND m.urgent_state IN ('new', 'acknowledged') THEN 1 ELSE 0 END), 0)";
+
+/// Ordering of chats in the review report.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ReportSort {
+    #[default]
+    Name,      // alphabetical
+    Activity,  // most messages in the time window first
+    Urgency,   // most messages addressed to the account owner, then most urgent messages, in the time window first
+}
+
+/// Review report query: which chats to include and in what order.
+#[derive(Debug, Clone, Default)]
+pub struct ReportQuery {
+    pub filter: ChatFilter,
+    pub only_urgent: bool,      // only chats with urgent messages in the window
+    pub only_duplicates: bool,  // only chats flagged as duplicate topics
+    pub sort: ReportSort,
+}
+
+/// A chat with its activity in the report's time window.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatActivity {
+    #[serde(flatten)]
+    pub chat: ChatRecord,
+    pub message_count: i64,
+    pub urgent_count: i64,
+    pub mention_count: i64,  // messages addressed to the account owner
+    pub last_activity: Option<i32>,
+}
+
+/// Get the chats selected by a report query together with their message and
+/// urgent counts inside the query's time window. Filtering and sorting happen in SQL.
+pub async fn get_report_chats(conn: &Connection, query: &ReportQuery) -> Result<Vec<ChatActivity>, Box<dyn std::error::Error>> {
+    let mut sql = format!(
+        "SELECT c.peer_id, c.type, c.name, c.category, c.suggested_name, c.duplicate, \n                COUNT(m.id), {open} AS open_urgent, MAX(m.date), \n                c.name_decision, c.category_decision, {tags}, COALESCE(SUM(m.mentions_me), 0) AS mentions \n         FROM chats c \n         LEFT JOIN chat_messages m ON m.chat_peer = c.peer_id",
+        open = OPEN_URGENT_COUNT,
+        tags = chat_tags_column("c."),
+    );
+    let mut params: Vec<Value> = Vec::new();
+    // Time window restricts which messages are counted, not which chats exist
+    if let Some(since) = query.filter.since {
+        params.push(since.into());
+        sql += &format!(" AND m.date >= ?{}", params.len());
+    }
+    if let Some(until) = query.filter.until {
+        params.push(until.into());
+        sql += &format!(" AND m.date < ?{}", params.len());
+    }
+    sql += " WHERE 1 = 1";
+    push_chat_conditions(&query.filter, "c.", &mut sql, &mut params);
+    if query.only_duplicates {
+        sql += " AND c.duplicate = 1";
+    }
+    sql += " GROUP BY c.peer_id";
+    if query.only_urgent {
+        sql += " HAVING open_urgent > 0";
+    }
+    sql += match query.sort {
+        ReportSort::Name => " ORDER BY c.name COLLATE NOCASE;",
+        ReportSort::Activity => " ORDER BY COUNT(m.id) DESC, MAX(m.date) DESC, c.name COLLATE NOCASE;",
+        ReportSort::Urgency => " ORDER BY mentions DESC, open_urgent DESC, MAX(m.date) DESC, c.name COLLATE NOCASE;",
+    };
+
+    let mut stmt = conn.prepare(&sql).await?;
+    let mut rows = stmt.query(params).await?;
+    let mut chats = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let duplicate: i32 = row.get(5)?;
+        chats.push(ChatActivity {
+            chat: ChatRecord {
+                peer_id: row.get(0)?,
+                kind: row.get::<Option<String>>(1)?.unwrap_or_default(),
+                name: row.get(2)?,
+                category: row.get(3)?,
+                suggested_name: row.get(4)?,
+                duplicate: duplicate != 0,
+                name_decision: row.get(9)?,
+                category_decision: row.get(10)?,
+                tags: split_tags(row.get(11)?),
+            },
+            message_count: row.get(6)?,
+            urgent_count: row.get(7)?,
+            mention_count: row.get(12)?,
+            last_activity: row.get(8)?,
+        });
+    }
+    Ok(chats)
+}
+
+/// Get a chat's most recent urgent messages within the filter's time window,
+/// at most `limit` of them (all if `None`), returned oldest first.
+pub async fn get_urgent_snippets(conn: &Connection, chat_peer: &str, filter: &ChatFilter, limit: Option<usize>)
+    -> Result<Vec<MessageRecord>, Box<dyn std::error::Error>>
+{
+    let mut inner = String::from(
+        "SELECT chat_peer, msg_id, date, text, urgent FROM chat_messages \n         WHERE chat_peer = ?1 AND urgent = 1 AND urgent_state IN ('new', 'acknowledged')"
+    );
+    let mut params: Vec<Value> = vec![chat_peer.to_string().into()];
+    if let Some(since) = filter.since {
+        params.push(since.into());
+        inner += &format!(" AND date >= ?{}", params.len());
+    }
+    if let Some(until) = filter.until {
+        params.push(until.into());
+        inner += &format!(" AND date < ?{}", params.len());
+    }
+    inner += " ORDER BY date DESC";
+    if let Some(limit) = limit {
+        params.push((limit as i64).into());
+        inner += &format!(" LIMIT ?{}", params.len());
+    }
+    let sql = format!("SELECT * FROM ({}) ORDER BY date ASC;", inner);
+
+    let mut stmt = conn.prepare(&sql).await?;
+    let mut rows = stmt.query(params).await?;
+    let mut messages = Vec::new();
+    while let Some(row) = rows.next().await? {
+        messages.push(MessageRecord {
+            chat_peer: row.get(0)?,
+            msg_id: row.get(1)?,
+            date: row.get(2)?,
+            text: row.get::<Option<String>>(3)?.unwrap_or_default(),
+            urgent: true,
+            embedding: None,
+        });
+    }
+    Ok(messages)
+}
+
+/// Get a chat's messages within the filter's date range (and with its tag, unless the
+/// chat itself carries it), oldest first.
+/// With `urgent_only`, only messages flagged urgent are returned.
+pub async fn get_message_records(conn: &Connection, chat_peer: &str, filter: &ChatFilter, urgent_only: bool, with_embeddings: bool)
+    -> Result<Vec<MessageRecord>, Box<dyn std::error::Error>>
+{
+    let mut sql = format!(
+        "SELECT chat_peer, msg_id, date, text, urgent, {} FROM chat_messages m WHERE m.chat_peer = ?1",
+        if with_embeddings { "embedding" } else { "NULL" }
+    );
+    let mut params: Vec<Value> = vec![chat_peer.to_string().into()];
+    push_message_window(filter, &mut sql, &mut params);
+    if urgent_only {
+        sql += " AND urgent = 1";
+    }
+    sql += " ORDER BY msg_id ASC;";
+
+    let mut stmt = conn.prepare(&sql).await?;
+    let mut rows = stmt.query(params).await?;
+    let mut messages = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let urgent: i32 = row.get(4)?;
+        messages.push(MessageRecord {
+            chat_peer: row.get(0)?,
+            msg_id: row.get(1)?,
+            date: row.get(2)?,
+            text: row.get::<Option<String>>(3)?.unwrap_or_default(),
+            urgent: urgent != 0,
+            embedding: row.get(5)?,
+        });
+    }
+    Ok(messages)
+}
+
+/// True if a digest was already sent for the given period key.
+pub async fn digest_sent(conn: &Connection, period_key: &str) -> Result<bool, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("SELECT 1 FROM digests WHERE period_key = ?1;").await?;
+    let mut rows = stmt.query(&[&period_key]).await?;
+    Ok(rows.next().await?.is_some())
+}
+
+/// Get the duplicate chat peer ids recorded with the most recent digest.
+pub async fn get_last_digest_duplicates(conn: &Connection) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT duplicates FROM digests ORDER BY sent_at DESC LIMIT 1;"
+    ).await?;
+    let mut rows = stmt.query(&[]).await?;
+    if let Some(row) = rows.next().await? {
+        let json: Option<String> = row.get(0)?;
+        if let Some(json) = json {
+            return Ok(serde_json::from_str(&json)?);
+        }
+    }
+    Ok(Vec::new())
+}
+
+/// Record a sent digest, together with the duplicate chats known at that time.
+pub async fn record_digest(conn: &mut Connection, period_key: &str, period_start: i64, period_end: i64, content: &str, duplicates: &[String])
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let duplicates_json = serde_json::to_string(duplicates)?;
+    conn.execute(
+        "INSERT OR REPLACE INTO digests (period_key, period_start, period_end, content, duplicates, sent_at) \n         VALUES (?1, ?2, ?3, ?4, ?5, ?6);",
+        &[&period_key, &period_start, &period_end, &content, &duplicates_json, &Utc::now().timestamp()],
+    ).await?;
+    Ok(())
+}
+
+/// A message waiting to be delivered by a notification sink: flagged urgent, or
+/// matching a watchlist (`reason` then names the watchlists and terms).
+#[derive(Debug, Clone, Serialize)]
+pub struct PendingNotification {
+    pub chat_peer: String,
+    pub chat_name: String,
+    pub msg_id: i32,
+    pub date: i32,
+    pub text: String,
+    pub kind: String,  // "urgent" or "watch"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    pub mentions_me: bool,  // Addressed to the account owner
+}
+
+/// Get urgent messages flagged at or after `flagged_since`, and watchlist hits found since then
+/// on messages sent since then, that `sink` hasn't delivered yet, at most `limit`: messages
+/// addressed to the account owner first, then oldest first.
+/// A message is delivered once per sink even if it is both urgent and on a watchlist.
+pub async fn get_pending_notifications(conn: &Connection, sink: &str, flagged_since: i64, limit: i64)
+    -> Result<Vec<PendingNotification>, Box<dyn std::error::Error>>
+{
+    let mut stmt = conn.prepare(
+        "SELECT p.chat_peer, p.chat_name, p.msg_id, p.date, p.text, p.kind, p.reason, p.mentions_me FROM ( \n             SELECT m.chat_peer, c.name AS chat_name, m.msg_id, m.date, m.text, 'urgent' AS kind, NULL AS reason, m.urgent_at AS raised_at, m.mentions_me \n             FROM chat_messages m \n             JOIN chats c ON c.peer_id = m.chat_peer \n             WHERE m.urgent = 1 AND m.urgent_state = 'new' AND m.urgent_at >= ?1 \n             UNION ALL \n             SELECT m.chat_peer, c.name, m.msg_id, m.date, m.text, 'watch', GROUP_CONCAT(w.watchlist || ': ' || w.term, ', '), MIN(w.created_at), m.mentions_me \n             FROM watch_hits w \n             JOIN chat_messages m ON m.chat_peer = w.chat_peer AND m.msg_id = w.msg_id \n             JOIN chats c ON c.peer_id = w.chat_peer \n             WHERE w.notify = 1 AND w.created_at >= ?1 AND m.date >= ?1 \n               AND NOT (m.urgent = 1 AND m.urgent_state = 'new' AND m.urgent_at >= ?1) \n             GROUP BY w.chat_peer, w.msg_id \n         ) p \n         WHERE NOT EXISTS (SELECT 1 FROM notification_log n \n                           WHERE n.sink = ?2 AND n.chat_peer = p.chat_peer AND n.msg_id = p.msg_id) \n         ORDER BY p.mentions_me DESC, p.raised_at ASC, p.date ASC \n         LIMIT ?3;"
+    ).await?;
+    let mut rows = stmt.query(&[&flagged_since, &sink, &limit]).await?;
+    let mut pending = Vec::new();
+    while let Some(row) = rows.next().await? {
+        pending.push(PendingNotification {
+            chat_peer: row.get(0)?,
+            chat_name: row.get(1)?,
+            msg_id: row.get(2)?,
+            date: row.get(3)?,
+            text: row.get::<Option<String>>(4)?.unwrap_or_default(),
+            kind: row.get(5)?,
+            reason: row.get(6)?,
+            mentions_me: row.get::<i32>(7)? != 0,
+        });
+    }
+    Ok(pending)
+}
+
+/// Count notifications a sink has delivered since the given time (for rate limiting).
+pub async fn count_notifications_since(conn: &Connection, sink: &str, since: i64) -> Result<i64, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT COUNT(*) FROM notification_log WHERE sink = ?1 AND sent_at >= ?2;"
+    ).await?;
+    let mut rows = stmt.query(&[&sink, &since]).await?;
+    match rows.next().await? {
+        Some(row) => Ok(row.get(0)?),
+        None => Ok(0),
+    }
+}
+
+/// Record that a sink delivered a notification for a message, so it is never sent twice.
+pub async fn record_notification(conn: &mut Connection, sink: &str, chat_peer: &str, msg_id: i32) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "INSERT OR IGNORE INTO notification_log (sink, chat_peer, msg_id, sent_at) VALUES (?1, ?2, ?3, ?4);",
+        &[&sink, &chat_peer, &msg_id, &Utc::now().timestamp()],
+    ).await?;
+    Ok(())
+}
+
+/// Record the start of a monitoring cycle and return its run id.
+pub async fn start_run(conn: &mut Connection) -> Result<i64, Box<dyn std::error::Error>> {
+    conn.execute(
+        "INSERT INTO runs (started_at) VALUES (?1);",
+        &[&Utc::now().timestamp()],
+    ).await?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Record the outcome of a monitoring cycle.
+pub async fn finish_run(conn: &mut Connection, run_id: i64, chats: i64, messages_stored: i64, chats_analyzed: i64, status: &str)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    conn.execute(
+        "UPDATE runs SET finished_at = ?1, chats = ?2, messages_stored = ?3, chats_analyzed = ?4, status = ?5 WHERE id = ?6;",
+        &[&Utc::now().timestamp(), &chats, &messages_stored, &chats_analyzed, &status, &run_id],
+    ).await?;
+    Ok(())
+}
+
+/// One monitoring cycle from the run history.
+#[derive(Debug, Clone, Serialize)]
+pub struct RunRecord {
+    pub id: i64,
+    pub started_at: i64,
+    pub finished_at: Option<i64>,
+    pub chats: i64,
+    pub messages_stored: i64,
+    pub chats_analyzed: i64,
+    pub status: String,  // running, completed, interrupted or failed
+}
+
+/// Get the most recent monitoring cycles, newest first.
+pub async fn get_runs(conn: &Connection, limit: i64) -> Result<Vec<RunRecord>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, started_at, finished_at, chats, messages_stored, chats_analyzed, status \n         FROM runs ORDER BY id DESC LIMIT ?1;"
+    ).await?;
+    let mut rows = stmt.query(&[&limit]).await?;
+    let mut runs = Vec::new();
+    while let Some(row) = rows.next().await? {
+        runs.push(RunRecord {
+            id: row.get(0)?,
+            started_at: row.get(1)?,
+            finished_at: row.get(2)?,
+            chats: row.get(3)?,
+            messages_stored: row.get(4)?,
+            chats_analyzed: row.get(5)?,
+            status: row.get(6)?,
+        });
+    }
+    Ok(runs)
+}
+
+/// Get one page of a chat's messages, newest first, starting below `before_msg_id` if given.
+pub async fn get_message_page(conn: &Connection, chat_peer: &str, before_msg_id: Option<i32>, limit: i64)
+    -> Result<Vec<MessageRecord>, Box<dyn std::error::Error>>
+{
+    let before = before_msg_id.unwrap_or(i32::MAX);
+    let mut stmt = conn.prepare(
+        "SELECT chat_peer, msg_id, date, text, urgent FROM chat_messages \n         WHERE chat_peer = ?1 AND msg_id < ?2 \n         ORDER BY msg_id DESC LIMIT ?3;"
+    ).await?;
+    let mut rows = stmt.query(&[&chat_peer, &before, &limit]).await?;
+    let mut messages = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let urgent: i32 = row.get(4)?;
+        messages.push(MessageRecord {
+            chat_peer: row.get(0)?,
+            msg_id: row.get(1)?,
+            date: row.get(2)?,
+            text: row.get::<Option<String>>(3)?.unwrap_or_default(),
+            urgent: urgent != 0,
+            embedding: None,
+        });
+    }
+    Ok(messages)
+}
+
+/// A message returned by search or the urgent list, with the name of its chat.
+/// `distance` is the cosine distance to the query for semantic search.
+#[derive(Debug, Clone, Serialize)]
+pub struct MessageHit {
+    #[serde(flatten)]
+    pub message: MessageRecord,
+    pub chat_name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub urgent_state: Option<String>,     // review state of an urgent message (see UrgentState)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub urgent_state_by: Option<String>,  // who last changed the state
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub urgent_state_at: Option<i64>,     // when the state was last changed
+    pub mentions_me: bool,                // addressed to the account owner
+}
+
+/// Keyword search: messages whose text contains `query` (case-insensitive),
+/// restricted by the filter, newest first.
+pub async fn search_messages(conn: &Connection, query: &str, filter: &ChatFilter, limit: i64)
+    -> Result<Vec<MessageHit>, Box<dyn std::error::Error>>
+{
+    let mut params: Vec<Value> = vec![format!("%{}%", like_pattern(query)).into()];
+    let mut sql = String::from(
+        "SELECT m.chat_peer, m.msg_id, m.date, m.text, m.urgent, c.name, NULL, \n                m.urgent_state, m.urgent_state_by, m.urgent_state_at, m.mentions_me \n         FROM chat_messages m \n         JOIN chats c ON c.peer_id = m.chat_peer \n         WHERE m.text LIKE ?1 ESCAPE '\\'"
+    );
+    push_message_window(filter, &mut sql, &mut params);
+    push_chat_conditions(filter, "c.", &mut sql, &mut params);
+    params.push(limit.into());
+    sql += &format!(" ORDER BY m.date DESC LIMIT ?{};", params.len());
+    collect_hits(conn, &sql, params).await
+}
+
+/// Semantic search: the messages whose embeddings are nearest to `embedding`
+/// (via the vector index), restricted by the filter, closest first.
+pub async fn semantic_search(conn: &Connection, embedding: &[f32], filter: &ChatFilter, limit: i64)
+    -> Result<Vec<MessageHit>, Box<dyn std::error::Error>>
+{
+    let vector = serde_json::to_string(embedding)?;
+    // The index returns candidates before filtering; over-fetch so filtered searches still fill a page
+    let candidates = if filter.chat.is_some() || filter.chat_exact.is_some() || filter.category.is_some() || filter.tag.is_some()
+        || filter.since.is_some() || filter.until.is_some()
+    {
+        limit * 10
+    } else {
+        limit
+    };
+    let mut params: Vec<Value> = vec![vector.into(), candidates.into()];
+    let mut sql = String::from(
+        "SELECT m.chat_peer, m.msg_id, m.date, m.text, m.urgent, c.name, \n                vector_distance_cos(m.embedding, vector32(?1)) AS distance, \n                m.urgent_state, m.urgent_state_by, m.urgent_state_at, m.mentions_me \n         FROM vector_top_k('idx_chat_messages_embedding', vector32(?1), ?2) v \n         JOIN chat_messages m ON m.rowid = v.id \n         JOIN chats c ON c.peer_id = m.chat_peer \n         WHERE 1 = 1"
+    );
+    push_message_window(filter, &mut sql, &mut params);
+    push_chat_conditions(filter, "c.", &mut sql, &mut params);
+    params.push(limit.into());
+    sql += &format!(" ORDER BY distance ASC LIMIT ?{};", params.len());
+    collect_hits(conn, &sql, params).await
+}
+
+/// Urgent messages in one of the given states across all chats within the filter,
+/// those addressed to the account owner first, then newest first.
+pub async fn get_urgent_messages(conn: &Connection, filter: &ChatFilter, states: &[UrgentState], limit: i64)
+    -> Result<Vec<MessageHit>, Box<dyn std::error::Error>>
+{
+    let mut params: Vec<Value> = Vec::new();
+    let mut sql = String::from(
+        "SELECT m.chat_peer, m.msg_id, m.date, m.text, m.urgent, c.name, NULL, \n                m.urgent_state, m.urgent_state_by, m.urgent_state_at, m.mentions_me \n         FROM chat_messages m \n         JOIN chats c ON c.peer_id = m.chat_peer \n         WHERE m.urgent = 1"
+    );
+    let placeholders: Vec<String> = states.iter().map(|state| {
+        params.push(state.as_str().into());
+        format!("?{}", params.len())
+    }).collect();
+    sql += &format!(" AND m.urgent_state IN ({})", placeholders.join(", "));
+    push_message_window(filter, &mut sql, &mut params);
+    push_chat_conditions(filter, "c.", &mut sql, &mut params);
+    params.push(limit.into());
+    sql += &format!(" ORDER BY m.mentions_me DESC, m.date DESC LIMIT ?{};", params.len());
+    collect_hits(conn, &sql, params).await
+}
+
+/// Messages addressed to the account owner (mentions and replies to their messages)
+/// across all chats within the filter, newest first.
+pub async fn get_mentions(conn: &Connection, filter: &ChatFilter, limit: i64)
+    -> Result<Vec<MessageHit>, Box<dyn std::error::Error>>
+{
+    let mut params: Vec<Value> = Vec::new();
+    let mut sql = String::from(
+        "SELECT m.chat_peer, m.msg_id, m.date, m.text, m.urgent, c.name, NULL, \n                m.urgent_state, m.urgent_state_by, m.urgent_state_at, m.mentions_me \n         FROM chat_messages m \n         JOIN chats c ON c.peer_id = m.chat_peer \n         WHERE m.mentions_me = 1"
+    );
+    push_message_window(filter, &mut sql, &mut params);
+    push_chat_conditions(filter, "c.", &mut sql, &mut params);
+    params.push(limit.into());
+    sql += &format!(" ORDER BY m.date DESC LIMIT ?{};", params.len());
+    collect_hits(conn, &sql, params).await
+}
+
+/// Helper: append the filter's time window as conditions on `m.date`.
+fn push_message_window(filter: &ChatFilter, sql: &mut String, params: &mut Vec<Value>) {
+    if let Some(since) = filter.since {
+        params.push(since.into());
+        *sql += &format!(" AND m.date >= ?{}", params.len());
+    }
+    if let Some(until) = filter.until {
+        params.push(until.into());
+        *sql += &format!(" AND m.date < ?{}", params.len());
+    }
+    // A tagged chat matches all its messages; otherwise the message itself must carry the tag
+    if let Some(tag) = &filter.tag {
+        params.push(tag.clone().into());
+        *sql += &format!(
+            " AND (m.chat_peer IN (SELECT ct.chat_peer FROM chat_tags ct JOIN tags t ON t.id = ct.tag_id \n                 WHERE t.name = ?{p} AND ct.source != 'removed') \n             OR EXISTS (SELECT 1 FROM message_tags mt JOIN tags t ON t.id = mt.tag_id \n                 WHERE mt.chat_peer = m.chat_peer AND mt.msg_id = m.msg_id AND t.name = ?{p} AND mt.source != 'removed'))",
+            p = params.len(),
+        );
+    }
+}
+
+/// Helper: run a query selecting (chat_peer, msg_id, date, text, urgent, chat name, distance,
+/// urgent_state, urgent_state_by, urgent_state_at).
+async fn collect_hits(conn: &Connection, sql: &str, params: Vec<Value>) -> Result<Vec<MessageHit>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(sql).await?;
+    let mut rows = stmt.query(params).await?;
+    let mut hits = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let urgent: i32 = row.get(4)?;
+        hits.push(MessageHit {
+            message: MessageRecord {
+                chat_peer: row.get(0)?,
+                msg_id: row.get(1)?,
+                date: row.get(2)?,
+                text: row.get::<Option<String>>(3)?.unwrap_or_default(),
+                urgent: urgent != 0,
+                embedding: None,
+            },
+            chat_name: row.get(5)?,
+            distance: row.get(6)?,
+            urgent_state: row.get(7)?,
+            urgent_state_by: row.get(8)?,
+            urgent_state_at: row.get(9)?,
+            mentions_me: row.get::<i32>(10)? != 0,
+        });
+    }
+    Ok(hits)
+}
+
+/// Change the review state of an urgent message, recording who did it and when.
+/// Marking a message as a false positive is also stored as feedback for the analyzer.
+/// Returns false if there is no urgent message with that id in the chat.
+pub async fn set_urgent_state(conn: &mut Connection, chat_peer: &str, msg_id: i32, state: UrgentState, by: &str)
+    -> Result<bool, Box<dyn std::error::Error>>
+{
+    let mut tx = conn.transaction().await?;
+    let changed = tx.execute(
+        "UPDATE chat_messages SET urgent_state = ?1, urgent_state_by = ?2, urgent_state_at = ?3 \n         WHERE chat_peer = ?4 AND msg_id = ?5 AND urgent = 1;",
+        &[&state.as_str(), &by, &Utc::now().timestamp(), &chat_peer, &msg_id],
+    ).await?;
+    if changed > 0 && state == UrgentState::FalsePositive {
+        tx.execute(
+            "INSERT INTO feedback (chat_peer, msg_id, kind, created_by, created_at) VALUES (?1, ?2, ?3, ?4, ?5);",
+            &[&chat_peer, &msg_id, &FeedbackKind::UrgentFalsePositive.as_str(), &by, &Utc::now().timestamp()],
+        ).await?;
+    }
+    tx.commit().await?;
+    Ok(changed > 0)
+}
+
+/// Kind of correction stored in the feedback table.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeedbackKind {
+    CategoryOverride,     // the chat belongs in `value` (the model said `previous`)
+    UrgentFalsePositive,  // the message was flagged urgent but isn't
+    UrgentFalseNegative,  // the message is urgent but wasn't flagged
+}
+
+impl FeedbackKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FeedbackKind::CategoryOverride => "category_override",
+            FeedbackKind::UrgentFalsePositive => "urgent_false_positive",
+            FeedbackKind::UrgentFalseNegative => "urgent_false_negative",
+        }
+    }
+
+    fn from_str(value: &str) -> Option<Self> {
+        match value {
+            "category_override" => Some(FeedbackKind::CategoryOverride),
+            "urgent_false_positive" => Some(FeedbackKind::UrgentFalsePositive),
+            "urgent_false_negative" => Some(FeedbackKind::UrgentFalseNegative),
+            _ => None,
+        }
+    }
+}
+
+/// A stored correction, with the chat name and message text it refers to.
+#[derive(Debug, Clone, Serialize)]
+pub struct Feedback {
+    pub id: i64,
+    pub chat_peer: String,
+    pub chat_name: String,
+    pub msg_id: Option<i32>,
+    #[serde(serialize_with = "serialize_feedback_kind")]
+    pub kind: FeedbackKind,
+    pub value: Option<String>,
+    pub previous: Option<String>,
+    pub message_text: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: i64,
+}
+
+fn serialize_feedback_kind<S: serde::Serializer>(kind: &FeedbackKind, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(kind.as_str())
+}
+
+/// Override a chat's category. The override sticks: later analyses keep it until it is cleared.
+pub async fn override_category(conn: &mut Connection, chat_peer: &str, category: &str, by: &str)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let mut tx = conn.transaction().await?;
+    let mut stmt = tx.prepare("SELECT category FROM chats WHERE peer_id = ?1;").await?;
+    let mut rows = stmt.query(&[&chat_peer]).await?;
+    let previous: Option<String> = match rows.next().await? {
+        Some(row) => row.get(0)?,
+        None => return Err(format!("No chat with peer id \"{}\"", chat_peer).into()),
+    };
+    tx.execute(
+        "UPDATE chats SET category = ?1, category_override = ?1, category_decision = 'accepted' WHERE peer_id = ?2;",
+        &[&category, &chat_peer],
+    ).await?;
+    tx.execute(
+        "INSERT INTO feedback (chat_peer, kind, value, previous, created_by, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6);",
+        &[&chat_peer, &FeedbackKind::CategoryOverride.as_str(), &category, &previous, &by, &Utc::now().timestamp()],
+    ).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Remove a chat's category override; the rule's or else the model's category applies again.
+pub async fn clear_category_override(conn: &mut Connection, chat_peer: &str) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute(
+        "UPDATE chats SET category_override = NULL, category = COALESCE(rule_category, ai_category) WHERE peer_id = ?1;",
+        &[&chat_peer],
+    ).await?;
+    Ok(())
+}
+
+/// Flag a message the model missed as urgent and record the correction.
+/// Returns false if the message doesn't exist or is already urgent.
+pub async fn mark_missed_urgent(conn: &mut Connection, chat_peer: &str, msg_id: i32, by: &str)
+    -> Result<bool, Box<dyn std::error::Error>>
+{
+    let now = Utc::now().timestamp();
+    let mut tx = conn.transaction().await?;
+    let changed = tx.execute(
+        "UPDATE chat_messages SET urgent = 1, urgent_at = ?1, urgent_state = 'new', urgent_state_by = ?2, urgent_state_at = ?1, \n            urgent_source = 'manual' \n         WHERE chat_peer = ?3 AND msg_id = ?4 AND urgent = 0;",
+        &[&now, &by, &chat_peer, &msg_id],
+    ).await?;
+    if changed > 0 {
+        tx.execute(
+            "INSERT INTO feedback (chat_peer, msg_id, kind, created_by, created_at) VALUES (?1, ?2, ?3, ?4, ?5);",
+            &[&chat_peer, &msg_id, &FeedbackKind::UrgentFalseNegative.as_str(), &by, &now],
+        ).await?;
+    }
+    tx.commit().await?;
+    Ok(changed > 0)
+}
+
+/// Get the most recent corrections, newest first.
+pub async fn get_recent_feedback(conn: &Connection, limit: i64) -> Result<Vec<Feedback>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT f.id, f.chat_peer, COALESCE(c.name, f.chat_peer), f.msg_id, f.kind, f.value, f.previous, m.text, f.created_by, f.created_at \n         FROM feedback f \n         LEFT JOIN chats c ON c.peer_id = f.chat_peer \n         LEFT JOIN chat_messages m ON m.chat_peer = f.chat_peer AND m.msg_id = f.msg_id \n         ORDER BY f.created_at DESC, f.id DESC LIMIT ?1;"
+    ).await?;
+    let mut rows = stmt.query(&[&limit]).await?;
+    let mut feedback = Vec::new();
+    while let Some(row) = rows.next().await? {
+        let kind: String = row.get(4)?;
+        let Some(kind) = FeedbackKind::from_str(&kind) else {
+            continue;
+        };
+        feedback.push(Feedback {
+            id: row.get(0)?,
+            chat_peer: row.get(1)?,
+            chat_name: row.get(2)?,
+            msg_id: row.get(3)?,
+            kind,
+            value: row.get(5)?,
+            previous: row.get(6)?,
+            message_text: row.get(7)?,
+            created_by: row.get(8)?,
+            created_at: row.get(9)?,
+        });
+    }
+    Ok(feedback)
+}
+
+/// Number of chats per stored category (None for chats not analyzed yet).
+pub async fn get_category_counts(conn: &Connection) -> Result<Vec<(Option<String>, i64)>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT category, COUNT(*) FROM chats GROUP BY category ORDER BY category;"
+    ).await?;
+    let mut rows = stmt.query(&[]).await?;
+    let mut counts = Vec::new();
+    while let Some(row) = rows.next().await? {
+        counts.push((row.get(0)?, row.get(1)?));
+    }
+    Ok(counts)
+}
+
+/// Rename a category everywhere it is stored: chat categories, overrides and the
+/// category corrections used as prompt examples.
+pub async fn rename_category(conn: &mut Connection, from: &str, to: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let mut tx = conn.transaction().await?;
+    tx.execute("UPDATE chats SET category = ?2 WHERE category = ?1;", &[&from, &to]).await?;
+    tx.execute("UPDATE chats SET category_override = ?2 WHERE category_override = ?1;", &[&from, &to]).await?;
+    tx.execute("UPDATE chats SET ai_category = ?2 WHERE ai_category = ?1;", &[&from, &to]).await?;
+    tx.execute(
+        "UPDATE feedback SET value = ?3 WHERE kind = ?1 AND value = ?2;",
+        &[&FeedbackKind::CategoryOverride.as_str(), &from, &to],
+    ).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Where a tag on a chat or message came from. A removed tag stays recorded so the
+/// analyzer doesn't propose it again.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TagSource {
+    Ai,
+    Manual,
+    Rule,
+    Removed,
+}
+
+impl TagSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TagSource::Ai => "ai",
+            TagSource::Manual => "manual",
+            TagSource::Rule => "rule",
+            TagSource::Removed => "removed",
+        }
+    }
+}
+
+/// A tag on a chat, or on one of its messages if `msg_id` is set.
+#[derive(Debug, Clone, Serialize)]
+pub struct TagRecord {
+    pub name: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub msg_id: Option<i32>,
+    pub source: String,
+    pub confidence: Option<f64>,
+}
+
+/// Number of chats and messages carrying a tag.
+#[derive(Debug, Clone, Serialize)]
+pub struct TagCount {
+    pub name: String,
+    pub chats: i64,
+    pub messages: i64,
+}
+
+/// Helper: id of the tag named `name` (case-insensitive), creating it if needed.
+async fn tag_id(tx: &libsql::Transaction, name: &str) -> Result<i64, Box<dyn std::error::Error>> {
+    tx.execute("INSERT INTO tags (name) VALUES (?1) ON CONFLICT(name) DO NOTHING;", &[&name]).await?;
+    let mut stmt = tx.prepare("SELECT id FROM tags WHERE name = ?1;").await?;
+    let mut rows = stmt.query(&[&name]).await?;
+    match rows.next().await? {
+        Some(row) => Ok(row.get(0)?),
+        None => Err(format!("Tag \"{}\" could not be created", name).into()),
+    }
+}
+
+/// Replace the analyzer's tags on a chat and add its tags on individual messages.
+/// Manual and removed tags are left alone.
+pub async fn store_ai_tags(conn: &mut Connection, chat_peer: &str, chat_tags: &[(String, f64)], message_tags: &[(i32, String, f64)])
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let now = Utc::now().timestamp();
+    let mut tx = conn.transaction().await?;
+    tx.execute("DELETE FROM chat_tags WHERE chat_peer = ?1 AND source = 'ai';", &[&chat_peer]).await?;
+    for (name, confidence) in chat_tags {
+        let id = tag_id(&tx, name).await?;
+        tx.execute(
+            "INSERT INTO chat_tags (chat_peer, tag_id, source, confidence, created_at) VALUES (?1, ?2, 'ai', ?3, ?4) \n             ON CONFLICT(chat_peer, tag_id) DO NOTHING;",
+            &[&chat_peer, &id, confidence, &now],
+        ).await?;
+    }
+    for (msg_id, name, confidence) in message_tags {
+        let id = tag_id(&tx, name).await?;
+        tx.execute(
+            "INSERT INTO message_tags (chat_peer, msg_id, tag_id, source, confidence, created_at) VALUES (?1, ?2, ?3, 'ai', ?4, ?5) \n             ON CONFLICT(chat_peer, msg_id, tag_id) DO UPDATE SET confidence = excluded.confidence WHERE source = 'ai';",
+            &[&chat_peer, msg_id, &id, confidence, &now],
+        ).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Tag a chat (or, with `msg_id`, one of its messages) by hand, or mark the tag removed.
+/// Returns false when removing a tag that isn't there.
+pub async fn set_tag(conn: &mut Connection, chat_peer: &str, msg_id: Option<i32>, name: &str, source: TagSource)
+    -> Result<bool, Box<dyn std::error::Error>>
+{
+    let now = Utc::now().timestamp();
+    let confidence = (source == TagSource::Manual).then_some(1.0);
+    let mut tx = conn.transaction().await?;
+    if source == TagSource::Removed {
+        let mut stmt = tx.prepare(match msg_id {
+            None => "SELECT 1 FROM chat_tags ct JOIN tags t ON t.id = ct.tag_id \n                     WHERE ct.chat_peer = ?1 AND t.name = ?2 AND ct.source != 'removed';",
+            Some(_) => "SELECT 1 FROM message_tags mt JOIN tags t ON t.id = mt.tag_id \n                        WHERE mt.chat_peer = ?1 AND t.name = ?2 AND mt.msg_id = ?3 AND mt.source != 'removed';",
+        }).await?;
+        let mut rows = match msg_id {
+            None => stmt.query(&[&chat_peer, &name]).await?,
+            Some(msg_id) => stmt.query(&[&chat_peer, &name, &msg_id]).await?,
+        };
+        if rows.next().await?.is_none() {
+            return Ok(false);
+        }
+    }
+    let id = tag_id(&tx, name).await?;
+    match msg_id {
+        None => tx.execute(
+            "INSERT INTO chat_tags (chat_peer, tag_id, source, confidence, created_at) VALUES (?1, ?2, ?3, ?4, ?5) \n             ON CONFLICT(chat_peer, tag_id) DO UPDATE SET source = excluded.source, confidence = excluded.confidence, \n                created_at = excluded.created_at;",
+            &[&chat_peer, &id, &source.as_str(), &confidence, &now],
+        ).await?,
+        Some(msg_id) => tx.execute(
+            "INSERT INTO message_tags (chat_peer, msg_id, tag_id, source, confidence, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6) \n             ON CONFLICT(chat_peer, msg_id, tag_id) DO UPDATE SET source = excluded.source, confidence = excluded.confidence, \n                created_at = excluded.created_at;",
+            &[&chat_peer, &msg_id, &id, &source.as_str(), &confidence, &now],
+        ).await?,
+    };
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// A chat's tags followed by its message tags (newest message first), without removed ones.
+pub async fn get_tags_for_chat(conn: &Connection, chat_peer: &str) -> Result<Vec<TagRecord>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.name, NULL, ct.source, ct.confidence FROM chat_tags ct JOIN tags t ON t.id = ct.tag_id \n         WHERE ct.chat_peer = ?1 AND ct.source != 'removed' \n         UNION ALL \n         SELECT t.name, mt.msg_id, mt.source, mt.confidence FROM message_tags mt JOIN tags t ON t.id = mt.tag_id \n         WHERE mt.chat_peer = ?1 AND mt.source != 'removed' \n         ORDER BY 2 IS NOT NULL, 2 DESC, 1 COLLATE NOCASE;"
+    ).await?;
+    let mut rows = stmt.query(&[&chat_peer]).await?;
+    let mut tags = Vec::new();
+    while let Some(row) = rows.next().await? {
+        tags.push(TagRecord {
+            name: row.get(0)?,
+            msg_id: row.get(1)?,
+            source: row.get(2)?,
+            confidence: row.get(3)?,
+        });
+    }
+    Ok(tags)
+}
+
+/// Every tag in use with the number of chats and messages carrying it, most used first.
+pub async fn get_tag_counts(conn: &Connection) -> Result<Vec<TagCount>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT t.name, \n                (SELECT COUNT(*) FROM chat_tags ct WHERE ct.tag_id = t.id AND ct.source != 'removed') AS chats, \n                (SELECT COUNT(*) FROM message_tags mt WHERE mt.tag_id = t.id AND mt.source != 'removed') AS messages \n         FROM tags t \n         WHERE chats + messages > 0 \n         ORDER BY chats + messages DESC, t.name COLLATE NOCASE;"
+    ).await?;
+    let mut rows = stmt.query(&[]).await?;
+    let mut counts = Vec::new();
+    while let Some(row) = rows.next().await? {
+        counts.push(TagCount { name: row.get(0)?, chats: row.get(1)?, messages: row.get(2)? });
+    }
+    Ok(counts)
+}
+
+/// A change made to Telegram by `apply`, with what is needed to revert it:
+/// the folder as it was before (`before_blob`, None if it was created) or the old chat title.
+#[derive(Debug, Clone)]
+pub struct ApplyLogEntry {
+    pub id: i64,
+    pub batch: i64,
+    pub applied_at: i64,
+    pub kind: String,    // "folder" or "rename"
+    pub target: String,  // folder id or chat peer id
+    pub label: String,   // human-readable description
+    pub before_text: Option<String>,
+    pub before_blob: Option<Vec<u8>>,
+}
+
+/// Number for the next batch of applied changes.
+pub async fn next_apply_batch(conn: &Connection) -> Result<i64, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("SELECT COALESCE(MAX(batch), 0) + 1 FROM apply_log;").await?;
+    let mut rows = stmt.query(&[]).await?;
+    match rows.next().await? {
+        Some(row) => Ok(row.get(0)?),
+        None => Ok(1),
+    }
+}
+
+/// Record one applied change in the undo log.
+pub async fn log_apply(conn: &mut Connection, batch: i64, kind: &str, target: &str, label: &str,
+    before_text: Option<&str>, before_blob: Option<Vec<u8>>) -> Result<(), Box<dyn std::error::Error>>
+{
+    conn.execute(
+        "INSERT INTO apply_log (batch, applied_at, kind, target, label, before_text, before_blob) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);",
+        &[&batch, &Utc::now().timestamp(), &kind, &target, &label, &before_text, &before_blob],
+    ).await?;
+    Ok(())
+}
+
+/// Changes of a batch that haven't been undone, in the order they were applied.
+/// Without `batch`, the most recent batch with changes left to undo.
+pub async fn get_apply_batch(conn: &Connection, batch: Option<i64>) -> Result<Vec<ApplyLogEntry>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT id, batch, applied_at, kind, target, label, before_text, before_blob FROM apply_log \n         WHERE undone_at IS NULL AND batch = COALESCE(?1, (SELECT MAX(batch) FROM apply_log WHERE undone_at IS NULL)) \n         ORDER BY id ASC;"
+    ).await?;
+    let mut rows = stmt.query(&[&batch]).await?;
+    let mut entries = Vec::new();
+    while let Some(row) = rows.next().await? {
+        entries.push(ApplyLogEntry {
+            id: row.get(0)?,
+            batch: row.get(1)?,
+            applied_at: row.get(2)?,
+            kind: row.get(3)?,
+            target: row.get(4)?,
+            label: row.get(5)?,
+            before_text: row.get(6)?,
+            before_blob: row.get(7)?,
+        });
+    }
+    Ok(entries)
+}
+
+/// Mark one logged change as undone.
+pub async fn mark_apply_undone(conn: &mut Connection, id: i64) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute("UPDATE apply_log SET undone_at = ?1 WHERE id = ?2;", &[&Utc::now().timestamp(), &id]).await?;
+    Ok(())
+}
+
+/// Store a chat's new title right away (the monitor would pick it up on its next cycle).
+pub async fn set_chat_name(conn: &mut Connection, chat_peer: &str, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute("UPDATE chats SET name = ?1 WHERE peer_id = ?2;", &[&name, &chat_peer]).await?;
+    Ok(())
+}
+
+/// Record that a chat was moved into or out of the Archive folder.
+pub async fn set_chat_archived(conn: &mut Connection, chat_peer: &str, archived: bool) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute("UPDATE chats SET archived = ?1 WHERE peer_id = ?2;", &[&(archived as i32), &chat_peer]).await?;
+    Ok(())
+}
+
+/// Record that a chat's notifications were muted or unmuted.
+pub async fn set_chat_muted(conn: &mut Connection, chat_peer: &str, muted: bool) -> Result<(), Box<dyn std::error::Error>> {
+    conn.execute("UPDATE chats SET muted = ?1 WHERE peer_id = ?2;", &[&(muted as i32), &chat_peer]).await?;
+    Ok(())
+}
+
+/// What the rules look at for a chat: its title, type, category, tags and latest message.
+#[derive(Debug, Clone)]
+pub struct RuleChat {
+    pub peer_id: String,
+    pub kind: String,
+    pub name: String,
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    pub last_message_at: Option<i64>,
+}
+
+/// Get the chats matching the filter's chat, category and tag conditions for rule evaluation.
+pub async fn get_rule_chats(conn: &Connection, filter: &ChatFilter) -> Result<Vec<RuleChat>, Box<dyn std::error::Error>> {
+    let mut sql = format!(
+        "SELECT peer_id, type, name, category, {}, \n                (SELECT MAX(date) FROM chat_messages WHERE chat_peer = peer_id) \n         FROM chats WHERE 1 = 1",
+        chat_tags_column(""),
+    );
+    let mut params: Vec<Value> = Vec::new();
+    push_chat_conditions(filter, "", &mut sql, &mut params);
+    sql += " ORDER BY name COLLATE NOCASE;";
+
+    let mut stmt = conn.prepare(&sql).await?;
+    let mut rows = stmt.query(params).await?;
+    let mut chats = Vec::new();
+    while let Some(row) = rows.next().await? {
+        chats.push(RuleChat {
+            peer_id: row.get(0)?,
+            kind: row.get::<Option<String>>(1)?.unwrap_or_default(),
+            name: row.get(2)?,
+            category: row.get(3)?,
+            tags: split_tags(row.get(4)?),
+            last_message_at: row.get(5)?,
+        });
+    }
+    Ok(chats)
+}
+
+/// Get a chat's most recent messages within the filter's time window (and with its tag, unless
+/// the chat itself carries it), at most `limit` of them, newest first.
+pub async fn get_rule_messages(conn: &Connection, chat_peer: &str, filter: &ChatFilter, limit: i64)
+    -> Result<Vec<MessageInfo>, Box<dyn std::error::Error>>
+{
+    let mut sql = "SELECT msg_id, date, text, sender_id, outgoing, reply_to, mentions_me FROM chat_messages m WHERE m.chat_peer = ?1".to_string();
+    let mut params: Vec<Value> = vec![chat_peer.to_string().into()];
+    push_message_window(filter, &mut sql, &mut params);
+    params.push(limit.into());
+    sql += &format!(" ORDER BY msg_id DESC LIMIT ?{};", params.len());
+
+    let mut stmt = conn.prepare(&sql).await?;
+    let mut rows = stmt.query(params).await?;
+    let mut messages = Vec::new();
+    while let Some(row) = rows.next().await? {
+        messages.push(MessageInfo {
+            msg_id: row.get(0)?,
+            date: row.get(1)?,
+            text: row.get::<Option<String>>(2)?.unwrap_or_default(),
+            sender_id: row.get(3)?,
+            outgoing: row.get::<i32>(4)? != 0,
+            reply_to: row.get(5)?,
+            mentions_me: row.get::<i32>(6)? != 0,
+        });
+    }
+    Ok(messages)
+}
+
+/// Ids of the members of a chat given by peer id or exact name (case-insensitive).
+pub async fn get_group_member_ids(conn: &Connection, chat: &str) -> Result<HashSet<i64>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT cm.user_id FROM chat_members cm JOIN chats c ON c.peer_id = cm.chat_peer \n         WHERE c.peer_id = ?1 OR c.name = ?1 COLLATE NOCASE;"
+    ).await?;
+    let mut rows = stmt.query(&[&chat]).await?;
+    let mut ids = HashSet::new();
+    while let Some(row) = rows.next().await? {
+        ids.insert(row.get(0)?);
+    }
+    Ok(ids)
+}
+
+/// Id of the user with the given username (without `@`, case-insensitive), if known.
+pub async fn find_user_by_username(conn: &Connection, username: &str) -> Result<Option<i64>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare("SELECT user_id FROM users WHERE username = ?1 COLLATE NOCASE LIMIT 1;").await?;
+    let mut rows = stmt.query(&[&username]).await?;
+    match rows.next().await? {
+        Some(row) => Ok(Some(row.get(0)?)),
+        None => Ok(None),
+    }
+}
+
+/// Store what the chat-level rules decided for a chat: the category they set (None clears it)
+/// and their tags, replacing the previous rule tags. Categories and tags set by hand win.
+pub async fn set_rule_results(conn: &mut Connection, chat_peer: &str, category: Option<&str>, tags: &[String])
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let now = Utc::now().timestamp();
+    let mut tx = conn.transaction().await?;
+    tx.execute(
+        "UPDATE chats SET rule_category = ?1, category = COALESCE(category_override, ?1, ai_category) WHERE peer_id = ?2;",
+        &[&category, &chat_peer],
+    ).await?;
+    tx.execute("DELETE FROM chat_tags WHERE chat_peer = ?1 AND source = 'rule';", &[&chat_peer]).await?;
+    for name in tags {
+        let id = tag_id(&tx, name).await?;
+        tx.execute(
+            "INSERT INTO chat_tags (chat_peer, tag_id, source, confidence, created_at) VALUES (?1, ?2, 'rule', 1.0, ?3) \n             ON CONFLICT(chat_peer, tag_id) DO NOTHING;",
+            &[&chat_peer, &id, &now],
+        ).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Tag messages on behalf of the rules. Tags already on a message (of any source) are kept.
+pub async fn add_rule_message_tags(conn: &mut Connection, chat_peer: &str, message_tags: &[(i32, String)])
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    if message_tags.is_empty() {
+        return Ok(());
+    }
+    let now = Utc::now().timestamp();
+    let mut tx = conn.transaction().await?;
+    for (msg_id, name) in message_tags {
+        let id = tag_id(&tx, name).await?;
+        tx.execute(
+            "INSERT INTO message_tags (chat_peer, msg_id, tag_id, source, confidence, created_at) VALUES (?1, ?2, ?3, 'rule', 1.0, ?4) \n             ON CONFLICT(chat_peer, msg_id, tag_id) DO NOTHING;",
+            &[&chat_peer, msg_id, &id, &now],
+        ).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// How often a rule matched and where it last did.
+#[derive(Debug, Clone)]
+pub struct RuleStats {
+    pub rule: String,
+    pub hits: i64,
+    pub last_hit_at: Option<i64>,
+    pub last_chat_peer: Option<String>,
+    pub last_msg_id: Option<i32>,
+}
+
+/// Count rule matches in a chat: one `(rule, msg_id)` pair per match, `msg_id` None for chat rules.
+/// Chat rules are evaluated every cycle, so `rule_matches` remembers what already counted: a
+/// message rule counts once per message, a chat rule once each time it starts matching the chat
+/// (chat rules that no longer match are forgotten here).
+pub async fn record_rule_hits(conn: &mut Connection, chat_peer: &str, hits: &[(String, Option<i32>)])
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let now = Utc::now().timestamp();
+    let mut tx = conn.transaction().await?;
+    let mut matching_before = Vec::new();
+    {
+        let mut stmt = tx.prepare("SELECT rule FROM rule_matches WHERE chat_peer = ?1 AND msg_id = 0;").await?;
+        let mut rows = stmt.query(&[&chat_peer]).await?;
+        while let Some(row) = rows.next().await? {
+            matching_before.push(row.get::<String>(0)?);
+        }
+    }
+    for rule in &matching_before {
+        if !hits.iter().any(|(r, msg_id)| r == rule && msg_id.is_none()) {
+            tx.execute(
+                "DELETE FROM rule_matches WHERE rule = ?1 AND chat_peer = ?2 AND msg_id = 0;",
+                &[rule, &chat_peer],
+            ).await?;
+        }
+    }
+    for (rule, msg_id) in hits {
+        let is_new = tx.execute(
+            "INSERT OR IGNORE INTO rule_matches (rule, chat_peer, msg_id) VALUES (?1, ?2, ?3);",
+            &[rule, &chat_peer, &msg_id.unwrap_or(0)],
+        ).await? > 0;
+        if !is_new {
+            continue;
+        }
+        tx.execute(
+            "INSERT INTO rule_hits (rule, hits, last_hit_at, last_chat_peer, last_msg_id) VALUES (?1, 1, ?2, ?3, ?4) \n             ON CONFLICT(rule) DO UPDATE SET hits = hits + 1, last_hit_at = excluded.last_hit_at, \n                last_chat_peer = excluded.last_chat_peer, last_msg_id = excluded.last_msg_id;",
+            &[rule, &now, &chat_peer, msg_id],
+        ).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Hit statistics of every rule that matched at least once.
+pub async fn get_rule_stats(conn: &Connection) -> Result<Vec<RuleStats>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT rule, hits, last_hit_at, last_chat_peer, last_msg_id FROM rule_hits ORDER BY rule;"
+    ).await?;
+    let mut rows = stmt.query(&[]).await?;
+    let mut stats = Vec::new();
+    while let Some(row) = rows.next().await? {
+        stats.push(RuleStats {
+            rule: row.get(0)?,
+            hits: row.get(1)?,
+            last_hit_at: row.get(2)?,
+            last_chat_peer: row.get(3)?,
+            last_msg_id: row.get(4)?,
+        });
+    }
+    Ok(stats)
+}
+
+/// A stored watchlist match with its message and chat.
+#[derive(Debug, Clone, Serialize)]
+pub struct WatchHitRecord {
+    pub chat_peer: String,
+    pub chat_name: String,
+    pub msg_id: i32,
+    pub date: i32,
+    pub text: String,
+    pub watchlist: String,
+    pub term: String,     // the term or pattern as configured
+    pub matched: String,  // the text it matched
+    pub created_at: i64,
+}
+
+/// Store watchlist matches for a chat's messages: `(msg_id, watchlist, term, matched, notify)`.
+/// Matches already stored are ignored. Returns the number of new matches to notify about.
+pub async fn store_watch_hits(conn: &mut Connection, chat_peer: &str, hits: &[(i32, String, String, String, bool)])
+    -> Result<usize, Box<dyn std::error::Error>>
+{
+    if hits.is_empty() {
+        return Ok(0);
+    }
+    let now = Utc::now().timestamp();
+    let mut to_notify = 0;
+    let mut tx = conn.transaction().await?;
+    for (msg_id, watchlist, term, matched, notify) in hits {
+        let inserted = tx.execute(
+            "INSERT OR IGNORE INTO watch_hits (chat_peer, msg_id, watchlist, term, matched, notify, created_at) \n             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7);",
+            &[&chat_peer, msg_id, watchlist, term, matched, &(*notify as i32), &now],
+        ).await?;
+        if inserted > 0 && *notify {
+            to_notify += 1;
+        }
+    }
+    tx.commit().await?;
+    Ok(to_notify)
+}
+
+/// Get the most recent watchlist matches in the filter's chats and time window, optionally
+/// only those of one watchlist, newest first, at most `limit`.
+pub async fn get_watch_hits(conn: &Connection, filter: &ChatFilter, watchlist: Option<&str>, limit: i64)
+    -> Result<Vec<WatchHitRecord>, Box<dyn std::error::Error>>
+{
+    let mut sql = "SELECT w.chat_peer, c.name, w.msg_id, m.date, m.text, w.watchlist, w.term, w.matched, w.created_at \n         FROM watch_hits w \n         JOIN chat_messages m ON m.chat_peer = w.chat_peer AND m.msg_id = w.msg_id \n         JOIN chats c ON c.peer_id = w.chat_peer \n         WHERE 1 = 1".to_string();
+    let mut params: Vec<Value> = Vec::new();
+    push_chat_conditions(filter, "c.", &mut sql, &mut params);
+    push_message_window(filter, &mut sql, &mut params);
+    if let Some(watchlist) = watchlist {
+        params.push(watchlist.to_string().into());
+        sql += &format!(" AND w.watchlist = ?{} COLLATE NOCASE", params.len());
+    }
+    params.push(limit.into());
+    sql += &format!(" ORDER BY m.date DESC, w.msg_id DESC LIMIT ?{};", params.len());
+
+    let mut stmt = conn.prepare(&sql).await?;
+    let mut rows = stmt.query(params).await?;
+    let mut hits = Vec::new();
+    while let Some(row) = rows.next().await? {
+        hits.push(WatchHitRecord {
+            chat_peer: row.get(0)?,
+            chat_name: row.get(1)?,
+            msg_id: row.get(2)?,
+            date: row.get(3)?,
+            text: row.get::<Option<String>>(4)?.unwrap_or_default(),
+            watchlist: row.get(5)?,
+            term: row.get(6)?,
+            matched: row.get(7)?,
+            created_at: row.get(8)?,
+        });
+    }
+    Ok(hits)
+}
+
+/// Number of stored matches and time of the latest per (watchlist, term).
+pub async fn get_watch_counts(conn: &Connection) -> Result<Vec<(String, String, i64, i64)>, Box<dyn std::error::Error>> {
+    let mut stmt = conn.prepare(
+        "SELECT watchlist, term, COUNT(*), MAX(created_at) FROM watch_hits GROUP BY watchlist, term;"
+    ).await?;
+    let mut rows = stmt.query(&[]).await?;
+    let mut counts = Vec::new();
+    while let Some(row) = rows.next().await? {
+        counts.push((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?));
+    }
+    Ok(counts)
+}
+
+/// A chat's activity, as used by the cleanup report.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChatMetrics {
+    pub peer_id: String,
+    pub kind: String,
+    pub name: String,
+    pub category: Option<String>,
+    pub messages: i64,               // stored messages since the start of the window
+    pub own_messages: i64,           // of which sent by the account owner
+    pub mentions: i64,               // of which addressed to the account owner
+    pub last_activity: Option<i32>,  // newest stored message or dialog top message, whichever is later
+    pub last_own_at: Option<i32>,    // newest stored message sent by the account owner
+    pub archived: bool,
+    pub muted: bool,
+}
+
+/// Activity metrics of the chats matching the filter's chat conditions, counting messages
+/// sent at or after `since`, ordered by name.
+pub async fn get_chat_metrics(conn: &Connection, filter: &ChatFilter, since: i64)
+    -> Result<Vec<ChatMetrics>, Box<dyn std::error::Error>>
+{
+    let mut params: Vec<Value> = vec![since.into()];
+    let mut sql = String::from(
+        "SELECT c.peer_id, c.type, c.name, c.category, \n                COUNT(m.id), COALESCE(SUM(m.outgoing), 0), COALESCE(SUM(m.mentions_me), 0), \n                MAX(COALESCE((SELECT MAX(date) FROM chat_messages WHERE chat_peer = c.peer_id), 0), COALESCE(c.last_message_at, 0)), \n                (SELECT MAX(date) FROM chat_messages WHERE chat_peer = c.peer_id AND outgoing = 1), \n                c.archived, c.muted \n         FROM chats c \n         LEFT JOIN chat_messages m ON m.chat_peer = c.peer_id AND m.date >= ?1 \n         WHERE 1 = 1"
+    );
+    push_chat_conditions(filter, "c.", &mut sql, &mut params);
+    sql += " GROUP BY c.peer_id ORDER BY c.name COLLATE NOCASE;";
+
+    let mut stmt = conn.prepare(&sql).await?;
+    let mut rows = stmt.query(params).await?;
+    let mut metrics = Vec::new();
+    while let Some(row) = rows.next().await? {
+        metrics.push(ChatMetrics {
+            peer_id: row.get(0)?,
+            kind: row.get::<Option<String>>(1)?.unwrap_or_default(),
+            name: row.get(2)?,
+            category: row.get(3)?,
+            messages: row.get(4)?,
+            own_messages: row.get(5)?,
+            mentions: row.get(6)?,
+            // Neither a stored message nor a known top message gives 0
+            last_activity: row.get::<Option<i32>>(7)?.filter(|ts| *ts > 0),
+            last_own_at: row.get(8)?,
+            archived: row.get::<Option<i32>>(9)?.unwrap_or(0) != 0,
+            muted: row.get::<Option<i32>>(10)?.unwrap_or(0) != 0,
+        });
+    }
+    Ok(metrics)
+}
+
+/// Bring the `chat_stats_daily` aggregates up to date: every (chat, UTC day) with messages
+/// stored since the last refresh is recounted, wherever in the history they landed (so backfills
+/// and imports are covered), and today's row of each chat records its current member count.
+/// Returns the number of recounted days.
+pub async fn refresh_daily_stats(conn: &mut Connection) -> Result<u64, Box<dyn std::error::Error>> {
+    let watermark: i64 = {
+        let mut stmt = conn.prepare("SELECT COALESCE(MAX(last_row), 0) FROM chat_stats_daily;").await?;
+        let mut rows = stmt.query(&[]).await?;
+        match rows.next().await? {
+            Some(row) => row.get(0)?,
+            None => 0,
+        }
+    };
+    let mut tx = conn.transaction().await?;
+    let recounted = tx.execute(
+        "INSERT INTO chat_stats_daily (chat_peer, day, messages, senders, own_messages, mentions, last_row) \n         SELECT m.chat_peer, date(m.date, 'unixepoch') AS day, COUNT(*), COUNT(DISTINCT m.sender_id), \n                COALESCE(SUM(m.outgoing), 0), COALESCE(SUM(m.mentions_me), 0), MAX(m.id) \n         FROM chat_messages m \n         WHERE (m.chat_peer, date(m.date, 'unixepoch')) IN \n               (SELECT DISTINCT chat_peer, date(date, 'unixepoch') FROM chat_messages WHERE id > ?1) \n         GROUP BY m.chat_peer, day \n         ON CONFLICT(chat_peer, day) DO UPDATE SET \n            messages = excluded.messages, senders = excluded.senders, own_messages = excluded.own_messages, \n            mentions = excluded.mentions, last_row = excluded.last_row;",
+        &[&watermark],
+    ).await?;
+    // Telegram's participant count, else the members we know of
+    tx.execute(
+        "INSERT INTO chat_stats_daily (chat_peer, day, member_count) \n         SELECT peer_id, date('now'), members FROM ( \n             SELECT c.peer_id, COALESCE(c.member_count, \n                    NULLIF((SELECT COUNT(*) FROM chat_members cm WHERE cm.chat_peer = c.peer_id), 0)) AS members \n             FROM chats c \n         ) WHERE members IS NOT NULL \n         ON CONFLICT(chat_peer, day) DO UPDATE SET member_count = excluded.member_count;",
+        &[],
+    ).await?;
+    tx.commit().await?;
+    Ok(recounted)
+}
+
+/// One chat's aggregates for one UTC day.
+#[derive(Debug, Clone, Serialize)]
+pub struct DailyStats {
+    pub chat_peer: String,
+    pub chat_name: String,
+    pub day: String,  // YYYY-MM-DD
+    pub messages: i64,
+    pub senders: i64,
+    pub own_messages: i64,
+    pub mentions: i64,
+    pub member_count: Option<i64>,
+}
+
+/// Daily aggregates of the chats matching the filter's chat conditions for the days in
+/// its date range, ordered by chat name and day.
+pub async fn get_daily_stats(conn: &Connection, filter: &ChatFilter) -> Result<Vec<DailyStats>, Box<dyn std::error::Error>> {
+    let mut params: Vec<Value> = Vec::new();
+    let mut sql = String::from(
+        "SELECT s.chat_peer, c.name, s.day, s.messages, s.senders, s.own_messages, s.mentions, s.member_count \n         FROM chat_stats_daily s \n         JOIN chats c ON c.peer_id = s.chat_peer \n         WHERE 1 = 1"
+    );
+    if let Some(since) = filter.since {
+        params.push(since.into());
+        sql += &format!(" AND s.day >= date(?{}, 'unixepoch')", params.len());
+    }
+    if let Some(until) = filter.until {
+        params.push(until.into());
+        sql += &format!(" AND s.day < date(?{}, 'unixepoch')", params.len());
+    }
+    push_chat_conditions(filter, "c.", &mut sql, &mut params);
+    sql += " ORDER BY c.name COLLATE NOCASE, s.chat_peer, s.day;";
+
+    let mut stmt = conn.prepare(&sql).await?;
+    let mut rows = stmt.query(params).await?;
+    let mut stats = Vec::new();
+    while let Some(row) = rows.next().await? {
+        stats.push(DailyStats {
+            chat_peer: row.get(0)?,
+            chat_name: row.get(1)?,
+            day: row.get(2)?,
+            messages: row.get(3)?,
+            senders: row.get(4)?,
+            own_messages: row.get(5)?,
+            mentions: row.get(6)?,
+            member_count: row.get(7)?,
+        });
+    }
+    Ok(stats)
+}
+
+/// Messages per hour of the day (UTC) within the filter.
+pub async fn get_hourly_activity(conn: &Connection, filter: &ChatFilter) -> Result<[i64; 24], Box<dyn std::error::Error>> {
+    let mut params: Vec<Value> = Vec::new();
+    let mut sql = String::from(
+        "SELECT CAST(strftime('%H', m.date, 'unixepoch') AS INTEGER), COUNT(*) \n         FROM chat_messages m \n         JOIN chats c ON c.peer_id = m.chat_peer \n         WHERE 1 = 1"
+    );
+    push_message_window(filter, &mut sql, &mut params);
+    push_chat_conditions(filter, "c.", &mut sql, &mut params);
+    sql += " GROUP BY 1;";
+
+    let mut stmt = conn.prepare(&sql).await?;
+    let mut rows = stmt.query(params).await?;
+    let mut hours = [0i64; 24];
+    while let Some(row) = rows.next().await? {
+        let hour: i64 = row.get(0)?;
+        if let Some(slot) = hours.get_mut(hour as usize) {
+            *slot = row.get(1)?;
+        }
+    }
+    Ok(hours)
+}
+
+/// A user's messages within a stats window.
+#[derive(Debug, Clone, Serialize)]
+pub struct Poster {
+    pub user_id: i64,
+    pub name: Option<String>,
+    pub username: Option<String>,
+    pub messages: i64,
+    pub chats: i64,
+}
+
+/// The users who sent the most messages within the filter, most first.
+pub async fn get_top_posters(conn: &Connection, filter: &ChatFilter, limit: i64) -> Result<Vec<Poster>, Box<dyn std::error::Error>> {
+    let mut params: Vec<Value> = Vec::new();
+    let mut sql = String::from(
+        "SELECT m.sender_id, u.name, u.username, COUNT(*), COUNT(DISTINCT m.chat_peer) \n         FROM chat_messages m \n         JOIN chats c ON c.peer_id = m.chat_peer \n         LEFT JOIN users u ON u.user_id = m.sender_id \n         WHERE m.sender_id IS NOT NULL"
+    );
+    push_message_window(filter, &mut sql, &mut params);
+    push_chat_conditions(filter, "c.", &mut sql, &mut params);
+    params.push(limit.into());
+    sql += &format!(" GROUP BY m.sender_id ORDER BY COUNT(*) DESC LIMIT ?{};", params.len());
+
+    let mut stmt = conn.prepare(&sql).await?;
+    let mut rows = stmt.query(params).await?;
+    let mut posters = Vec::new();
+    while let Some(row) = rows.next().await? {
+        posters.push(Poster {
+            user_id: row.get(0)?,
+            name: row.get(1)?,
+            username: row.get(2)?,
+            messages: row.get(3)?,
+            chats: row.get(4)?,
+        });
+    }
+    Ok(posters)
+}
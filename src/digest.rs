@@ -0,0 +1,275 @@
+use std::collections::HashSet;
+use log::{info, error};
+use grammers_client::Client;
+use libsql::Connection;
+use chrono::Utc;
+use crate::{telegram, database, ai};
+use crate::database::{ChatFilter, ReportQuery, ReportSort};
+use crate::report::format_timestamp;
+
+/// Number of chats listed in each digest section.
+const DIGEST_TOP_CHATS: usize = 5;
+/// Urgent messages listed per chat in the digest.
+const DIGEST_URGENT_PER_CHAT: usize = 3;
+/// Recent messages per active chat given to the LLM as digest material.
+const DIGEST_CONTEXT_MESSAGES: usize = 15;
+/// Digest buckets are aligned to Monday 1970-01-05 00:00 UTC, so daily digests
+/// start at midnight and weekly digests on Monday.
+const BUCKET_ORIGIN: i64 = 4 * 86400;
+
+/// How often digests are produced and how far back each one looks.
+#[derive(Debug, Clone)]
+pub struct DigestPeriod {
+    pub label: String,  // "daily", "weekly" or e.g. "12h"
+    pub hours: i64,
+}
+
+impl DigestPeriod {
+    /// Parse `daily`, `weekly` or a number of hours such as `12h`.
+    pub fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let hours = match value {
+            "daily" => 24,
+            "weekly" => 24 * 7,
+            other => other.trim_end_matches('h').parse::<i64>()
+                .ok()
+                .filter(|h| *h > 0)
+                .ok_or_else(|| format!("Invalid digest period \"{}\" (expected daily, weekly or <N>h)", other))?,
+        };
+        Ok(DigestPeriod { label: value.to_string(), hours })
+    }
+
+    /// Period configured for the monitor loop via DIGEST_PERIOD, if any.
+    pub fn from_env() -> Option<Self> {
+        let value = std::env::var("DIGEST_PERIOD").ok()?;
+        match DigestPeriod::parse(&value) {
+            Ok(period) => Some(period),
+            Err(e) => {
+                error!("Ignoring DIGEST_PERIOD: {}", e);
+                None
+            }
+        }
+    }
+
+    /// The last complete period before `now`, as (start, end) with the end exclusive.
+    /// Periods are consecutive buckets of `hours`, aligned to BUCKET_ORIGIN.
+    fn previous_bucket(&self, now: i64) -> (i64, i64) {
+        let length = self.hours * 3600;
+        let current_start = (now - BUCKET_ORIGIN).div_euclid(length) * length + BUCKET_ORIGIN;
+        (current_start - length, current_start)
+    }
+
+    /// Key identifying the period starting at `start`; at most one digest is sent per key.
+    fn key(&self, start: i64) -> String {
+        format!("{}:{}", self.label, format_timestamp(start as i32))
+    }
+}
+
+/// Compose a digest of the last complete period and send it to Saved Messages, unless one was
+/// already sent for that period (or `force` is set). Covering whole buckets keeps consecutive
+/// digests from overlapping or leaving gaps, whenever the monitor happens to run.
+/// Returns true if a digest was sent.
+pub async fn send_digest(client: &Client, conn: &mut Connection, period: &DigestPeriod, force: bool)
+    -> Result<bool, Box<dyn std::error::Error>>
+{
+    let (since, until) = period.previous_bucket(Utc::now().timestamp());
+    let key = period.key(since);
+    if !force && database::digest_sent(conn, &key).await? {
+        info!("Digest for {} already sent; skipping.", key);
+        return Ok(false);
+    }
+    let (content, duplicates) = compose_digest(conn, period, since, until).await?;
+    telegram::send_to_saved_messages(client, &content).await?;
+    database::record_digest(conn, &key, since, until, &content, &duplicates).await?;
+    info!("Digest for {} sent to Saved Messages.", key);
+    Ok(true)
+}
+
+/// Build the digest text (Telegram Markdown) and return it with the current duplicate chat ids.
+async fn compose_digest(conn: &Connection, period: &DigestPeriod, since: i64, until: i64)
+    -> Result<(String, Vec<String>), Box<dyn std::error::Error>>
+{
+    let window = ChatFilter { since: Some(since), until: Some(until), ..Default::default() };
+
+    // Most urgent and most active chats in the window
+    let urgent_chats = database::get_report_chats(conn, &ReportQuery {
+        filter: window.clone(),
+        only_urgent: true,
+        sort: ReportSort::Urgency,
+        ..Default::default()
+    }).await?;
+    let active_chats: Vec<_> = database::get_report_chats(conn, &ReportQuery {
+        filter: window.clone(),
+        sort: ReportSort::Activity,
+        ..Default::default()
+    }).await?
+        .into_iter()
+        .filter(|c| c.message_count > 0)
+        .take(DIGEST_TOP_CHATS)
+        .collect();
+
+    // Duplicate chats not yet reported by a previous digest
+    let duplicates = database::get_report_chats(conn, &ReportQuery { only_duplicates: true, ..Default::default() }).await?;
+    let reported: HashSet<String> = database::get_last_digest_duplicates(conn).await?.into_iter().collect();
+    let new_duplicates: Vec<_> = duplicates.iter().filter(|c| !reported.contains(&c.chat.peer_id)).collect();
+
+    // Material for the LLM: urgent items and the latest messages of the most active chats
+    let mut urgent_lines = Vec::new();
+    let mut material = String::from("Urgent messages:\n");
+    for chat in urgent_chats.iter().take(DIGEST_TOP_CHATS) {
+        for msg in database::get_urgent_snippets(conn, &chat.chat.peer_id, &window, Some(DIGEST_URGENT_PER_CHAT)).await? {
+            let text = msg.text.replace('\n', " ");
+            material += &format!("- [{}] {}\n", chat.chat.name, text);
+            urgent_lines.push(format!("• *{}*: {} ({})", md_escape(&chat.chat.name), md_escape(&snippet(&text, 120)), format_timestamp(msg.date)));
+        }
+    }
+    for chat in &active_chats {
+        material += &format!("\nRecent messages in \"{}\":\n", chat.chat.name);
+        let messages = database::get_message_records(conn, &chat.chat.peer_id, &window, false, false).await?;
+        let skip = messages.len().saturating_sub(DIGEST_CONTEXT_MESSAGES);
+        for msg in messages.iter().skip(skip) {
+            material += &format!("- {}\n", msg.text.replace('\n', " "));
+        }
+    }
+
+    let period_label = format!("{} hours", period.hours);
+    let mut out = String::new();
+    line(&mut out, &format!("**Telegram digest — {}**", period_label));
+    line(&mut out, &format!("_{} → {} UTC_", format_timestamp(since as i32), format_timestamp(until as i32)));
+
+    match ai::summarize_digest(&ai::analysis_model(), &period_label, &material).await {
+        Ok((summary, action_items)) => {
+            section(&mut out, "Summary");
+            line(&mut out, &md_escape(&summary));
+            if !action_items.is_empty() {
+                section(&mut out, "Action items");
+                for item in action_items {
+                    line(&mut out, &format!("• {}", md_escape(&item)));
+                }
+            }
+        }
+        Err(e) => {
+            // The structured sections below are still useful without the LLM
+            error!("Digest summary failed: {}", e);
+            section(&mut out, "Summary");
+            line(&mut out, "_AI summary unavailable._");
+        }
+    }
+
+    if !urgent_lines.is_empty() {
+        section(&mut out, "Top urgent");
+        for l in &urgent_lines {
+            line(&mut out, l);
+        }
+    }
+    if !active_chats.is_empty() {
+        section(&mut out, "Most active chats");
+        for chat in &active_chats {
+            line(&mut out, &format!("• {} — {} messages", md_escape(&chat.chat.name), chat.message_count));
+        }
+    }
+    if !new_duplicates.is_empty() {
+        section(&mut out, "New duplicate topics");
+        for chat in &new_duplicates {
+            line(&mut out, &format!("• {} ({})", md_escape(&chat.chat.name),
+                md_escape(chat.chat.category.as_deref().unwrap_or("Uncategorized"))));
+        }
+    }
+
+    let duplicate_ids = duplicates.into_iter().map(|c| c.chat.peer_id).collect();
+    Ok((out, duplicate_ids))
+}
+
+/// Helper: append a line ending in a Markdown hard break (Telegram keeps the newline).
+fn line(out: &mut String, text: &str) {
+    out.push_str(text);
+    out.push_str("\\\n");
+}
+
+/// Helper: start a new bold section heading.
+fn section(out: &mut String, title: &str) {
+    out.push_str("\\\n");
+    line(out, &format!("**{}**", title));
+}
+
+/// Helper: escape characters that Markdown would interpret in chat-supplied text.
+pub fn md_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(c, '\\' | '*' | '_' | '`' | '[' | ']' | '#' | '<' | '>') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Helper: first `max_chars` characters of a text, with an ellipsis if cut.
+fn snippet(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((idx, _)) => format!("{}…", &text[..idx]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDateTime;
+
+    /// Helper: a UTC `YYYY-MM-DD HH:MM` time as a UNIX timestamp.
+    fn at(value: &str) -> i64 {
+        NaiveDateTime::parse_from_str(value, "%Y-%m-%d %H:%M").unwrap().and_utc().timestamp()
+    }
+
+    fn period(value: &str) -> DigestPeriod {
+        DigestPeriod::parse(value).unwrap()
+    }
+
+    #[test]
+    fn parse_accepts_named_periods_and_hours() {
+        assert_eq!(period("daily").hours, 24);
+        assert_eq!(period("weekly").hours, 168);
+        assert_eq!(period("12h").hours, 12);
+        assert!(DigestPeriod::parse("0h").is_err());
+        assert!(DigestPeriod::parse("monthly").is_err());
+    }
+
+    #[test]
+    fn daily_digests_cover_yesterday() {
+        let daily = period("daily");
+        let yesterday = (at("2026-10-13 00:00"), at("2026-10-14 00:00"));
+        assert_eq!(daily.previous_bucket(at("2026-10-14 15:30")), yesterday);
+        assert_eq!(daily.previous_bucket(at("2026-10-14 00:00")), yesterday);
+        assert_eq!(daily.previous_bucket(at("2026-10-14 23:59")), yesterday);
+    }
+
+    #[test]
+    fn weekly_digests_cover_the_previous_monday_to_monday() {
+        let weekly = period("weekly");
+        // 2026-10-14 is a Wednesday
+        assert_eq!(weekly.previous_bucket(at("2026-10-14 15:30")), (at("2026-10-05 00:00"), at("2026-10-12 00:00")));
+        assert_eq!(weekly.previous_bucket(at("2026-10-12 00:00")), (at("2026-10-05 00:00"), at("2026-10-12 00:00")));
+    }
+
+    #[test]
+    fn hourly_digests_cover_the_previous_block() {
+        let half_day = period("12h");
+        assert_eq!(half_day.previous_bucket(at("2026-10-14 15:30")), (at("2026-10-14 00:00"), at("2026-10-14 12:00")));
+        assert_eq!(half_day.previous_bucket(at("2026-10-14 11:59")), (at("2026-10-13 12:00"), at("2026-10-14 00:00")));
+    }
+
+    #[test]
+    fn keys_name_the_period_and_its_start() {
+        let daily = period("daily");
+        let (start, _) = daily.previous_bucket(at("2026-10-14 15:30"));
+        assert_eq!(daily.key(start), "daily:2026-10-13 00:00:00");
+        // Every run within the same day maps to the same key, so the digest is sent once
+        assert_eq!(daily.key(daily.previous_bucket(at("2026-10-14 00:05")).0), daily.key(start));
+    }
+
+    #[test]
+    fn md_escape_escapes_markup() {
+        assert_eq!(md_escape("*bold* _it_ [link](x) `code`"), "\\*bold\\* \\_it\\_ \\[link\\](x) \\`code\\`");
+        assert_eq!(md_escape("plain text"), "plain text");
+    }
+}
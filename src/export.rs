@@ -0,0 +1,236 @@
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use log::info;
+use libsql::Connection;
+use serde::Serialize;
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use crate::database::{self, ChatFilter, ChatRecord, MessageRecord};
+use crate::report::format_timestamp;
+
+/// Output format of the `export` command.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    JsonLines,
+    Csv,
+    Markdown,
+}
+
+impl ExportFormat {
+    /// Parse a format name as given on the command line.
+    pub fn parse(value: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        match value.to_lowercase().as_str() {
+            "jsonl" | "json" => Ok(ExportFormat::JsonLines),
+            "csv" => Ok(ExportFormat::Csv),
+            "md" | "markdown" => Ok(ExportFormat::Markdown),
+            other => Err(format!("Unknown export format \"{}\" (expected jsonl, csv or markdown)", other).into()),
+        }
+    }
+}
+
+/// What to export and where.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    pub format: ExportFormat,
+    pub out_dir: PathBuf,
+    pub filter: ChatFilter,
+    pub with_embeddings: bool,  // include message embeddings as base64 (JSONL and CSV only)
+}
+
+/// A message as written to JSONL.
+#[derive(Serialize)]
+struct MessageRow<'a> {
+    #[serde(flatten)]
+    message: &'a MessageRecord,
+    datetime: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    embedding: Option<String>,
+}
+
+/// A chat member as written to JSONL.
+#[derive(Serialize)]
+struct MemberRow<'a> {
+    chat_peer: &'a str,
+    user_id: i64,
+    name: &'a str,
+    username: Option<&'a str>,
+    bio: Option<&'a str>,
+}
+
+/// Everything exported for one chat.
+struct ChatExport {
+    chat: ChatRecord,
+    messages: Vec<MessageRecord>,
+    members: Vec<(i64, String, Option<String>, Option<String>)>,
+}
+
+/// Export chats (with categories and suggested names), messages, urgent items and
+/// members matching the filter into `out_dir`.
+/// JSONL and CSV write `chats`, `messages`, `urgent` and `members` files;
+/// Markdown writes one transcript per chat.
+pub async fn run_export(conn: &Connection, options: &ExportOptions) -> Result<(), Box<dyn std::error::Error>> {
+    fs::create_dir_all(&options.out_dir)?;
+    let chats = database::get_chat_records(conn, &options.filter).await?;
+    let mut exports = Vec::with_capacity(chats.len());
+    for chat in chats {
+        let messages = database::get_message_records(conn, &chat.peer_id, &options.filter, false, options.with_embeddings).await?;
+        let members = database::get_chat_members(conn, &chat.peer_id).await?;
+        exports.push(ChatExport { chat, messages, members });
+    }
+
+    match options.format {
+        ExportFormat::JsonLines => write_jsonl(&options.out_dir, &exports)?,
+        ExportFormat::Csv => write_csv(&options.out_dir, &exports, options.with_embeddings)?,
+        ExportFormat::Markdown => write_markdown(&options.out_dir, &exports)?,
+    }
+    let message_count: usize = exports.iter().map(|e| e.messages.len()).sum();
+    info!("Exported {} chats and {} messages to {}", exports.len(), message_count, options.out_dir.display());
+    Ok(())
+}
+
+/// Write chats.jsonl, messages.jsonl, urgent.jsonl and members.jsonl.
+fn write_jsonl(dir: &Path, exports: &[ChatExport]) -> Result<(), Box<dyn std::error::Error>> {
+    let mut chats = BufWriter::new(File::create(dir.join("chats.jsonl"))?);
+    let mut messages = BufWriter::new(File::create(dir.join("messages.jsonl"))?);
+    let mut urgent = BufWriter::new(File::create(dir.join("urgent.jsonl"))?);
+    let mut members = BufWriter::new(File::create(dir.join("members.jsonl"))?);
+    for export in exports {
+        writeln!(chats, "{}", serde_json::to_string(&export.chat)?)?;
+        for msg in &export.messages {
+            let row = MessageRow {
+                message: msg,
+                datetime: format_timestamp(msg.date),
+                embedding: msg.embedding.as_ref().map(|blob| BASE64.encode(blob)),
+            };
+            writeln!(messages, "{}", serde_json::to_string(&row)?)?;
+            if msg.urgent {
+                let row = MessageRow { embedding: None, ..row };
+                writeln!(urgent, "{}", serde_json::to_string(&row)?)?;
+            }
+        }
+        for (user_id, name, username, bio) in &export.members {
+            let row = MemberRow {
+                chat_peer: &export.chat.peer_id,
+                user_id: *user_id,
+                name,
+                username: username.as_deref(),
+                bio: bio.as_deref(),
+            };
+            writeln!(members, "{}", serde_json::to_string(&row)?)?;
+        }
+    }
+    chats.flush()?;
+    messages.flush()?;
+    urgent.flush()?;
+    members.flush()?;
+    Ok(())
+}
+
+/// Write chats.csv, messages.csv, urgent.csv and members.csv.
+fn write_csv(dir: &Path, exports: &[ChatExport], with_embeddings: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut chats = csv::Writer::from_path(dir.join("chats.csv"))?;
+    let mut messages = csv::Writer::from_path(dir.join("messages.csv"))?;
+    let mut urgent = csv::Writer::from_path(dir.join("urgent.csv"))?;
+    let mut members = csv::Writer::from_path(dir.join("members.csv"))?;
+
+    chats.write_record(["peer_id", "type", "name", "category", "suggested_name", "duplicate", "tags"])?;
+    let mut message_header = vec!["chat_peer", "msg_id", "date", "datetime", "text", "urgent"];
+    urgent.write_record(&message_header)?;
+    if with_embeddings {
+        message_header.push("embedding");
+    }
+    messages.write_record(&message_header)?;
+    members.write_record(["chat_peer", "user_id", "name", "username", "bio"])?;
+
+    for export in exports {
+        let chat = &export.chat;
+        chats.write_record([
+            chat.peer_id.as_str(),
+            chat.kind.as_str(),
+            chat.name.as_str(),
+            chat.category.as_deref().unwrap_or(""),
+            chat.suggested_name.as_deref().unwrap_or(""),
+            if chat.duplicate { "1" } else { "0" },
+            chat.tags.join(";").as_str(),
+        ])?;
+        for msg in &export.messages {
+            let mut record = vec![
+                msg.chat_peer.clone(),
+                msg.msg_id.to_string(),
+                msg.date.to_string(),
+                format_timestamp(msg.date),
+                msg.text.clone(),
+                (msg.urgent as i32).to_string(),
+            ];
+            if msg.urgent {
+                urgent.write_record(&record)?;
+            }
+            if with_embeddings {
+                record.push(msg.embedding.as_ref().map(|blob| BASE64.encode(blob)).unwrap_or_default());
+            }
+            messages.write_record(&record)?;
+        }
+        for (user_id, name, username, bio) in &export.members {
+            members.write_record([
+                chat.peer_id.as_str(),
+                user_id.to_string().as_str(),
+                name.as_str(),
+                username.as_deref().unwrap_or(""),
+                bio.as_deref().unwrap_or(""),
+            ])?;
+        }
+    }
+    chats.flush()?;
+    messages.flush()?;
+    urgent.flush()?;
+    members.flush()?;
+    Ok(())
+}
+
+/// Write one Markdown transcript per chat.
+fn write_markdown(dir: &Path, exports: &[ChatExport]) -> Result<(), Box<dyn std::error::Error>> {
+    for export in exports {
+        let chat = &export.chat;
+        let path = dir.join(format!("{}-{}.md", slugify(&chat.name), chat.peer_id.replace(':', "_")));
+        let mut out = BufWriter::new(File::create(path)?);
+        writeln!(out, "# {}\n", chat.name)?;
+        writeln!(out, "- Peer: `{}`", chat.peer_id)?;
+        writeln!(out, "- Category: {}", chat.category.as_deref().unwrap_or("Uncategorized"))?;
+        writeln!(out, "- Suggested name: {}", chat.suggested_name.as_deref().unwrap_or("-"))?;
+        if !chat.tags.is_empty() {
+            writeln!(out, "- Tags: {}", chat.tags.join(", "))?;
+        }
+        writeln!(out, "- Duplicate topic: {}", if chat.duplicate { "Yes" } else { "No" })?;
+
+        if !export.members.is_empty() {
+            writeln!(out, "\n## Members\n")?;
+            for (_user_id, name, username, _bio) in &export.members {
+                match username {
+                    Some(username) => writeln!(out, "- {} (@{})", name, username)?,
+                    None => writeln!(out, "- {}", name)?,
+                }
+            }
+        }
+
+        writeln!(out, "\n## Messages\n")?;
+        for msg in &export.messages {
+            let marker = if msg.urgent { " **[URGENT]**" } else { "" };
+            // Indent continuation lines so multi-line messages stay inside their list item
+            let text = msg.text.replace('\n', "\n  ");
+            writeln!(out, "- `{}` #{}{} {}", format_timestamp(msg.date), msg.msg_id, marker, text)?;
+        }
+        out.flush()?;
+    }
+    Ok(())
+}
+
+/// Helper: turn a chat name into a safe file name component.
+fn slugify(name: &str) -> String {
+    let slug: String = name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c.to_ascii_lowercase() } else { '-' })
+        .collect();
+    let slug = slug.split('-').filter(|s| !s.is_empty()).collect::<Vec<_>>().join("-");
+    if slug.is_empty() { "chat".to_string() } else { slug }
+}
@@ -0,0 +1,62 @@
+use log::info;
+use libsql::Connection;
+use crate::database;
+use crate::database::{FeedbackKind, UrgentState};
+use crate::report::format_timestamp;
+
+/// Corrections listed by `feedback list` unless `--limit` says otherwise.
+const DEFAULT_LIST_LIMIT: i64 = 50;
+
+/// Set a chat's category by hand. Later analyses keep it until it is cleared.
+pub async fn set_category(conn: &mut Connection, chat_peer: &str, category: &str, by: &str)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    database::override_category(conn, chat_peer, category, by).await?;
+    info!("Category of {} set to \"{}\" by {}", chat_peer, category, by);
+    Ok(())
+}
+
+/// Drop a chat's category override so the analyzer decides again.
+pub async fn clear_category(conn: &mut Connection, chat_peer: &str) -> Result<(), Box<dyn std::error::Error>> {
+    database::clear_category_override(conn, chat_peer).await?;
+    info!("Category override of {} cleared", chat_peer);
+    Ok(())
+}
+
+/// Record that the analyzer missed an urgent message and flag it.
+pub async fn mark_urgent(conn: &mut Connection, chat_peer: &str, msg_id: i32, by: &str)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    if !database::mark_missed_urgent(conn, chat_peer, msg_id, by).await? {
+        return Err(format!("No message #{} in {} that isn't already urgent", msg_id, chat_peer).into());
+    }
+    info!("Message #{} in {} flagged urgent by {}", msg_id, chat_peer, by);
+    Ok(())
+}
+
+/// Record that a flagged message isn't urgent (same as `urgent false-positive`).
+pub async fn mark_not_urgent(conn: &mut Connection, chat_peer: &str, msg_id: i32, by: &str)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    crate::urgent::set_state(conn, chat_peer, msg_id, UrgentState::FalsePositive, by).await
+}
+
+/// Print the most recent corrections, newest first.
+pub async fn list(conn: &Connection, limit: Option<i64>) -> Result<(), Box<dyn std::error::Error>> {
+    let items = database::get_recent_feedback(conn, limit.unwrap_or(DEFAULT_LIST_LIMIT)).await?;
+    if items.is_empty() {
+        println!("No feedback recorded.");
+        return Ok(());
+    }
+    for item in &items {
+        let by = item.created_by.as_deref().unwrap_or("unknown");
+        println!("{}  {} — {} by {}", format_timestamp(item.created_at as i32), item.chat_name, item.kind.as_str(), by);
+        match item.kind {
+            FeedbackKind::CategoryOverride => println!("    category: {} -> {}",
+                item.previous.as_deref().unwrap_or("(none)"), item.value.as_deref().unwrap_or("")),
+            FeedbackKind::UrgentFalsePositive | FeedbackKind::UrgentFalseNegative => println!("    #{} {}",
+                item.msg_id.unwrap_or_default(), item.message_text.as_deref().unwrap_or("").replace('\n', " ")),
+        }
+    }
+    Ok(())
+}
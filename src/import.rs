@@ -0,0 +1,278 @@
+use log::{info, warn};
+use libsql::Connection;
+use serde::Deserialize;
+use chrono::NaiveDateTime;
+use std::collections::HashSet;
+use crate::{database, ai, watch};
+use crate::shutdown::Shutdown;
+use crate::telegram::{ChatInfo, ChatKind, MessageInfo};
+use crate::watch::Watchlists;
+
+/// Number of messages embedded and stored per transaction during an import.
+const IMPORT_BATCH: usize = 100;
+
+/// Top level of a Telegram Desktop `result.json`: either a full account export
+/// (chats nested under `chats.list`) or a single exported chat.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ExportFile {
+    Account { chats: ExportChatList },
+    Single(ExportChat),
+}
+
+#[derive(Deserialize)]
+struct ExportChatList {
+    list: Vec<ExportChat>,
+}
+
+/// One exported chat.
+#[derive(Deserialize)]
+struct ExportChat {
+    name: Option<String>,
+    #[serde(rename = "type")]
+    kind: String,
+    id: i64,
+    #[serde(default)]
+    messages: Vec<ExportMessage>,
+}
+
+/// One exported message. `text` is either a plain string or an array mixing
+/// strings and entity objects such as `{"type": "bold", "text": "..."}`.
+#[derive(Deserialize)]
+struct ExportMessage {
+    id: i32,
+    #[serde(rename = "type")]
+    kind: String,
+    date: String,
+    date_unixtime: Option<String>,
+    from: Option<String>,
+    from_id: Option<String>,
+    reply_to_message_id: Option<i32>,
+    #[serde(default)]
+    text: serde_json::Value,
+}
+
+/// Totals reported at the end of an import.
+#[derive(Debug, Default)]
+struct ImportStats {
+    chats: usize,
+    messages: usize,
+    duplicates: usize,
+    members: usize,
+}
+
+/// Import a Telegram Desktop JSON export into the database. Group and channel chats
+/// keep their Telegram ids (so they line up with live monitoring), messages keep their
+/// ids and are embedded like live ones, and messages already stored are skipped.
+/// With `only_chat`, imports just the chat with that name. Watchlist matches are recorded
+/// as for live messages.
+pub async fn import_export(conn: &mut Connection, path: &str, only_chat: Option<&str>, shutdown: &Shutdown)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let raw = std::fs::read_to_string(path)?;
+    let export: ExportFile = serde_json::from_str(&raw)
+        .map_err(|e| format!("{} is not a Telegram Desktop JSON export: {}", path, e))?;
+    let chats = match export {
+        ExportFile::Account { chats } => chats.list,
+        ExportFile::Single(chat) => vec![chat],
+    };
+
+    let watchlists = Watchlists::from_env()?;
+    let mut stats = ImportStats::default();
+    for chat in chats {
+        if shutdown.is_requested() {
+            break;
+        }
+        let title = chat.name.clone().unwrap_or_else(|| format!("Chat {}", chat.id));
+        if only_chat.map_or(false, |c| !title.eq_ignore_ascii_case(c)) {
+            continue;
+        }
+        let Some(info) = chat_info(&chat, &title) else {
+            info!("Skipping \"{}\" ({}): only groups and channels are organized", title, chat.kind);
+            continue;
+        };
+        import_chat(conn, &info, chat.messages, &watchlists, &mut stats, shutdown).await?;
+        stats.chats += 1;
+    }
+    info!("Import finished: {} chats, {} new messages, {} already stored, {} members",
+        stats.chats, stats.messages, stats.duplicates, stats.members);
+    Ok(())
+}
+
+/// Store one exported chat, its new messages and the users who posted them.
+async fn import_chat(conn: &mut Connection, chat: &ChatInfo, messages: Vec<ExportMessage>, watchlists: &Watchlists,
+    stats: &mut ImportStats, shutdown: &Shutdown)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    database::ensure_chat(conn, chat).await?;
+    let existing = database::get_message_ids(conn, &chat.peer_id).await?;
+
+    let mut pending: Vec<MessageInfo> = Vec::new();
+    let mut seen_users: HashSet<i64> = HashSet::new();
+    for msg in messages {
+        if msg.kind != "message" {
+            continue;  // service messages (joins, pins, ...)
+        }
+        let Some(date) = message_date(&msg) else {
+            warn!("Skipping message {} in \"{}\": unreadable date \"{}\"", msg.id, chat.title, msg.date);
+            continue;
+        };
+        if let (Some(from), Some(user_id)) = (msg.from.as_deref(), msg.from_id.as_deref().and_then(user_id_of)) {
+            // First appearance in the export approximates when the user joined
+            if seen_users.insert(user_id) {
+                database::ensure_member(conn, &chat.peer_id, user_id, from, date).await?;
+                stats.members += 1;
+            }
+        }
+        if existing.contains(&msg.id) {
+            stats.duplicates += 1;
+            continue;
+        }
+        let text = flatten_text(&msg.text);
+        if text.is_empty() {
+            continue;  // media without caption
+        }
+        let sender_id = msg.from_id.as_deref().and_then(user_id_of);
+        // Exports don't say which messages are ours, so mentions aren't detected for imports
+        pending.push(MessageInfo {
+            msg_id: msg.id,
+            date,
+            text,
+            sender_id,
+            outgoing: false,
+            reply_to: msg.reply_to_message_id,
+            mentions_me: false,
+        });
+    }
+
+    let total = pending.len();
+    let mut stored = 0;
+    while !pending.is_empty() {
+        if shutdown.is_requested() {
+            info!("Import of \"{}\" interrupted after {} of {} messages; rerun to continue.", chat.title, stored, total);
+            break;
+        }
+        let batch: Vec<MessageInfo> = pending.drain(..pending.len().min(IMPORT_BATCH)).collect();
+        let count = batch.len();
+        // Stored even when the embedding model is unavailable; the monitor embeds them later
+        let embedded = ai::embed_messages(batch).await;
+        database::save_messages(conn, &chat.peer_id, &embedded).await?;
+        let saved: Vec<MessageInfo> = embedded.into_iter().map(|(msg, _)| msg).collect();
+        watch::scan_messages(conn, watchlists, &chat.peer_id, &saved).await?;
+        stored += count;
+        stats.messages += count;
+        info!("Import \"{}\": {}/{} messages stored", chat.title, stored, total);
+    }
+    Ok(())
+}
+
+/// Map an exported chat to our chat model. Supergroups and channels use channel ids,
+/// legacy groups use chat ids; private chats and bots are not organized.
+fn chat_info(chat: &ExportChat, title: &str) -> Option<ChatInfo> {
+    let (prefix, kind) = match chat.kind.as_str() {
+        "private_group" => ("group", ChatKind::Group),
+        "private_supergroup" | "public_supergroup" | "private_channel" | "public_channel" => ("channel", ChatKind::Channel),
+        _ => return None,
+    };
+    Some(ChatInfo {
+        peer_id: format!("{}:{}", prefix, chat.id),
+        title: title.to_string(),
+        kind,
+        tg_id: chat.id,
+        access_hash: None,  // not part of exports; filled in by the next live sync
+        can_change_info: false,
+        last_message_at: None,
+        archived: false,
+        muted: false,
+        member_count: None,
+    })
+}
+
+/// Parse a message date, preferring the exact `date_unixtime` of newer exports.
+fn message_date(msg: &ExportMessage) -> Option<i32> {
+    if let Some(ts) = msg.date_unixtime.as_deref().and_then(|d| d.parse::<i64>().ok()) {
+        return Some(ts as i32);
+    }
+    NaiveDateTime::parse_from_str(&msg.date, "%Y-%m-%dT%H:%M:%S")
+        .ok()
+        .map(|dt| dt.and_utc().timestamp() as i32)
+}
+
+/// Extract the numeric user id from an export sender id such as `user123456`.
+/// Senders that are channels (`channel...`) are not users and yield None.
+fn user_id_of(from_id: &str) -> Option<i64> {
+    from_id.strip_prefix("user")?.parse().ok()
+}
+
+/// Flatten an exported `text` field into plain text. Entity objects contribute
+/// their text; links whose target differs from the visible text keep the URL.
+fn flatten_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        serde_json::Value::Array(parts) => {
+            let mut text = String::new();
+            for part in parts {
+                match part {
+                    serde_json::Value::String(s) => text.push_str(s),
+                    serde_json::Value::Object(entity) => {
+                        let visible = entity.get("text").and_then(|t| t.as_str()).unwrap_or("");
+                        text.push_str(visible);
+                        if let Some(href) = entity.get("href").and_then(|h| h.as_str()) {
+                            if href != visible {
+                                text.push_str(&format!(" ({})", href));
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            text
+        }
+        _ => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn message(value: serde_json::Value) -> ExportMessage {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn flatten_text_joins_strings_and_entities() {
+        assert_eq!(flatten_text(&json!("plain")), "plain");
+        assert_eq!(flatten_text(&json!(["Hello ", {"type": "bold", "text": "world"}, "!"])), "Hello world!");
+        assert_eq!(flatten_text(&json!(null)), "");
+    }
+
+    #[test]
+    fn flatten_text_keeps_link_targets_that_differ() {
+        let text = json!([
+            "See ",
+            {"type": "text_link", "text": "the docs", "href": "https://example.com/docs"},
+            " or ",
+            {"type": "link", "text": "https://example.com", "href": "https://example.com"},
+        ]);
+        assert_eq!(flatten_text(&text), "See the docs (https://example.com/docs) or https://example.com");
+    }
+
+    #[test]
+    fn message_date_prefers_unixtime() {
+        let msg = message(json!({"id": 1, "type": "message", "date": "2024-03-01T12:00:00", "date_unixtime": "1709294400"}));
+        assert_eq!(message_date(&msg), Some(1709294400));
+        let msg = message(json!({"id": 1, "type": "message", "date": "2024-03-01T12:00:00"}));
+        assert_eq!(message_date(&msg), Some(1709294400));
+        let msg = message(json!({"id": 1, "type": "message", "date": "yesterday", "date_unixtime": "soon"}));
+        assert_eq!(message_date(&msg), None);
+    }
+
+    #[test]
+    fn only_user_senders_have_user_ids() {
+        assert_eq!(user_id_of("user123456"), Some(123456));
+        assert_eq!(user_id_of("channel123456"), None);
+        assert_eq!(user_id_of("user"), None);
+    }
+}
@@ -1,13 +1,38 @@
 use std::env;
 use log::info;
 use dotenv::dotenv;
+use chrono::{NaiveDate, TimeZone, Utc};
 
 mod telegram;
 mod database;
 mod ai;
 mod scheduler;
+mod shutdown;
+mod backfill;
+mod import;
+mod export;
+mod report;
+mod digest;
+mod notify;
+mod server;
+mod dashboard;
+mod tui;
+mod urgent;
+mod feedback;
+mod taxonomy;
+mod tags;
+mod apply;
+mod rules;
+mod watch;
+mod mentions;
+mod cleanup;
+mod analytics;
+mod people;
 
-#[tokio::main(flavor = "current_thread")]
+/// File holding the Telegram session between runs.
+const SESSION_FILE: &str = "telegram.session";
+
+#[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // Load environment variables from .env file
     dotenv().ok();
@@ -28,22 +53,350 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // Initialize SQLite database (creates file and tables if not exist)
     let db_path = "telegram_monitor.db";
-    let mut conn = database::init_db(db_path)?;
-
-    // Connect to Telegram (establish session, authenticate if needed)
-    let client = telegram::connect(api_id, &api_hash, "telegram.session").await?;
-    info!("Telegram client connected and authorized.");
+    let mut conn = database::init_db(db_path).await?;
 
-    // Check for "--review" CLI argument
+    // Dispatch on the CLI command (monitor mode when none is given)
     let args: Vec<String> = env::args().collect();
-    if args.len() > 1 && args[1] == "--review" {
-        // If review flag, output the stored categorized chats and urgent messages
-        database::print_report(&conn)?;
-    } else {
-        // Run the periodic monitoring loop (every 30 minutes)
-        info!("Starting monitoring loop. Press Ctrl+C to stop.");
-        scheduler::run_schedule(&client, &mut conn).await?;
+    match args.get(1).map(|s| s.as_str()) {
+        Some("--review") => {
+            // If review flag, output the stored categorized chats and urgent messages
+            // --review [--format text|json|markdown|html] [--out <file>]
+            //          [--since D] [--until D] [--category C] [--tag T] [--chat <pattern>] [--chat-exact C] [--only-urgent] [--only-duplicates]
+            //          [--sort name|activity|urgency] [--snippets N]
+            let formatter = report::formatter_for(&flag_value(&args, "--format").unwrap_or_else(|| "text".into()))?;
+            let options = report::ReportOptions {
+                query: database::ReportQuery {
+                    filter: chat_filter_args(&args)?,
+                    only_urgent: args.iter().any(|a| a == "--only-urgent"),
+                    only_duplicates: args.iter().any(|a| a == "--only-duplicates"),
+                    sort: match flag_value(&args, "--sort").as_deref() {
+                        None | Some("name") => database::ReportSort::Name,
+                        Some("activity") => database::ReportSort::Activity,
+                        Some("urgency") => database::ReportSort::Urgency,
+                        Some(other) => return Err(format!("Unknown sort \"{}\" (expected name, activity or urgency)", other).into()),
+                    },
+                },
+                snippets_per_chat: flag_value(&args, "--snippets").map(|n| n.parse::<usize>()).transpose()?,
+            };
+            let rendered = formatter.render(&report::build_report(&conn, &options).await?)?;
+            match flag_value(&args, "--out") {
+                Some(path) => {
+                    std::fs::write(&path, rendered)?;
+                    info!("Report written to {}", path);
+                }
+                None => print!("{}", rendered),
+            }
+        }
+        Some("reanalyze") => {
+            // reanalyze [<chat peer_id or name> | all] [--model <name>]
+            let target = positional_arg(&args, 2).filter(|t| t != "all");
+            let model = flag_value(&args, "--model").unwrap_or_else(ai::analysis_model);
+            // No Telegram session here, so Saved Messages notifications wait for the monitor loop
+            let taxonomy = taxonomy::Taxonomy::from_env()?;
+            let notifiers = notify::Notifiers::from_env(None);
+            let shutdown = shutdown::install();
+            scheduler::reanalyze(&mut conn, target.as_deref(), &model, &taxonomy, &notifiers, &shutdown).await?;
+        }
+        Some("import") => {
+            // import <path/to/result.json> [--chat <name>]
+            let path = positional_arg(&args, 2)
+                .ok_or("Usage: telegram-organizer import <path/to/result.json> [--chat <name>]")?;
+            let only_chat = flag_value(&args, "--chat");
+            let shutdown = shutdown::install();
+            import::import_export(&mut conn, &path, only_chat.as_deref(), &shutdown).await?;
+        }
+        Some("export") => {
+            // export --format jsonl|csv|markdown [--out <dir>] [--chat <name>] [--chat-exact <name>] [--category <name>] [--tag <name>]
+            //        [--since YYYY-MM-DD] [--until YYYY-MM-DD] [--embeddings]
+            let options = export::ExportOptions {
+                format: export::ExportFormat::parse(&flag_value(&args, "--format").unwrap_or_else(|| "jsonl".into()))?,
+                out_dir: flag_value(&args, "--out").unwrap_or_else(|| "export".into()).into(),
+                filter: chat_filter_args(&args)?,
+                with_embeddings: args.iter().any(|a| a == "--embeddings"),
+            };
+            export::run_export(&conn, &options).await?;
+        }
+        Some("digest") => {
+            // digest [--period daily|weekly|<N>h] [--force]
+            let period = digest::DigestPeriod::parse(&flag_value(&args, "--period").unwrap_or_else(|| "daily".into()))?;
+            let force = args.iter().any(|a| a == "--force");
+            let client = telegram::connect(api_id, &api_hash, SESSION_FILE).await?;
+            digest::send_digest(&client, &mut conn, &period, force).await?;
+            telegram::save_session(&client, SESSION_FILE)?;
+        }
+        Some("serve") => {
+            // serve [--addr <host:port>]
+            let addr = flag_value(&args, "--addr")
+                .or_else(|| env::var("SERVE_ADDR").ok())
+                .unwrap_or_else(|| server::DEFAULT_ADDR.to_string());
+            let token = env::var("API_TOKEN")
+                .map_err(|_| "Please set API_TOKEN to the bearer token API clients must present")?;
+            let shutdown = shutdown::install();
+            server::serve(conn.clone(), &addr, token, shutdown).await?;
+        }
+        Some("urgent") => {
+            // urgent list [--state <s1,s2>|all] [--chat C] [--category C] [--tag T] [--since D] [--until D] [--limit N]
+            // urgent ack|resolve|false-positive|reopen <chat peer_id> <msg_id> [--by <name>]
+            let usage = "Usage: telegram-organizer urgent list|ack|resolve|false-positive|reopen ...";
+            match positional_arg(&args, 2).as_deref() {
+                Some("list") => {
+                    let states = match flag_value(&args, "--state").as_deref() {
+                        None => database::UrgentState::OPEN.to_vec(),
+                        Some("all") => database::UrgentState::ALL.to_vec(),
+                        Some(list) => list.split(',').map(database::UrgentState::parse).collect::<Result<Vec<_>, _>>()?,
+                    };
+                    let limit = flag_value(&args, "--limit").map(|n| n.parse::<i64>()).transpose()?;
+                    urgent::list(&conn, &chat_filter_args(&args)?, &states, limit).await?;
+                }
+                Some(action) => {
+                    let state = match action {
+                        "ack" | "acknowledge" => database::UrgentState::Acknowledged,
+                        "resolve" => database::UrgentState::Resolved,
+                        "false-positive" | "fp" => database::UrgentState::FalsePositive,
+                        "reopen" => database::UrgentState::New,
+                        _ => return Err(usage.into()),
+                    };
+                    let chat_peer = positional_arg(&args, 3).ok_or(usage)?;
+                    let msg_id: i32 = positional_arg(&args, 4).ok_or(usage)?.parse()?;
+                    let by = flag_value(&args, "--by").unwrap_or_else(urgent::current_user);
+                    urgent::set_state(&mut conn, &chat_peer, msg_id, state, &by).await?;
+                }
+                None => return Err(usage.into()),
+            }
+        }
+        Some("feedback") => {
+            // feedback category <chat peer_id> <category> [--by <name>] | feedback category <chat peer_id> --clear
+            // feedback urgent|not-urgent <chat peer_id> <msg_id> [--by <name>]
+            // feedback list [--limit N]
+            let usage = "Usage: telegram-organizer feedback category|urgent|not-urgent|list ...";
+            let by = flag_value(&args, "--by").unwrap_or_else(urgent::current_user);
+            match positional_arg(&args, 2).as_deref() {
+                Some("list") => {
+                    let limit = flag_value(&args, "--limit").map(|n| n.parse::<i64>()).transpose()?;
+                    feedback::list(&conn, limit).await?;
+                }
+                Some("category") => {
+                    let chat_peer = positional_arg(&args, 3).ok_or(usage)?;
+                    if args.iter().any(|a| a == "--clear") {
+                        feedback::clear_category(&mut conn, &chat_peer).await?;
+                    } else {
+                        let category = positional_arg(&args, 4).ok_or(usage)?;
+                        let taxonomy = taxonomy::Taxonomy::from_env()?;
+                        let category = taxonomy.find(&category)
+                            .ok_or_else(|| format!("\"{}\" is not in the taxonomy (see `categories`)", category))?;
+                        feedback::set_category(&mut conn, &chat_peer, category, &by).await?;
+                    }
+                }
+                Some(action @ ("urgent" | "not-urgent")) => {
+                    let chat_peer = positional_arg(&args, 3).ok_or(usage)?;
+                    let msg_id: i32 = positional_arg(&args, 4).ok_or(usage)?.parse()?;
+                    if action == "urgent" {
+                        feedback::mark_urgent(&mut conn, &chat_peer, msg_id, &by).await?;
+                    } else {
+                        feedback::mark_not_urgent(&mut conn, &chat_peer, msg_id, &by).await?;
+                    }
+                }
+                _ => return Err(usage.into()),
+            }
+        }
+        Some("categories") => {
+            // categories: the taxonomy with chat counts per category
+            // categories remap [--dry-run]: move free-form categories onto the taxonomy
+            let taxonomy = taxonomy::Taxonomy::from_env()?;
+            match positional_arg(&args, 2).as_deref() {
+                None => taxonomy::print_counts(&conn, &taxonomy).await?,
+                Some("remap") => {
+                    let dry_run = args.iter().any(|a| a == "--dry-run");
+                    taxonomy::remap(&mut conn, &taxonomy, dry_run).await?;
+                }
+                Some(_) => return Err("Usage: telegram-organizer categories [remap [--dry-run]]".into()),
+            }
+        }
+        Some("tags") => {
+            // tags: every tag with its chat and message counts
+            // tags show <chat peer_id>
+            // tags add|remove <chat peer_id> <tag> [--msg <msg_id>]
+            let usage = "Usage: telegram-organizer tags [show <chat> | add|remove <chat> <tag> [--msg <msg_id>]]";
+            match positional_arg(&args, 2).as_deref() {
+                None => tags::print_counts(&conn).await?,
+                Some("show") => tags::show(&conn, &positional_arg(&args, 3).ok_or(usage)?).await?,
+                Some(action @ ("add" | "remove")) => {
+                    let chat_peer = positional_arg(&args, 3).ok_or(usage)?;
+                    let tag = positional_arg(&args, 4).ok_or(usage)?;
+                    let msg_id = flag_value(&args, "--msg").map(|n| n.parse::<i32>()).transpose()?;
+                    if action == "add" {
+                        tags::add(&mut conn, &chat_peer, msg_id, &tag).await?;
+                    } else {
+                        tags::remove(&mut conn, &chat_peer, msg_id, &tag).await?;
+                    }
+                }
+                Some(_) => return Err(usage.into()),
+            }
+        }
+        Some("apply") => {
+            // apply [--dry-run] [--no-folders] [--no-renames] [--accepted-only]: put chats into one
+            //       folder per category and rename chats we administer, after confirmation
+            // apply undo [--batch N] [--dry-run]: revert the last (or given) batch of applied changes
+            let dry_run = args.iter().any(|a| a == "--dry-run");
+            let client = telegram::connect(api_id, &api_hash, SESSION_FILE).await?;
+            match positional_arg(&args, 2).as_deref() {
+                None => {
+                    let options = apply::ApplyOptions {
+                        folders: !args.iter().any(|a| a == "--no-folders"),
+                        renames: !args.iter().any(|a| a == "--no-renames"),
+                        accepted_only: args.iter().any(|a| a == "--accepted-only"),
+                        dry_run,
+                    };
+                    let taxonomy = taxonomy::Taxonomy::from_env()?;
+                    apply::run_apply(&client, &mut conn, &taxonomy, &options).await?;
+                }
+                Some("undo") => {
+                    let batch = flag_value(&args, "--batch").map(|n| n.parse::<i64>()).transpose()?;
+                    apply::undo(&client, &mut conn, batch, dry_run).await?;
+                }
+                Some(_) => return Err("Usage: telegram-organizer apply [undo] [--dry-run] ...".into()),
+            }
+            telegram::save_session(&client, SESSION_FILE)?;
+        }
+        Some("rules") => {
+            // rules: the rules with their hit counts
+            // rules test [--chat C] [--category C] [--tag T] [--since D] [--until D] [--limit N]:
+            //       what the rules would do to stored chats and messages, without changing anything
+            let taxonomy = taxonomy::Taxonomy::from_env()?;
+            let mut rules = rules::RuleSet::from_env(&taxonomy)?;
+            match positional_arg(&args, 2).as_deref() {
+                None => rules::print_rules(&conn, &rules).await?,
+                Some("test") => {
+                    let limit = flag_value(&args, "--limit").map(|n| n.parse::<i64>()).transpose()?;
+                    rules::test(&conn, &mut rules, &chat_filter_args(&args)?, limit).await?;
+                }
+                Some(_) => return Err("Usage: telegram-organizer rules [test [--chat C] [--since D] [--limit N]]".into()),
+            }
+        }
+        Some("watch") => {
+            // watch: the watchlists with match counts per term
+            // watch hits [--list <name>] [--chat C] [--category C] [--tag T] [--since D] [--until D] [--limit N]
+            let watchlists = watch::Watchlists::from_env()?;
+            match positional_arg(&args, 2).as_deref() {
+                None => watch::print_lists(&conn, &watchlists).await?,
+                Some("hits") => {
+                    let limit = flag_value(&args, "--limit").map(|n| n.parse::<i64>()).transpose()?;
+                    let list = flag_value(&args, "--list");
+                    watch::print_hits(&conn, &chat_filter_args(&args)?, list.as_deref(), limit).await?;
+                }
+                Some(_) => return Err("Usage: telegram-organizer watch [hits [--list <name>] [--chat C] [--since D] [--limit N]]".into()),
+            }
+        }
+        Some("cleanup") => {
+            // cleanup [--all] [--chat C] [--category C] [--tag T]: activity per chat with suggestions to leave, archive or mute
+            // cleanup apply [--no-archive] [--no-mute] [--dry-run] [--chat C] [--category C] [--tag T]:
+            //       archive and mute the suggested chats, after confirmation
+            let config = cleanup::CleanupConfig::from_env();
+            let filter = chat_filter_args(&args)?;
+            match positional_arg(&args, 2).as_deref() {
+                None => cleanup::print_report(&conn, &filter, &config, args.iter().any(|a| a == "--all")).await?,
+                Some("apply") => {
+                    let mut actions = Vec::new();
+                    if !args.iter().any(|a| a == "--no-archive") {
+                        actions.push(cleanup::Action::Archive);
+                    }
+                    if !args.iter().any(|a| a == "--no-mute") {
+                        actions.push(cleanup::Action::Mute);
+                    }
+                    let dry_run = args.iter().any(|a| a == "--dry-run");
+                    let client = telegram::connect(api_id, &api_hash, SESSION_FILE).await?;
+                    cleanup::run_cleanup(&client, &mut conn, &filter, &config, &actions, dry_run).await?;
+                    telegram::save_session(&client, SESSION_FILE)?;
+                }
+                Some(_) => return Err("Usage: telegram-organizer cleanup [apply [--no-archive] [--no-mute] [--dry-run]] [--all] [--chat C]".into()),
+            }
+        }
+        Some("stats") => {
+            // stats [--days N | --since D [--until D]] [--chat C] [--category C] [--tag T] [--csv [--out <file>]]:
+            //       messages per day, busiest hours, top posters and member growth
+            let days = flag_value(&args, "--days").map(|n| n.parse::<i64>()).transpose()?.unwrap_or(analytics::DEFAULT_DAYS);
+            let filter = analytics::with_default_window(&chat_filter_args(&args)?, days);
+            if args.iter().any(|a| a == "--csv") {
+                analytics::write_csv(&mut conn, &filter, flag_value(&args, "--out").as_deref()).await?;
+            } else {
+                analytics::print_stats(&mut conn, &filter).await?;
+            }
+        }
+        Some("person") => {
+            // person <username|id> [--refresh] [--no-ai]: shared chats, activity, bio and interest profile
+            let target = positional_arg(&args, 2).ok_or("Usage: telegram-organizer person <username|id> [--refresh] [--no-ai]")?;
+            let user_id = people::resolve(&conn, &target).await?;
+            let refresh = args.iter().any(|a| a == "--refresh");
+            let use_ai = !args.iter().any(|a| a == "--no-ai");
+            people::print_person(&mut conn, user_id, refresh, use_ai).await?;
+        }
+        Some("people") => {
+            // people [<query>] [--limit N]: people matching the query by name, username or bio, most active first
+            let limit = flag_value(&args, "--limit").map(|n| n.parse::<i64>()).transpose()?.unwrap_or(people::DEFAULT_LIMIT);
+            people::search(&conn, positional_arg(&args, 2).as_deref(), limit).await?;
+        }
+        Some("tui") => {
+            // tui: browse chats, timelines, urgent messages and members in the terminal
+            tui::run(conn.clone()).await?;
+        }
+        Some("backfill") => {
+            // backfill [<chat peer_id or title> | all] [--since YYYY-MM-DD]
+            let target = positional_arg(&args, 2).filter(|t| t != "all");
+            let since = flag_value(&args, "--since").map(|d| parse_date_arg(&d)).transpose()?;
+            let client = telegram::connect(api_id, &api_hash, SESSION_FILE).await?;
+            info!("Telegram client connected and authorized.");
+            let shutdown = shutdown::install();
+            backfill::run_backfill(&client, &mut conn, target.as_deref(), since, &shutdown).await?;
+            telegram::save_session(&client, SESSION_FILE)?;
+        }
+        _ => {
+            // Connect to Telegram (establish session, authenticate if needed)
+            let session_file = SESSION_FILE;
+            let client = telegram::connect(api_id, &api_hash, session_file).await?;
+            info!("Telegram client connected and authorized.");
+
+            // Run the periodic monitoring loop (every 30 minutes)
+            info!("Starting monitoring loop. Press Ctrl+C to stop.");
+            let shutdown = shutdown::install();
+            scheduler::run_schedule(&client, &mut conn, shutdown).await?;
+            // Persist the session so the next start resumes without re-login
+            telegram::save_session(&client, session_file)?;
+            info!("Session saved. Shutdown complete.");
+        }
     }
 
     Ok(())
-} 
\ No newline at end of file
+}
+
+/// Get the value following a `--flag` argument, if present.
+fn flag_value(args: &[String], flag: &str) -> Option<String> {
+    args.iter()
+        .position(|a| a == flag)
+        .and_then(|i| args.get(i + 1))
+        .cloned()
+}
+
+/// Get the positional argument at `index` unless it is a `--flag`.
+fn positional_arg(args: &[String], index: usize) -> Option<String> {
+    args.get(index).filter(|a| !a.starts_with("--")).cloned()
+}
+
+/// Build a chat filter from the `--chat`, `--chat-exact`, `--category`, `--tag`, `--since` and `--until` arguments.
+fn chat_filter_args(args: &[String]) -> Result<database::ChatFilter, Box<dyn std::error::Error>> {
+    Ok(database::ChatFilter {
+        chat: flag_value(args, "--chat"),
+        chat_exact: flag_value(args, "--chat-exact"),
+        category: flag_value(args, "--category"),
+        tag: flag_value(args, "--tag"),
+        since: flag_value(args, "--since").map(|d| parse_date_arg(&d)).transpose()?,
+        until: flag_value(args, "--until").map(|d| parse_date_arg(&d)).transpose()?,
+    })
+}
+
+/// Parse a `YYYY-MM-DD` date argument into a UNIX timestamp (midnight UTC).
+fn parse_date_arg(value: &str) -> Result<i64, Box<dyn std::error::Error>> {
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|e| format!("Invalid date \"{}\" (expected YYYY-MM-DD): {}", value, e))?;
+    let midnight = date.and_hms_opt(0, 0, 0).ok_or("Invalid date")?;
+    Ok(Utc.from_utc_datetime(&midnight).timestamp())
+}
@@ -0,0 +1,99 @@
+use std::collections::HashSet;
+use grammers_client::Client;
+use libsql::Connection;
+use regex::{Regex, RegexBuilder};
+use crate::{telegram, database};
+use crate::telegram::MessageInfo;
+
+/// The logged-in account, as far as mention detection is concerned.
+pub struct Owner {
+    user_id: i64,
+    mention: Option<Regex>,  // `@username`, if the account has one
+}
+
+impl Owner {
+    /// Look up the logged-in account.
+    pub async fn fetch(client: &Client) -> Result<Self, Box<dyn std::error::Error>> {
+        let (user_id, username) = telegram::fetch_me(client).await?;
+        Ok(Owner::new(user_id, username.as_deref()))
+    }
+
+    pub fn new(user_id: i64, username: Option<&str>) -> Self {
+        // Usernames are word characters only, so `\b` ends the name; the start must not
+        // follow a word character either, so e-mail addresses don't count
+        let mention = username.filter(|u| !u.is_empty()).map(|u| {
+            RegexBuilder::new(&format!(r"(?:^|\W)@{}\b", regex::escape(u)))
+                .case_insensitive(true)
+                .build()
+                .expect("escaped username is a valid pattern")
+        });
+        Owner { user_id, mention }
+    }
+
+    /// True if the text mentions the account by `@username`.
+    pub fn mentioned_in(&self, text: &str) -> bool {
+        self.mention.as_ref().map_or(false, |re| re.is_match(text))
+    }
+}
+
+/// Flag freshly fetched messages that are addressed to the account owner: Telegram marked them
+/// as mentioning us, they name our `@username`, or they reply to one of our own messages (in this
+/// batch or already stored). Our own messages are never flagged.
+/// Returns the number of flagged messages.
+pub async fn detect(conn: &Connection, owner: &Owner, chat_peer: &str, messages: &mut [MessageInfo])
+    -> Result<usize, Box<dyn std::error::Error>>
+{
+    let mut own: HashSet<i32> = messages.iter()
+        .filter(|m| m.outgoing)
+        .map(|m| m.msg_id)
+        .collect();
+    let unresolved: Vec<i32> = messages.iter()
+        .filter_map(|m| m.reply_to)
+        .filter(|id| !own.contains(id))
+        .collect();
+    own.extend(database::get_outgoing_ids(conn, chat_peer, &unresolved).await?);
+
+    let mut flagged = 0;
+    for msg in messages.iter_mut() {
+        if msg.outgoing || msg.sender_id == Some(owner.user_id) {
+            msg.mentions_me = false;
+            continue;
+        }
+        msg.mentions_me = msg.mentions_me
+            || owner.mentioned_in(&msg.text)
+            || msg.reply_to.map_or(false, |id| own.contains(&id));
+        if msg.mentions_me {
+            flagged += 1;
+        }
+    }
+    Ok(flagged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mentions_match_the_username_anywhere() {
+        let owner = Owner::new(1, Some("alice"));
+        assert!(owner.mentioned_in("@alice can you look?"));
+        assert!(owner.mentioned_in("thanks, @Alice!"));
+        assert!(owner.mentioned_in("cc:@alice"));
+        assert!(owner.mentioned_in("first line\n@alice second"));
+    }
+
+    #[test]
+    fn longer_names_and_email_addresses_are_not_mentions() {
+        let owner = Owner::new(1, Some("alice"));
+        assert!(!owner.mentioned_in("@alicebob said so"));
+        assert!(!owner.mentioned_in("@alice_2 said so"));
+        assert!(!owner.mentioned_in("write to bob@alice.com"));
+        assert!(!owner.mentioned_in("alice without the at sign"));
+    }
+
+    #[test]
+    fn accounts_without_a_username_are_never_mentioned() {
+        assert!(!Owner::new(1, None).mentioned_in("@alice"));
+        assert!(!Owner::new(1, Some("")).mentioned_in("@ hello"));
+    }
+}
@@ -0,0 +1,356 @@
+use std::process::Stdio;
+use log::{info, error, warn};
+use grammers_client::Client;
+use libsql::Connection;
+use chrono::{Local, Timelike, Utc};
+use futures::future::LocalBoxFuture;
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+use tokio::sync::Mutex;
+use crate::{digest, telegram, database};
+use crate::database::PendingNotification;
+use crate::report::format_timestamp;
+
+/// Default number of notifications each sink may deliver per hour.
+const DEFAULT_RATE_LIMIT_PER_HOUR: i64 = 20;
+/// Urgent messages flagged longer ago than this are no longer delivered.
+const DEFAULT_LOOKBACK_HOURS: i64 = 24;
+
+/// A destination for notifications about urgent messages and watchlist matches.
+pub trait Notifier {
+    /// Short, stable name used in configuration and in the notification log.
+    fn name(&self) -> &str;
+    /// Deliver one notification.
+    fn send<'a>(&'a self, notification: &'a PendingNotification) -> LocalBoxFuture<'a, Result<(), Box<dyn std::error::Error>>>;
+}
+
+/// Sends notifications to the account's own Saved Messages.
+pub struct SavedMessagesNotifier {
+    client: Client,
+}
+
+impl Notifier for SavedMessagesNotifier {
+    fn name(&self) -> &str {
+        "saved"
+    }
+
+    fn send<'a>(&'a self, n: &'a PendingNotification) -> LocalBoxFuture<'a, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            // Chat names, reasons and message text are user-supplied; keep them from breaking the markup
+            let text = format!("**{}** ({})\\\n{}", digest::md_escape(&headline(n)), format_timestamp(n.date), digest::md_escape(&n.text));
+            telegram::send_to_saved_messages(&self.client, &text).await
+        })
+    }
+}
+
+/// POSTs each notification as JSON to a URL.
+pub struct WebhookNotifier {
+    url: String,
+    http: reqwest::Client,
+}
+
+impl Notifier for WebhookNotifier {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn send<'a>(&'a self, n: &'a PendingNotification) -> LocalBoxFuture<'a, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let body = serde_json::json!({
+                "kind": n.kind,
+                "reason": n.reason,
+                "mentions_me": n.mentions_me,
+                "chat_peer": n.chat_peer,
+                "chat_name": n.chat_name,
+                "msg_id": n.msg_id,
+                "date": n.date,
+                "datetime": format_timestamp(n.date),
+                "text": n.text,
+            });
+            self.http.post(&self.url).json(&body).send().await?.error_for_status()?;
+            Ok(())
+        })
+    }
+}
+
+/// Shows a desktop notification through the D-Bus notification service (`notify-send`).
+pub struct DesktopNotifier;
+
+impl Notifier for DesktopNotifier {
+    fn name(&self) -> &str {
+        "desktop"
+    }
+
+    fn send<'a>(&'a self, n: &'a PendingNotification) -> LocalBoxFuture<'a, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let status = Command::new("notify-send")
+                .arg("--urgency=critical")
+                .arg("--app-name=telegram-organizer")
+                .arg(headline(n))
+                .arg(&n.text)
+                .status()
+                .await?;
+            if !status.success() {
+                return Err(format!("notify-send exited with {}", status).into());
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Runs a local shell command per notification. The notification is passed as JSON on
+/// stdin and as NOTIFY_* environment variables.
+pub struct CommandNotifier {
+    command: String,
+}
+
+impl Notifier for CommandNotifier {
+    fn name(&self) -> &str {
+        "command"
+    }
+
+    fn send<'a>(&'a self, n: &'a PendingNotification) -> LocalBoxFuture<'a, Result<(), Box<dyn std::error::Error>>> {
+        Box::pin(async move {
+            let mut child = Command::new("sh")
+                .arg("-c")
+                .arg(&self.command)
+                .env("NOTIFY_KIND", &n.kind)
+                .env("NOTIFY_REASON", n.reason.as_deref().unwrap_or(""))
+                .env("NOTIFY_MENTIONS_ME", if n.mentions_me { "1" } else { "0" })
+                .env("NOTIFY_CHAT_PEER", &n.chat_peer)
+                .env("NOTIFY_CHAT_NAME", &n.chat_name)
+                .env("NOTIFY_MSG_ID", n.msg_id.to_string())
+                .env("NOTIFY_DATE", n.date.to_string())
+                .env("NOTIFY_TEXT", &n.text)
+                .stdin(Stdio::piped())
+                .spawn()?;
+            if let Some(mut stdin) = child.stdin.take() {
+                stdin.write_all(serde_json::to_string(n)?.as_bytes()).await?;
+            }
+            let status = child.wait().await?;
+            if !status.success() {
+                return Err(format!("notification command exited with {}", status).into());
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Helper: first line of a notification, e.g. `Urgent in Team`, `Urgent for you in Team`
+/// or `Watchlist hit in Team (outage: down)`.
+fn headline(n: &PendingNotification) -> String {
+    let to_you = if n.mentions_me { " for you" } else { "" };
+    match (n.kind.as_str(), &n.reason) {
+        ("watch", Some(reason)) => format!("Watchlist hit{} in {} ({})", to_you, n.chat_name, reason),
+        ("watch", None) => format!("Watchlist hit{} in {}", to_you, n.chat_name),
+        _ => format!("Urgent{} in {}", to_you, n.chat_name),
+    }
+}
+
+/// Daily window (local time) during which a sink holds back notifications.
+#[derive(Debug, Clone, Copy)]
+struct QuietHours {
+    start: u32,  // minutes after midnight
+    end: u32,
+}
+
+impl QuietHours {
+    /// Parse `HH-HH` or `HH:MM-HH:MM`, e.g. `22-07` or `22:30-07:00`.
+    fn parse(value: &str) -> Option<Self> {
+        let (start, end) = value.split_once('-')?;
+        Some(QuietHours { start: parse_clock(start)?, end: parse_clock(end)? })
+    }
+
+    /// True if the given local time (minutes after midnight) falls in the window.
+    fn contains(&self, minute: u32) -> bool {
+        if self.start <= self.end {
+            minute >= self.start && minute < self.end
+        } else {
+            // Window spans midnight
+            minute >= self.start || minute < self.end
+        }
+    }
+}
+
+/// Helper: parse `HH` or `HH:MM` into minutes after midnight.
+fn parse_clock(value: &str) -> Option<u32> {
+    let (h, m) = match value.trim().split_once(':') {
+        Some((h, m)) => (h.parse::<u32>().ok()?, m.parse::<u32>().ok()?),
+        None => (value.trim().parse::<u32>().ok()?, 0),
+    };
+    (h < 24 && m < 60).then_some(h * 60 + m)
+}
+
+/// A configured sink with its delivery policy.
+struct Sink {
+    notifier: Box<dyn Notifier>,
+    rate_limit_per_hour: i64,
+    quiet_hours: Option<QuietHours>,
+}
+
+/// All configured notification sinks.
+/// Configuration comes from the environment:
+/// - NOTIFY_SINKS: comma-separated list of `saved`, `webhook`, `desktop`, `command`
+/// - NOTIFY_WEBHOOK_URL / NOTIFY_COMMAND: targets of the webhook and command sinks
+/// - NOTIFY_RATE_LIMIT_PER_HOUR, NOTIFY_QUIET_HOURS (e.g. `22-07`, local time): defaults for all sinks,
+///   overridable per sink as NOTIFY_<SINK>_RATE_LIMIT_PER_HOUR / NOTIFY_<SINK>_QUIET_HOURS
+/// - NOTIFY_LOOKBACK_HOURS: how long an undelivered urgent message or watchlist match stays eligible (default 24)
+pub struct Notifiers {
+    sinks: Vec<Sink>,
+    lookback_hours: i64,
+    // Serializes dispatch so concurrent analyses can't deliver the same message twice
+    dispatch_lock: Mutex<()>,
+}
+
+impl Notifiers {
+    /// Build the configured sinks. The Saved Messages sink needs a Telegram `client`;
+    /// without one it is skipped.
+    pub fn from_env(client: Option<&Client>) -> Self {
+        let mut sinks = Vec::new();
+        let names = std::env::var("NOTIFY_SINKS").unwrap_or_default();
+        for name in names.split(',').map(|n| n.trim().to_lowercase()).filter(|n| !n.is_empty()) {
+            let notifier: Box<dyn Notifier> = match name.as_str() {
+                "saved" => match client {
+                    Some(client) => Box::new(SavedMessagesNotifier { client: client.clone() }),
+                    None => {
+                        info!("Saved Messages notifications need a Telegram connection; skipped for this command.");
+                        continue;
+                    }
+                },
+                "webhook" => match std::env::var("NOTIFY_WEBHOOK_URL") {
+                    Ok(url) => Box::new(WebhookNotifier { url, http: reqwest::Client::new() }),
+                    Err(_) => {
+                        warn!("NOTIFY_SINKS includes webhook but NOTIFY_WEBHOOK_URL is not set; skipped.");
+                        continue;
+                    }
+                },
+                "desktop" => Box::new(DesktopNotifier),
+                "command" => match std::env::var("NOTIFY_COMMAND") {
+                    Ok(command) => Box::new(CommandNotifier { command }),
+                    Err(_) => {
+                        warn!("NOTIFY_SINKS includes command but NOTIFY_COMMAND is not set; skipped.");
+                        continue;
+                    }
+                },
+                other => {
+                    warn!("Unknown notification sink \"{}\"; skipped.", other);
+                    continue;
+                }
+            };
+            let key = name.to_uppercase();
+            let rate_limit_per_hour = sink_setting(&key, "RATE_LIMIT_PER_HOUR")
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(DEFAULT_RATE_LIMIT_PER_HOUR);
+            let quiet_hours = sink_setting(&key, "QUIET_HOURS").and_then(|v| {
+                let parsed = QuietHours::parse(&v);
+                if parsed.is_none() {
+                    warn!("Invalid quiet hours \"{}\" for {} notifications (expected e.g. 22-07)", v, name);
+                }
+                parsed
+            });
+            sinks.push(Sink { notifier, rate_limit_per_hour, quiet_hours });
+        }
+        let lookback_hours = std::env::var("NOTIFY_LOOKBACK_HOURS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(DEFAULT_LOOKBACK_HOURS);
+        Notifiers { sinks, lookback_hours, dispatch_lock: Mutex::new(()) }
+    }
+
+    /// Deliver pending urgent-message and watchlist notifications to every sink. Each message is sent at
+    /// most once per sink; messages held back by quiet hours or the rate limit stay pending
+    /// and go out on a later dispatch.
+    pub async fn dispatch(&self, conn: &mut Connection) {
+        if self.sinks.is_empty() {
+            return;
+        }
+        let _guard = self.dispatch_lock.lock().await;
+        let now = Utc::now().timestamp();
+        let local = Local::now();
+        let minute = local.hour() * 60 + local.minute();
+        for sink in &self.sinks {
+            let name = sink.notifier.name();
+            if sink.quiet_hours.map_or(false, |q| q.contains(minute)) {
+                continue;
+            }
+            let sent_last_hour = match database::count_notifications_since(conn, name, now - 3600).await {
+                Ok(count) => count,
+                Err(e) => {
+                    error!("Failed to read notification log for {}: {}", name, e);
+                    continue;
+                }
+            };
+            let budget = sink.rate_limit_per_hour - sent_last_hour;
+            if budget <= 0 {
+                continue;
+            }
+            let pending = match database::get_pending_notifications(conn, name, now - self.lookback_hours * 3600, budget).await {
+                Ok(pending) => pending,
+                Err(e) => {
+                    error!("Failed to load pending notifications for {}: {}", name, e);
+                    continue;
+                }
+            };
+            for n in &pending {
+                if let Err(e) = sink.notifier.send(n).await {
+                    // Left pending; retried on the next dispatch
+                    error!("Failed to send {} notification for message {} in {}: {}", name, n.msg_id, n.chat_name, e);
+                    break;
+                }
+                if let Err(e) = database::record_notification(conn, name, &n.chat_peer, n.msg_id).await {
+                    error!("Failed to record {} notification for message {}: {}", name, n.msg_id, e);
+                }
+            }
+        }
+    }
+}
+
+/// Helper: per-sink setting NOTIFY_<SINK>_<KEY>, falling back to NOTIFY_<KEY>.
+fn sink_setting(sink: &str, key: &str) -> Option<String> {
+    std::env::var(format!("NOTIFY_{}_{}", sink, key))
+        .or_else(|_| std::env::var(format!("NOTIFY_{}", key)))
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_clock_accepts_hours_and_minutes() {
+        assert_eq!(parse_clock("7"), Some(7 * 60));
+        assert_eq!(parse_clock(" 22:30 "), Some(22 * 60 + 30));
+        assert_eq!(parse_clock("00:00"), Some(0));
+        assert_eq!(parse_clock("24"), None);
+        assert_eq!(parse_clock("12:60"), None);
+        assert_eq!(parse_clock("noon"), None);
+    }
+
+    #[test]
+    fn quiet_hours_within_a_day() {
+        let quiet = QuietHours::parse("13-15").unwrap();
+        assert!(!quiet.contains(12 * 60 + 59));
+        assert!(quiet.contains(13 * 60));
+        assert!(quiet.contains(14 * 60 + 59));
+        assert!(!quiet.contains(15 * 60));
+    }
+
+    #[test]
+    fn quiet_hours_across_midnight() {
+        let quiet = QuietHours::parse("22:30-07:00").unwrap();
+        assert!(!quiet.contains(22 * 60 + 29));
+        assert!(quiet.contains(22 * 60 + 30));
+        assert!(quiet.contains(23 * 60 + 59));
+        assert!(quiet.contains(0));
+        assert!(quiet.contains(6 * 60 + 59));
+        assert!(!quiet.contains(7 * 60));
+        assert!(!quiet.contains(12 * 60));
+    }
+
+    #[test]
+    fn invalid_quiet_hours_are_rejected() {
+        assert!(QuietHours::parse("22").is_none());
+        assert!(QuietHours::parse("22-25").is_none());
+        assert!(QuietHours::parse("late-early").is_none());
+    }
+}
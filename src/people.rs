@@ -0,0 +1,211 @@
+use std::collections::HashMap;
+use log::{info, warn};
+use grammers_client::Client;
+use libsql::Connection;
+use chrono::Utc;
+use crate::{ai, database, telegram};
+use crate::database::Person;
+use crate::report::format_timestamp;
+use crate::shutdown::Shutdown;
+
+/// Number of people listed by `people` when no `--limit` is given.
+pub const DEFAULT_LIMIT: i64 = 25;
+/// Latest messages the interest profile is generated from.
+const PROFILE_MESSAGES: i64 = 150;
+/// Fewest messages worth generating an interest profile from.
+const MIN_PROFILE_MESSAGES: usize = 5;
+/// Longest excerpt of the last message shown.
+const EXCERPT_CHARS: usize = 200;
+/// Longest chat name shown in the shared chats table.
+const NAME_CHARS: usize = 32;
+/// Default hours a fetched profile is kept before it is looked up again (PROFILE_TTL_HOURS).
+const DEFAULT_PROFILE_TTL_HOURS: i64 = 7 * 24;
+/// Default number of profile lookups per monitor cycle (PROFILE_BUDGET).
+const DEFAULT_PROFILE_BUDGET: i64 = 30;
+/// Pause between profile lookups to stay clear of rate limits.
+const PROFILE_DELAY_SECS: u64 = 2;
+
+/// How full profiles are refreshed. Each value can be overridden via the matching environment variable.
+#[derive(Debug, Clone)]
+pub struct ProfileConfig {
+    pub ttl_hours: i64,  // PROFILE_TTL_HOURS
+    pub budget: i64,     // PROFILE_BUDGET (0 turns lookups off)
+}
+
+impl ProfileConfig {
+    pub fn from_env() -> Self {
+        ProfileConfig {
+            ttl_hours: env_value("PROFILE_TTL_HOURS", DEFAULT_PROFILE_TTL_HOURS),
+            budget: env_value("PROFILE_BUDGET", DEFAULT_PROFILE_BUDGET),
+        }
+    }
+}
+
+/// Helper: read a number from the environment, or use the default.
+fn env_value<T: std::str::FromStr>(key: &str, default: T) -> T {
+    std::env::var(key).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
+/// Fetch the full profiles (bio, flags, common chats) of up to `budget` members whose stored
+/// profile is missing or older than the TTL, never fetched first. A failed lookup counts as a
+/// fetch, so the user isn't retried before the TTL runs out. Stops early on shutdown.
+/// Returns the number of profiles stored.
+pub async fn refresh_profiles(client: &Client, conn: &mut Connection, config: &ProfileConfig, shutdown: &Shutdown)
+    -> Result<usize, Box<dyn std::error::Error>>
+{
+    if config.budget <= 0 {
+        return Ok(0);
+    }
+    let fetched_before = Utc::now().timestamp() - config.ttl_hours * 3600;
+    let due = database::get_profiles_due(conn, fetched_before, config.budget).await?;
+    let mut stored = 0;
+    for (i, (user_id, access_hash, name)) in due.iter().enumerate() {
+        if shutdown.is_requested() {
+            break;
+        }
+        if i > 0 {
+            tokio::time::sleep(tokio::time::Duration::from_secs(PROFILE_DELAY_SECS)).await;
+        }
+        match telegram::get_user_info(client, *user_id, *access_hash).await {
+            Ok(profile) => {
+                database::save_user_profile(conn, *user_id, &profile).await?;
+                stored += 1;
+            }
+            Err(e) => {
+                warn!("Could not fetch the profile of {}: {}", name, e);
+                database::touch_user_profile(conn, *user_id).await?;
+            }
+        }
+    }
+    if !due.is_empty() {
+        info!("Fetched {} of {} due user profile(s)", stored, due.len());
+    }
+    Ok(stored)
+}
+
+/// Resolve a `person` argument: a numeric user id, or a username with or without `@`.
+pub async fn resolve(conn: &Connection, target: &str) -> Result<i64, Box<dyn std::error::Error>> {
+    if let Ok(id) = target.parse::<i64>() {
+        return Ok(id);
+    }
+    let username = target.trim_start_matches('@');
+    database::find_user_by_username(conn, username).await?
+        .ok_or_else(|| format!("No stored user with username @{}", username).into())
+}
+
+/// Print everything stored about a person: name, username and bio, the chats they share with
+/// us (as members or message senders) with their message counts there, their last message, and
+/// an LLM profile of their topics of interest. The profile is cached and regenerated when they
+/// have written since, or when `refresh` is set; `use_ai` false shows the cached one only.
+pub async fn print_person(conn: &mut Connection, user_id: i64, refresh: bool, use_ai: bool)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let mut person = database::get_person(conn, user_id).await?
+        .ok_or_else(|| format!("Nothing stored about user {}", user_id))?;
+    println!("{}", display_name(&person));
+    println!("  Id: {}", person.user_id);
+    if let Some(flags) = flags(&person) {
+        println!("  Account: {}", flags);
+    }
+    if let Some(bio) = person.bio.as_deref().filter(|b| !b.is_empty()) {
+        println!("  Bio: {}", bio);
+    }
+    println!("  Messages: {} in {} chat(s)", person.messages, person.chats);
+    if let Some(common) = person.common_chats_count {
+        println!("  Chats in common (per Telegram): {}", common);
+    }
+
+    let names: HashMap<String, String> = database::get_chats(conn).await?.into_iter().collect();
+    let posted = database::get_sender_chats(conn, user_id).await?;
+    let mut shared: Vec<(String, i64, Option<i32>)> = posted.into_iter()
+        .map(|(peer, messages, last)| (peer, messages, Some(last)))
+        .collect();
+    // Chats they're a member of but never wrote in come last
+    for peer in database::get_user_chats(conn, user_id).await? {
+        if !shared.iter().any(|(p, _, _)| *p == peer) {
+            shared.push((peer, 0, None));
+        }
+    }
+    if !shared.is_empty() {
+        println!("\nShared chats");
+        println!("  {:<w$}  {:>6}  {}", "Chat", "Msgs", "Last message", w = NAME_CHARS);
+        for (peer, messages, last) in &shared {
+            let name: String = names.get(peer).unwrap_or(peer).chars().take(NAME_CHARS).collect();
+            println!("  {:<w$}  {:>6}  {}", name, messages, last.map(format_timestamp).unwrap_or_else(|| "-".into()), w = NAME_CHARS);
+        }
+    }
+
+    let messages = database::get_sender_messages(conn, user_id, PROFILE_MESSAGES).await?;
+    if let Some((chat, date, text)) = messages.first() {
+        let excerpt: String = text.chars().take(EXCERPT_CHARS).collect();
+        let ellipsis = if text.chars().count() > EXCERPT_CHARS { "…" } else { "" };
+        println!("\nLast message ({} in {}):\n  {}{}", format_timestamp(*date), chat, excerpt.replace('\n', " "), ellipsis);
+    }
+
+    let stale = match (person.topics_at, person.last_message_at) {
+        (None, _) => true,
+        (Some(at), Some(last)) => last as i64 > at,
+        (Some(_), None) => false,
+    };
+    if use_ai && (refresh || stale) && messages.len() >= MIN_PROFILE_MESSAGES {
+        let name = display_name(&person);
+        let sample: Vec<(String, String)> = messages.iter().map(|(chat, _, text)| (chat.clone(), text.clone())).collect();
+        match ai::profile_person(&ai::analysis_model(), &name, &sample).await {
+            Ok(topics) => {
+                database::set_person_topics(conn, user_id, &topics).await?;
+                person.topics = Some(topics);
+            }
+            Err(e) => warn!("Could not generate a profile for {}: {}", name, e),
+        }
+    }
+    match person.topics.as_deref() {
+        Some(topics) => {
+            println!("\nInterests");
+            for line in topics.lines() {
+                println!("  {}", line);
+            }
+        }
+        None if messages.len() < MIN_PROFILE_MESSAGES => println!("\nToo few messages for an interest profile."),
+        None => {}
+    }
+    Ok(())
+}
+
+/// List people whose name, username or bio matches `query` (all people without one), most
+/// active first.
+pub async fn search(conn: &Connection, query: Option<&str>, limit: i64) -> Result<(), Box<dyn std::error::Error>> {
+    let people = database::search_people(conn, query, limit).await?;
+    if people.is_empty() {
+        println!("No matching people.");
+        return Ok(());
+    }
+    println!("{:>12}  {:>6}  {:>5}  {:<19}  {}", "Id", "Msgs", "Chats", "Last message", "Name");
+    for p in &people {
+        let flags = flags(p).map(|f| format!(" [{}]", f)).unwrap_or_default();
+        println!("{:>12}  {:>6}  {:>5}  {:<19}  {}{}", p.user_id, p.messages, p.chats,
+            p.last_message_at.map(format_timestamp).unwrap_or_else(|| "-".into()), display_name(p), flags);
+        if let Some(bio) = p.bio.as_deref().filter(|b| !b.is_empty()) {
+            println!("{:>48}{}", "", bio.replace('\n', " "));
+        }
+    }
+    Ok(())
+}
+
+/// Helper: the account flags worth pointing out, if any.
+fn flags(person: &Person) -> Option<String> {
+    let flags: Vec<&str> = [(person.bot, "bot"), (person.verified, "verified"), (person.premium, "premium")]
+        .iter()
+        .filter(|(set, _)| *set)
+        .map(|(_, label)| *label)
+        .collect();
+    (!flags.is_empty()).then(|| flags.join(", "))
+}
+
+/// Helper: the person's name with their `@username`, or a placeholder when neither is known.
+fn display_name(person: &Person) -> String {
+    let name = person.name.clone().unwrap_or_else(|| format!("user {}", person.user_id));
+    match person.username.as_deref() {
+        Some(username) => format!("{} (@{})", name, username),
+        None => name,
+    }
+}
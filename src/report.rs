@@ -0,0 +1,400 @@
+use std::collections::BTreeMap;
+use libsql::Connection;
+use serde::Serialize;
+use chrono::{DateTime, Utc};
+use textwrap;
+use crate::database::{self, ChatRecord, ReportQuery};
+use crate::taxonomy;
+
+/// Most recent messages addressed to the user shown at the top of the report.
+const MENTIONS_SHOWN: i64 = 20;
+
+/// Typed review report: the latest messages addressed to the user, every chat with its analysis,
+/// members and urgent messages, plus per-category counts and duplicate-topic groups.
+/// Rendered by a `ReportFormatter`.
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub generated_at: String,
+    pub mentions: Vec<Mention>,
+    pub chats: Vec<ChatReport>,
+    pub categories: Vec<CategoryCount>,
+    pub duplicates: Vec<DuplicateGroup>,
+}
+
+/// What the report covers.
+#[derive(Debug, Clone, Default)]
+pub struct ReportOptions {
+    pub query: ReportQuery,
+    pub snippets_per_chat: Option<usize>,  // most recent urgent messages shown per chat (all if None)
+}
+
+/// One chat in the report. Counts cover the report's time window.
+#[derive(Debug, Serialize)]
+pub struct ChatReport {
+    #[serde(flatten)]
+    pub chat: ChatRecord,
+    pub message_count: i64,
+    pub urgent_count: i64,
+    pub mention_count: i64,
+    pub last_activity: Option<String>,
+    pub members: Vec<Member>,
+    pub urgent: Vec<UrgentMessage>,
+}
+
+/// A chat member.
+#[derive(Debug, Serialize)]
+pub struct Member {
+    pub user_id: i64,
+    pub name: String,
+    pub username: Option<String>,
+    pub bio: Option<String>,
+}
+
+/// A message flagged urgent by the analyzer that is still open (new or acknowledged).
+#[derive(Debug, Serialize)]
+pub struct UrgentMessage {
+    pub msg_id: i32,
+    pub date: i32,
+    pub datetime: String,
+    pub text: String,
+}
+
+/// A message that mentions the user or replies to one of their messages.
+#[derive(Debug, Serialize)]
+pub struct Mention {
+    pub chat_peer: String,
+    pub chat_name: String,
+    pub msg_id: i32,
+    pub date: i32,
+    pub datetime: String,
+    pub text: String,
+    pub urgent: bool,
+}
+
+/// Number of chats per category.
+#[derive(Debug, Serialize)]
+pub struct CategoryCount {
+    pub category: String,
+    pub chats: usize,
+}
+
+/// Chats sharing a category, i.e. likely duplicate topics.
+#[derive(Debug, Serialize)]
+pub struct DuplicateGroup {
+    pub category: String,
+    pub chats: Vec<String>,
+}
+
+/// Build the report from the database. Chat selection, ordering and the
+/// per-chat snippet limit are applied by the queries themselves.
+pub async fn build_report(conn: &Connection, options: &ReportOptions) -> Result<Report, Box<dyn std::error::Error>> {
+    let filter = &options.query.filter;
+    let mut chats = Vec::new();
+    for activity in database::get_report_chats(conn, &options.query).await? {
+        let chat = activity.chat;
+        let members = database::get_chat_members(conn, &chat.peer_id).await?
+            .into_iter()
+            .map(|(user_id, name, username, bio)| Member { user_id, name, username, bio })
+            .collect();
+        let urgent = database::get_urgent_snippets(conn, &chat.peer_id, filter, options.snippets_per_chat).await?
+            .into_iter()
+            .map(|m| UrgentMessage { msg_id: m.msg_id, date: m.date, datetime: format_timestamp(m.date), text: m.text })
+            .collect();
+        chats.push(ChatReport {
+            chat,
+            message_count: activity.message_count,
+            urgent_count: activity.urgent_count,
+            mention_count: activity.mention_count,
+            last_activity: activity.last_activity.map(format_timestamp),
+            members,
+            urgent,
+        });
+    }
+
+    // Group chats by category (case-insensitive, as duplicate detection does)
+    let mut by_category: BTreeMap<String, (String, Vec<String>)> = BTreeMap::new();
+    for c in &chats {
+        let category = c.chat.category.clone().unwrap_or_else(|| "Uncategorized".into());
+        by_category.entry(category.to_lowercase())
+            .or_insert_with(|| (category, Vec::new()))
+            .1.push(c.chat.name.clone());
+    }
+    let categories = by_category.values()
+        .map(|(category, names)| CategoryCount { category: category.clone(), chats: names.len() })
+        .collect();
+    let duplicates = by_category.into_values()
+        .filter(|(category, names)| names.len() > 1 && category != "Uncategorized" && category != taxonomy::OTHER)
+        .map(|(category, chats)| DuplicateGroup { category, chats })
+        .collect();
+
+    let mentions = database::get_mentions(conn, filter, MENTIONS_SHOWN).await?
+        .into_iter()
+        .map(|hit| Mention {
+            chat_peer: hit.message.chat_peer,
+            chat_name: hit.chat_name,
+            msg_id: hit.message.msg_id,
+            date: hit.message.date,
+            datetime: format_timestamp(hit.message.date),
+            text: hit.message.text,
+            urgent: hit.message.urgent,
+        })
+        .collect();
+
+    Ok(Report {
+        generated_at: Utc::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+        mentions,
+        chats,
+        categories,
+        duplicates,
+    })
+}
+
+/// Renders a report into some output format.
+pub trait ReportFormatter {
+    fn render(&self, report: &Report) -> Result<String, Box<dyn std::error::Error>>;
+}
+
+/// Get the formatter for a format name given on the command line.
+pub fn formatter_for(name: &str) -> Result<Box<dyn ReportFormatter>, Box<dyn std::error::Error>> {
+    match name.to_lowercase().as_str() {
+        "text" | "txt" => Ok(Box::new(TextFormatter)),
+        "json" => Ok(Box::new(JsonFormatter)),
+        "md" | "markdown" => Ok(Box::new(MarkdownFormatter)),
+        "html" => Ok(Box::new(HtmlFormatter)),
+        other => Err(format!("Unknown report format \"{}\" (expected text, json, markdown or html)", other).into()),
+    }
+}
+
+/// Plain text, as printed by `--review`.
+pub struct TextFormatter;
+
+impl ReportFormatter for TextFormatter {
+    fn render(&self, report: &Report) -> Result<String, Box<dyn std::error::Error>> {
+        let mut out = String::from("=== Telegram Chats Report ===\n");
+        if !report.mentions.is_empty() {
+            out += "\nAddressed to you:\n";
+            for m in &report.mentions {
+                out += &format!(" - [{}{}] {}: {}\n", m.datetime, if m.urgent { ", URGENT" } else { "" },
+                    m.chat_name, snippet(&m.text.replace('\n', " "), 60));
+            }
+        }
+        for c in &report.chats {
+            let chat = &c.chat;
+            out += &format!("\nChat: {}{}\n", chat.name, if chat.duplicate { " (Duplicate Topic)" } else { "" });
+            out += &format!(" - Category: {}\n", chat.category.as_deref().unwrap_or("Uncategorized"));
+            out += &format!(" - Suggested Name: {}\n", chat.suggested_name.as_deref().unwrap_or("-"));
+            if !chat.tags.is_empty() {
+                out += &format!(" - Tags: {}\n", chat.tags.join(", "));
+            }
+            out += &format!(" - Duplicate: {}\n", if chat.duplicate { "Yes" } else { "No" });
+            out += &format!(" - Activity: {} messages, {} urgent, {} to you, last {}\n",
+                c.message_count, c.urgent_count, c.mention_count, c.last_activity.as_deref().unwrap_or("-"));
+
+            out += "\n Members:\n";
+            for m in &c.members {
+                out += &format!("   * {} (@{})\n", m.name, m.username.as_deref().unwrap_or("-"));
+                if let Some(bio_text) = &m.bio {
+                    // Indent and wrap bio text for better readability
+                    for line in textwrap::wrap(bio_text, 60) {
+                        out += &format!("     Bio: {}\n", line);
+                    }
+                }
+            }
+            for u in &c.urgent {
+                out += &format!("   * [URGENT @ {}] {}\n", u.datetime, snippet(&u.text, 50));
+            }
+        }
+        out += "\nEnd of report.\n";
+        Ok(out)
+    }
+}
+
+/// Pretty-printed JSON of the whole report.
+pub struct JsonFormatter;
+
+impl ReportFormatter for JsonFormatter {
+    fn render(&self, report: &Report) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(serde_json::to_string_pretty(report)? + "\n")
+    }
+}
+
+/// Markdown document with summary tables and one section per chat.
+pub struct MarkdownFormatter;
+
+impl ReportFormatter for MarkdownFormatter {
+    fn render(&self, report: &Report) -> Result<String, Box<dyn std::error::Error>> {
+        let mut out = format!("# Telegram Chats Report\n\nGenerated {} UTC\n", report.generated_at);
+
+        if !report.mentions.is_empty() {
+            out += "\n## Addressed to You\n\n";
+            for m in &report.mentions {
+                out += &format!("- `{}` **{}**{}: {}\n", m.datetime, m.chat_name,
+                    if m.urgent { " (urgent)" } else { "" }, m.text.replace('\n', " "));
+            }
+        }
+
+        out += "\n## Categories\n\n| Category | Chats |\n| --- | ---: |\n";
+        for c in &report.categories {
+            out += &format!("| {} | {} |\n", md_cell(&c.category), c.chats);
+        }
+
+        if !report.duplicates.is_empty() {
+            out += "\n## Duplicate Topics\n\n";
+            for d in &report.duplicates {
+                out += &format!("- **{}**: {}\n", d.category, d.chats.join(", "));
+            }
+        }
+
+        out += "\n## Chats\n";
+        for c in &report.chats {
+            let chat = &c.chat;
+            out += &format!("\n### {}{}\n\n", chat.name, if chat.duplicate { " (Duplicate Topic)" } else { "" });
+            out += &format!("- Category: {}\n", chat.category.as_deref().unwrap_or("Uncategorized"));
+            out += &format!("- Suggested name: {}\n", chat.suggested_name.as_deref().unwrap_or("-"));
+            if !chat.tags.is_empty() {
+                out += &format!("- Tags: {}\n", chat.tags.join(", "));
+            }
+            out += &format!("- Activity: {} messages, {} urgent, {} to you, last {}\n",
+                c.message_count, c.urgent_count, c.mention_count, c.last_activity.as_deref().unwrap_or("-"));
+            if !c.members.is_empty() {
+                out += "\n**Members**\n\n";
+                for m in &c.members {
+                    match &m.username {
+                        Some(username) => out += &format!("- {} (@{})\n", m.name, username),
+                        None => out += &format!("- {}\n", m.name),
+                    }
+                }
+            }
+            if !c.urgent.is_empty() {
+                out += "\n**Urgent**\n\n";
+                for u in &c.urgent {
+                    out += &format!("- `{}` {}\n", u.datetime, u.text.replace('\n', " "));
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Self-contained static HTML page with collapsible sections.
+pub struct HtmlFormatter;
+
+impl ReportFormatter for HtmlFormatter {
+    fn render(&self, report: &Report) -> Result<String, Box<dyn std::error::Error>> {
+        let mut out = String::from(concat!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n",
+            "<title>Telegram Chats Report</title>\n<style>\n",
+            "body { font-family: system-ui, sans-serif; max-width: 60rem; margin: 2rem auto; padding: 0 1rem; color: #222; }\n",
+            "details { border: 1px solid #ddd; border-radius: 6px; margin: .5rem 0; padding: .4rem .8rem; }\n",
+            "summary { cursor: pointer; font-weight: 600; }\n",
+            "table { border-collapse: collapse; } td, th { border: 1px solid #ddd; padding: .2rem .6rem; text-align: left; }\n",
+            ".badge { font-size: .75rem; padding: .1rem .4rem; border-radius: 4px; background: #eee; margin-left: .4rem; }\n",
+            ".dup { background: #ffe7b3; } .urgent { background: #ffd0d0; }\n",
+            ".muted { color: #777; }\n",
+            "</style>\n</head>\n<body>\n<h1>Telegram Chats Report</h1>\n",
+        ));
+        out += &format!("<p class=\"muted\">Generated {} UTC</p>\n", html_escape(&report.generated_at));
+
+        if !report.mentions.is_empty() {
+            out += "<details open>\n<summary>Addressed to you</summary>\n<ul>\n";
+            for m in &report.mentions {
+                out += &format!("<li><code>{}</code> <strong>{}</strong>{} {}</li>\n", html_escape(&m.datetime), html_escape(&m.chat_name),
+                    if m.urgent { "<span class=\"badge urgent\">urgent</span>" } else { "" }, html_escape(&m.text));
+            }
+            out += "</ul>\n</details>\n";
+        }
+
+        out += "<details open>\n<summary>Categories</summary>\n<table>\n<tr><th>Category</th><th>Chats</th></tr>\n";
+        for c in &report.categories {
+            out += &format!("<tr><td>{}</td><td>{}</td></tr>\n", html_escape(&c.category), c.chats);
+        }
+        out += "</table>\n</details>\n";
+
+        if !report.duplicates.is_empty() {
+            out += "<details open>\n<summary>Duplicate Topics</summary>\n<ul>\n";
+            for d in &report.duplicates {
+                let names: Vec<String> = d.chats.iter().map(|n| html_escape(n)).collect();
+                out += &format!("<li><strong>{}</strong>: {}</li>\n", html_escape(&d.category), names.join(", "));
+            }
+            out += "</ul>\n</details>\n";
+        }
+
+        out += "<h2>Chats</h2>\n";
+        for c in &report.chats {
+            let chat = &c.chat;
+            out += "<details>\n<summary>";
+            out += &html_escape(&chat.name);
+            out += &format!("<span class=\"badge\">{}</span>", html_escape(chat.category.as_deref().unwrap_or("Uncategorized")));
+            for tag in &chat.tags {
+                out += &format!("<span class=\"badge\">#{}</span>", html_escape(tag));
+            }
+            if chat.duplicate {
+                out += "<span class=\"badge dup\">duplicate</span>";
+            }
+            if !c.urgent.is_empty() {
+                out += &format!("<span class=\"badge urgent\">{} urgent</span>", c.urgent.len());
+            }
+            if c.mention_count > 0 {
+                out += &format!("<span class=\"badge\">{} to you</span>", c.mention_count);
+            }
+            out += "</summary>\n";
+            out += &format!("<p>Suggested name: {}</p>\n", html_escape(chat.suggested_name.as_deref().unwrap_or("-")));
+            out += &format!("<p class=\"muted\">{} messages, {} urgent, {} to you, last activity {}</p>\n",
+                c.message_count, c.urgent_count, c.mention_count, html_escape(c.last_activity.as_deref().unwrap_or("-")));
+            if !c.members.is_empty() {
+                out += &format!("<details>\n<summary>Members ({})</summary>\n<ul>\n", c.members.len());
+                for m in &c.members {
+                    out += &format!("<li>{}", html_escape(&m.name));
+                    if let Some(username) = &m.username {
+                        out += &format!(" <span class=\"muted\">@{}</span>", html_escape(username));
+                    }
+                    if let Some(bio) = &m.bio {
+                        out += &format!("<br><small>{}</small>", html_escape(bio));
+                    }
+                    out += "</li>\n";
+                }
+                out += "</ul>\n</details>\n";
+            }
+            if !c.urgent.is_empty() {
+                out += "<details open>\n<summary>Urgent messages</summary>\n<ul>\n";
+                for u in &c.urgent {
+                    out += &format!("<li><code>{}</code> {}</li>\n", html_escape(&u.datetime), html_escape(&u.text));
+                }
+                out += "</ul>\n</details>\n";
+            }
+            out += "</details>\n";
+        }
+        out += "</body>\n</html>\n";
+        Ok(out)
+    }
+}
+
+/// Format a UNIX timestamp as `YYYY-MM-DD HH:MM:SS` (UTC).
+pub fn format_timestamp(ts: i32) -> String {
+    DateTime::<Utc>::from_timestamp(ts as i64, 0)
+        .map(|dt| dt.format("%Y-%m-%d %H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+/// Helper: first `max_chars` characters of a text (never splits a UTF-8 character).
+fn snippet(text: &str, max_chars: usize) -> &str {
+    match text.char_indices().nth(max_chars) {
+        Some((idx, _)) => &text[..idx],
+        None => text,
+    }
+}
+
+/// Helper: escape a value for a Markdown table cell.
+fn md_cell(value: &str) -> String {
+    value.replace('|', "\\|").replace('\n', " ")
+}
+
+/// Helper: escape text for inclusion in HTML.
+pub fn html_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
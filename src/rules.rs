@@ -0,0 +1,896 @@
+use std::collections::{HashMap, HashSet};
+use chrono::{TimeZone, Utc};
+use log::{info, warn, error};
+use libsql::Connection;
+use regex::{Regex, RegexBuilder};
+use crate::database::{self, ChatFilter, RuleChat};
+use crate::taxonomy::Taxonomy;
+use crate::telegram::MessageInfo;
+
+/// Default location of the rules file (overridable via RULES_FILE).
+const DEFAULT_RULES_FILE: &str = "rules.txt";
+/// Default number of stored messages per chat checked by `rules test`.
+const DEFAULT_TEST_LIMIT: i64 = 200;
+/// Seconds in a day, for `inactive_days`.
+const DAY_SECS: i64 = 86_400;
+
+/// What a condition looks at. `text` and `sender` belong to a message; a rule using
+/// either of them is a message rule, every other rule is a chat rule.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Field {
+    Title,         // chat title
+    Type,          // "Group" or "Channel"
+    Category,      // stored category
+    Tag,           // any of the chat's tags
+    InactiveDays,  // whole days since the chat's latest stored message
+    Text,          // message text
+    Sender,        // message author: @username or user id
+}
+
+impl Field {
+    fn parse(name: &str) -> Option<Self> {
+        Some(match name {
+            "title" => Field::Title,
+            "type" => Field::Type,
+            "category" => Field::Category,
+            "tag" => Field::Tag,
+            "inactive_days" => Field::InactiveDays,
+            "text" => Field::Text,
+            "sender" => Field::Sender,
+            _ => return None,
+        })
+    }
+
+    fn is_message(&self) -> bool {
+        matches!(self, Field::Text | Field::Sender)
+    }
+}
+
+/// A numeric comparison operator.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Cmp {
+    Gt,
+    Lt,
+    Ge,
+    Le,
+}
+
+/// How a field is tested.
+#[derive(Debug, Clone)]
+enum Test {
+    Matches(Regex),              // field ~ /regex/flags
+    Contains(String),            // field contains "text" (case-insensitive)
+    Equals(String, bool),        // field = "text" / field != "text" (case-insensitive)
+    Number(Cmp, f64),            // inactive_days > 90
+    NumberEquals(f64, bool),     // inactive_days = 0 / != 0
+    Sender(SenderRef, bool),     // sender = @name / sender = 12345 (or !=)
+    InGroup(String),             // sender in "Finance team": a member of that chat
+}
+
+/// A sender named in a rule: by username (lowercase, without `@`) or by user id.
+#[derive(Debug, Clone, PartialEq)]
+enum SenderRef {
+    Username(String),
+    Id(i64),
+}
+
+/// A condition tree.
+#[derive(Debug, Clone)]
+enum Expr {
+    Cond(Field, Test),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    fn uses_message(&self) -> bool {
+        match self {
+            Expr::Cond(field, _) => field.is_message(),
+            Expr::Not(e) => e.uses_message(),
+            Expr::And(a, b) | Expr::Or(a, b) => a.uses_message() || b.uses_message(),
+        }
+    }
+
+    fn visit<'a>(&'a self, f: &mut impl FnMut(&'a Test)) {
+        match self {
+            Expr::Cond(_, test) => f(test),
+            Expr::Not(e) => e.visit(f),
+            Expr::And(a, b) | Expr::Or(a, b) => {
+                a.visit(f);
+                b.visit(f);
+            }
+        }
+    }
+}
+
+/// What a matching rule does.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Action {
+    Category(String),  // category = "Work/Clients" (chat rules)
+    Tag(String),       // tag "stale": tags the chat, or the message for message rules
+    Urgent,            // urgent (message rules)
+    SkipAi,            // skip_ai: don't send the chat to the model (chat rules)
+}
+
+impl std::fmt::Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Action::Category(c) => write!(f, "category = \"{}\"", c),
+            Action::Tag(t) => write!(f, "tag \"{}\"", t),
+            Action::Urgent => write!(f, "urgent"),
+            Action::SkipAi => write!(f, "skip_ai"),
+        }
+    }
+}
+
+/// One line of the rules file.
+#[derive(Debug, Clone)]
+pub struct Rule {
+    pub name: String,
+    pub source: String,  // the rule as written, after the name
+    expr: Expr,
+    pub actions: Vec<Action>,
+    pub per_message: bool,
+}
+
+/// The deterministic rules from the rules file (RULES_FILE, default `rules.txt`), one per line:
+///
+/// ```text
+/// # comment
+/// acme: if title ~ /^acme/i then category = "Work/Clients"
+/// invoices: if text ~ /INV-\d{4,}/ and sender in "Finance" then urgent, tag "invoice"
+/// stale: if inactive_days > 90 then tag "stale", skip_ai
+/// ```
+///
+/// Conditions combine `field op value` tests with `and`, `or`, `not` and parentheses.
+/// Fields: `title`, `type`, `category`, `tag`, `inactive_days`, `text`, `sender`.
+/// Operators: `~ /regex/flags`, `contains "text"`, `=`/`!=` (case-insensitive),
+/// `>`, `<`, `>=`, `<=` for numbers and `sender in "<chat>"` (member of that chat).
+/// Rules are evaluated after ingestion and before AI analysis, in file order; the first
+/// category set wins.
+pub struct RuleSet {
+    rules: Vec<Rule>,
+    usernames: HashMap<String, i64>,     // @usernames named in rules, resolved to user ids
+    groups: HashMap<String, HashSet<i64>>, // chats named by `in`, resolved to member ids
+}
+
+/// What the rules decided for one chat and its messages.
+#[derive(Debug, Clone, Default)]
+pub struct Outcome {
+    pub category: Option<String>,
+    pub tags: Vec<String>,
+    pub skip_ai: bool,
+    pub urgent: Vec<i32>,
+    pub message_tags: Vec<(i32, String)>,
+    pub hits: Vec<(String, Option<i32>)>,  // (rule, msg_id) per match
+}
+
+impl RuleSet {
+    /// Load the rules file; without one there are no rules.
+    /// Categories set by rules must be in the taxonomy.
+    pub fn from_env(taxonomy: &Taxonomy) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = std::env::var("RULES_FILE").unwrap_or_else(|_| DEFAULT_RULES_FILE.to_string());
+        match std::fs::read_to_string(&path) {
+            Ok(content) => Self::parse(&content, taxonomy).map_err(|e| format!("Invalid rules file {}: {}", path, e).into()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::parse("", taxonomy)?),
+            Err(e) => Err(format!("Failed to read rules file {}: {}", path, e).into()),
+        }
+    }
+
+    /// Parse rules, one per line, reporting the first invalid line.
+    pub fn parse(content: &str, taxonomy: &Taxonomy) -> Result<Self, String> {
+        let mut rules: Vec<Rule> = Vec::new();
+        for (number, line) in content.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let rule = parse_rule(line, taxonomy).map_err(|e| format!("line {}: {}", number + 1, e))?;
+            if rules.iter().any(|r| r.name == rule.name) {
+                return Err(format!("line {}: duplicate rule name \"{}\"", number + 1, rule.name));
+            }
+            rules.push(rule);
+        }
+        Ok(RuleSet { rules, usernames: HashMap::new(), groups: HashMap::new() })
+    }
+
+    pub fn rules(&self) -> &[Rule] {
+        &self.rules
+    }
+
+    /// Resolve the usernames and group chats the rules refer to against the database.
+    /// Called once per cycle so new members and profiles are picked up.
+    pub async fn refresh(&mut self, conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+        let mut usernames = HashMap::new();
+        let mut groups = HashMap::new();
+        for rule in &self.rules {
+            let mut referenced = Vec::new();
+            rule.expr.visit(&mut |test| referenced.push(test));
+            for test in referenced {
+                match test {
+                    Test::Sender(SenderRef::Username(name), _) if !usernames.contains_key(name) => {
+                        match database::find_user_by_username(conn, name).await? {
+                            Some(id) => {
+                                usernames.insert(name.clone(), id);
+                            }
+                            None => warn!("Rule \"{}\": unknown user @{}; its sender test matches no message", rule.name, name),
+                        }
+                    }
+                    Test::InGroup(chat) if !groups.contains_key(chat) => {
+                        groups.insert(chat.clone(), database::get_group_member_ids(conn, chat).await?);
+                    }
+                    _ => {}
+                }
+            }
+        }
+        self.usernames = usernames;
+        self.groups = groups;
+        Ok(())
+    }
+
+    /// Evaluate every rule against a chat and the given messages.
+    pub fn evaluate(&self, chat: &RuleChat, messages: &[MessageInfo], now: i64) -> Outcome {
+        let mut outcome = Outcome::default();
+        for rule in &self.rules {
+            if rule.per_message {
+                for msg in messages {
+                    if self.matches(&rule.expr, chat, Some(msg), now) {
+                        outcome.hits.push((rule.name.clone(), Some(msg.msg_id)));
+                        for action in &rule.actions {
+                            match action {
+                                Action::Urgent if !outcome.urgent.contains(&msg.msg_id) => outcome.urgent.push(msg.msg_id),
+                                Action::Tag(tag) => outcome.message_tags.push((msg.msg_id, tag.clone())),
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+            } else if self.matches(&rule.expr, chat, None, now) {
+                outcome.hits.push((rule.name.clone(), None));
+                for action in &rule.actions {
+                    match action {
+                        Action::Category(category) if outcome.category.is_none() => outcome.category = Some(category.clone()),
+                        Action::Tag(tag) if !outcome.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) => outcome.tags.push(tag.clone()),
+                        Action::SkipAi => outcome.skip_ai = true,
+                        _ => {}
+                    }
+                }
+            }
+        }
+        outcome
+    }
+
+    /// Helper: whether a condition holds. A rule only fires when its condition is known to hold.
+    fn matches(&self, expr: &Expr, chat: &RuleChat, msg: Option<&MessageInfo>, now: i64) -> bool {
+        self.truth(expr, chat, msg, now) == Some(true)
+    }
+
+    /// Helper: evaluate a condition; None when it can't be decided (a sender test on a username
+    /// nobody in the database has). Unknown stays unknown under `not`, and `and`/`or` only
+    /// decide without it when the other side does. Message fields are false without a message.
+    fn truth(&self, expr: &Expr, chat: &RuleChat, msg: Option<&MessageInfo>, now: i64) -> Option<bool> {
+        match expr {
+            Expr::Not(e) => self.truth(e, chat, msg, now).map(|b| !b),
+            Expr::And(a, b) => match self.truth(a, chat, msg, now) {
+                Some(false) => Some(false),
+                a => match (a, self.truth(b, chat, msg, now)) {
+                    (_, Some(false)) => Some(false),
+                    (Some(true), Some(true)) => Some(true),
+                    _ => None,
+                },
+            },
+            Expr::Or(a, b) => match self.truth(a, chat, msg, now) {
+                Some(true) => Some(true),
+                a => match (a, self.truth(b, chat, msg, now)) {
+                    (_, Some(true)) => Some(true),
+                    (Some(false), Some(false)) => Some(false),
+                    _ => None,
+                },
+            },
+            Expr::Cond(field, test) => match field {
+                Field::Title => Some(test_text(test, &chat.name)),
+                Field::Type => Some(test_text(test, &chat.kind)),
+                Field::Category => Some(test_text(test, chat.category.as_deref().unwrap_or(""))),
+                Field::Tag => Some(chat.tags.iter().any(|t| test_text(test, t))),
+                Field::InactiveDays => Some(chat.last_message_at
+                    .map(|last| ((now - last) / DAY_SECS) as f64)
+                    .map_or(false, |days| test_number(test, days))),
+                Field::Text => Some(msg.map_or(false, |m| test_text(test, &m.text))),
+                Field::Sender => match msg.and_then(|m| m.sender_id) {
+                    Some(id) => self.test_sender(test, id),
+                    None => Some(false),
+                },
+            },
+        }
+    }
+
+    /// Helper: test a message's sender; None for a username nobody in the database has.
+    fn test_sender(&self, test: &Test, sender_id: i64) -> Option<bool> {
+        match test {
+            Test::Sender(sender, negate) => {
+                let is = match sender {
+                    SenderRef::Id(id) => *id == sender_id,
+                    SenderRef::Username(name) => *self.usernames.get(name)? == sender_id,
+                };
+                Some(is != *negate)
+            }
+            Test::InGroup(chat) => Some(self.groups.get(chat).map_or(false, |members| members.contains(&sender_id))),
+            _ => Some(false),
+        }
+    }
+}
+
+/// Helper: test a text field.
+fn test_text(test: &Test, value: &str) -> bool {
+    match test {
+        Test::Matches(re) => re.is_match(value),
+        Test::Contains(needle) => value.to_lowercase().contains(needle.as_str()),
+        Test::Equals(expected, negate) => (value.to_lowercase() == *expected) != *negate,
+        _ => false,
+    }
+}
+
+/// Helper: test a numeric field.
+fn test_number(test: &Test, value: f64) -> bool {
+    match test {
+        Test::Number(Cmp::Gt, n) => value > *n,
+        Test::Number(Cmp::Lt, n) => value < *n,
+        Test::Number(Cmp::Ge, n) => value >= *n,
+        Test::Number(Cmp::Le, n) => value <= *n,
+        Test::NumberEquals(n, negate) => (value == *n) != *negate,
+        _ => false,
+    }
+}
+
+/// A token of the rule language.
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Regex(String, String),  // pattern, flags
+    Number(f64),
+    User(String),
+    Op(&'static str),
+    Open,
+    Close,
+    Comma,
+}
+
+/// Helper: split a rule into tokens.
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+        } else if c == '"' || c == '/' {
+            // Quoted string or regex; a backslash escapes the delimiter
+            let mut value = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(format!("unterminated {}", if c == '"' { "string" } else { "regex" })),
+                    Some(&d) if d == c => break,
+                    Some('\\') if chars.get(i + 1) == Some(&c) => {
+                        value.push(c);
+                        i += 1;
+                    }
+                    Some(&d) => value.push(d),
+                }
+                i += 1;
+            }
+            i += 1;
+            if c == '"' {
+                tokens.push(Token::Str(value));
+            } else {
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+                tokens.push(Token::Regex(value, chars[start..i].iter().collect()));
+            }
+        } else if c == '@' {
+            let start = i + 1;
+            i = start;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            if i == start {
+                return Err("expected a username after @".to_string());
+            }
+            tokens.push(Token::User(chars[start..i].iter().collect::<String>().to_lowercase()));
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let number: String = chars[start..i].iter().collect();
+            tokens.push(Token::Number(number.parse().map_err(|_| format!("invalid number {}", number))?));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            tokens.push(Token::Word(chars[start..i].iter().collect::<String>().to_lowercase()));
+        } else {
+            let next = chars.get(i + 1).copied();
+            let (op, len) = match (c, next) {
+                ('!', Some('=')) => ("!=", 2),
+                ('>', Some('=')) => (">=", 2),
+                ('<', Some('=')) => ("<=", 2),
+                ('=', Some('=')) => ("=", 2),
+                ('=', _) => ("=", 1),
+                ('>', _) => (">", 1),
+                ('<', _) => ("<", 1),
+                ('~', _) => ("~", 1),
+                ('(', _) => {
+                    tokens.push(Token::Open);
+                    i += 1;
+                    continue;
+                }
+                (')', _) => {
+                    tokens.push(Token::Close);
+                    i += 1;
+                    continue;
+                }
+                (',', _) => {
+                    tokens.push(Token::Comma);
+                    i += 1;
+                    continue;
+                }
+                _ => return Err(format!("unexpected character '{}'", c)),
+            };
+            tokens.push(Token::Op(op));
+            i += len;
+        }
+    }
+    Ok(tokens)
+}
+
+/// Helper: recursive-descent parser over a rule's tokens.
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<&'a Token> {
+        let token = self.tokens.get(self.pos);
+        self.pos += 1;
+        token
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        if self.peek() == Some(&Token::Word(word.to_string())) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn string(&mut self, what: &str) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Str(s)) => Ok(s.clone()),
+            other => Err(format!("expected a quoted {}, found {}", what, describe(other))),
+        }
+    }
+
+    // expr := and ("or" and)*
+    fn expr(&mut self) -> Result<Expr, String> {
+        let mut left = self.and()?;
+        while self.eat_word("or") {
+            left = Expr::Or(Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    // and := unary ("and" unary)*
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut left = self.unary()?;
+        while self.eat_word("and") {
+            left = Expr::And(Box::new(left), Box::new(self.unary()?));
+        }
+        Ok(left)
+    }
+
+    // unary := "not" unary | "(" expr ")" | condition
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.eat_word("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.pos += 1;
+            let inner = self.expr()?;
+            if self.next() != Some(&Token::Close) {
+                return Err("missing ')'".to_string());
+            }
+            return Ok(inner);
+        }
+        self.condition()
+    }
+
+    // condition := field op value
+    fn condition(&mut self) -> Result<Expr, String> {
+        let field = match self.next() {
+            Some(Token::Word(name)) => Field::parse(name).ok_or_else(|| format!("unknown field \"{}\"", name))?,
+            other => return Err(format!("expected a field, found {}", describe(other))),
+        };
+        let op = match self.next() {
+            Some(Token::Op(op)) => *op,
+            Some(Token::Word(w)) if w == "contains" => "contains",
+            Some(Token::Word(w)) if w == "in" => "in",
+            other => return Err(format!("expected an operator after the field, found {}", describe(other))),
+        };
+        let value = self.next();
+        let test = match (field, op, value) {
+            (Field::InactiveDays, "=" | "!=", Some(Token::Number(n))) => Test::NumberEquals(*n, op == "!="),
+            (Field::InactiveDays, _, Some(Token::Number(n))) => Test::Number(match op {
+                ">" => Cmp::Gt,
+                "<" => Cmp::Lt,
+                ">=" => Cmp::Ge,
+                "<=" => Cmp::Le,
+                _ => return Err(format!("inactive_days can't be used with {}", op)),
+            }, *n),
+            (Field::InactiveDays, _, _) => return Err("inactive_days must be compared with a number".to_string()),
+            (Field::Sender, "=" | "!=", Some(Token::User(name))) => Test::Sender(SenderRef::Username(name.clone()), op == "!="),
+            (Field::Sender, "=" | "!=", Some(Token::Number(n))) => Test::Sender(SenderRef::Id(*n as i64), op == "!="),
+            (Field::Sender, "in", Some(Token::Str(chat))) => Test::InGroup(chat.clone()),
+            (Field::Sender, _, _) => return Err("sender takes = @username, = <user id> or in \"<chat>\"".to_string()),
+            (_, "~", Some(Token::Regex(pattern, flags))) => Test::Matches(build_regex(pattern, flags)?),
+            (_, "contains", Some(Token::Str(s))) => Test::Contains(s.to_lowercase()),
+            (_, "=" | "!=", Some(Token::Str(s))) => Test::Equals(s.to_lowercase(), op == "!="),
+            (_, op, value) => return Err(format!("can't use {} with {}", op, describe(value))),
+        };
+        Ok(Expr::Cond(field, test))
+    }
+
+    // actions := action ("," action)*
+    fn actions(&mut self, taxonomy: &Taxonomy) -> Result<Vec<Action>, String> {
+        let mut actions = Vec::new();
+        loop {
+            let action = match self.next() {
+                Some(Token::Word(w)) if w == "category" => {
+                    if self.next() != Some(&Token::Op("=")) {
+                        return Err("expected = after category".to_string());
+                    }
+                    let label = self.string("category")?;
+                    let category = taxonomy.find(&label)
+                        .ok_or_else(|| format!("\"{}\" is not in the taxonomy", label))?;
+                    Action::Category(category.to_string())
+                }
+                Some(Token::Word(w)) if w == "tag" => {
+                    if self.peek() == Some(&Token::Op("=")) {
+                        self.pos += 1;
+                    }
+                    let tag = self.string("tag")?;
+                    if tag.trim().is_empty() {
+                        return Err("tag must not be empty".to_string());
+                    }
+                    Action::Tag(tag.trim().to_string())
+                }
+                Some(Token::Word(w)) if w == "urgent" => Action::Urgent,
+                Some(Token::Word(w)) if w == "skip_ai" => Action::SkipAi,
+                other => return Err(format!("expected an action (category, tag, urgent, skip_ai), found {}", describe(other))),
+            };
+            actions.push(action);
+            if self.peek() != Some(&Token::Comma) {
+                break;
+            }
+            self.pos += 1;
+        }
+        Ok(actions)
+    }
+}
+
+/// Helper: parse `name: if <condition> then <actions>`.
+fn parse_rule(line: &str, taxonomy: &Taxonomy) -> Result<Rule, String> {
+    let (name, source) = line.split_once(':').ok_or("expected \"<name>: if ... then ...\"")?;
+    let name = name.trim();
+    if name.is_empty() || !name.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+        return Err(format!("invalid rule name \"{}\" (letters, digits, - and _)", name));
+    }
+    let tokens = tokenize(source)?;
+    let mut parser = Parser { tokens: &tokens, pos: 0 };
+    if !parser.eat_word("if") {
+        return Err("expected \"if\" after the rule name".to_string());
+    }
+    let expr = parser.expr()?;
+    if !parser.eat_word("then") {
+        return Err(format!("expected \"then\", found {}", describe(parser.peek())));
+    }
+    let actions = parser.actions(taxonomy)?;
+    if parser.peek().is_some() {
+        return Err(format!("unexpected {} after the actions", describe(parser.peek())));
+    }
+    let per_message = expr.uses_message();
+    for action in &actions {
+        match action {
+            Action::Urgent if !per_message => return Err("urgent needs a condition on text or sender".to_string()),
+            Action::Category(_) | Action::SkipAi if per_message => {
+                return Err(format!("{} can't depend on text or sender", action));
+            }
+            _ => {}
+        }
+    }
+    Ok(Rule { name: name.to_string(), source: source.trim().to_string(), expr, actions, per_message })
+}
+
+/// Helper: compile a regex literal; flag `i` makes it case-insensitive.
+fn build_regex(pattern: &str, flags: &str) -> Result<Regex, String> {
+    if let Some(flag) = flags.chars().find(|f| *f != 'i') {
+        return Err(format!("unknown regex flag '{}'", flag));
+    }
+    RegexBuilder::new(pattern)
+        .case_insensitive(flags.contains('i'))
+        .build()
+        .map_err(|e| format!("invalid regex /{}/: {}", pattern, e))
+}
+
+/// Helper: name a token in an error message.
+fn describe(token: Option<&Token>) -> String {
+    match token {
+        None => "end of rule".to_string(),
+        Some(Token::Word(w)) => format!("\"{}\"", w),
+        Some(Token::Str(s)) => format!("string \"{}\"", s),
+        Some(Token::Regex(r, _)) => format!("regex /{}/", r),
+        Some(Token::Number(n)) => format!("number {}", n),
+        Some(Token::User(u)) => format!("@{}", u),
+        Some(Token::Op(op)) => format!("'{}'", op),
+        Some(Token::Open) => "'('".to_string(),
+        Some(Token::Close) => "')'".to_string(),
+        Some(Token::Comma) => "','".to_string(),
+    }
+}
+
+/// Evaluate the rules for a chat after its new messages were stored and store the outcome:
+/// the rule category and chat tags (replacing last cycle's), message tags, urgent flags and
+/// hit counts. Returns the outcome so the caller can skip analysis and notify.
+pub async fn apply_to_chat(conn: &mut Connection, rules: &RuleSet, chat_peer: &str, new_messages: &[MessageInfo])
+    -> Result<Outcome, Box<dyn std::error::Error>>
+{
+    let filter = ChatFilter { chat_exact: Some(chat_peer.to_string()), ..Default::default() };
+    let Some(chat) = database::get_rule_chats(conn, &filter).await?.into_iter().find(|c| c.peer_id == chat_peer) else {
+        return Ok(Outcome::default());
+    };
+    let outcome = rules.evaluate(&chat, new_messages, Utc::now().timestamp());
+    database::set_rule_results(conn, chat_peer, outcome.category.as_deref(), &outcome.tags).await?;
+    database::add_rule_message_tags(conn, chat_peer, &outcome.message_tags).await?;
+    let newly_flagged = database::mark_urgent(conn, chat_peer, &outcome.urgent, "rule").await?;
+    if !newly_flagged.is_empty() {
+        info!("Rules flagged {} message(s) urgent in {}", newly_flagged.len(), chat.name);
+    }
+    database::record_rule_hits(conn, chat_peer, &outcome.hits).await?;
+    Ok(outcome)
+}
+
+/// Print the rules with how often each matched and where it last did.
+pub async fn print_rules(conn: &Connection, rules: &RuleSet) -> Result<(), Box<dyn std::error::Error>> {
+    if rules.rules().is_empty() {
+        println!("No rules. Add some to {} (see README).", std::env::var("RULES_FILE").unwrap_or_else(|_| DEFAULT_RULES_FILE.to_string()));
+        return Ok(());
+    }
+    let stats: HashMap<String, database::RuleStats> = database::get_rule_stats(conn).await?
+        .into_iter().map(|s| (s.rule.clone(), s)).collect();
+    println!("{:>6}  {:<10} {:<16} Rule", "Hits", "Level", "Last hit");
+    for rule in rules.rules() {
+        let stat = stats.get(&rule.name);
+        let last = stat.and_then(|s| s.last_hit_at)
+            .and_then(|t| Utc.timestamp_opt(t, 0).single())
+            .map(|t| t.format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "never".to_string());
+        let level = if rule.per_message { "message" } else { "chat" };
+        println!("{:>6}  {:<10} {:<16} {}: {}", stat.map_or(0, |s| s.hits), level, last, rule.name, rule.source);
+        if let Some(peer) = stat.and_then(|s| s.last_chat_peer.as_deref()) {
+            let msg = stat.and_then(|s| s.last_msg_id).map(|id| format!(" #{}", id)).unwrap_or_default();
+            println!("{:>6}  {:<10} {:<16} last in {}{}", "", "", "", peer, msg);
+        }
+    }
+    Ok(())
+}
+
+/// Evaluate the rules against stored chats and their most recent messages (at most `limit`
+/// per chat) and print what they would do, without changing anything.
+pub async fn test(conn: &Connection, rules: &mut RuleSet, filter: &ChatFilter, limit: Option<i64>) -> Result<(), Box<dyn std::error::Error>> {
+    rules.refresh(conn).await?;
+    let now = Utc::now().timestamp();
+    let limit = limit.unwrap_or(DEFAULT_TEST_LIMIT);
+    let mut totals: HashMap<String, usize> = HashMap::new();
+    let mut chats_matched = 0;
+    for chat in database::get_rule_chats(conn, filter).await? {
+        let messages = if rules.rules().iter().any(|r| r.per_message) {
+            match database::get_rule_messages(conn, &chat.peer_id, filter, limit).await {
+                Ok(messages) => messages,
+                Err(e) => {
+                    error!("Failed to load messages for {}: {}", chat.name, e);
+                    continue;
+                }
+            }
+        } else {
+            Vec::new()
+        };
+        let outcome = rules.evaluate(&chat, &messages, now);
+        if outcome.hits.is_empty() {
+            continue;
+        }
+        chats_matched += 1;
+        println!("{} ({})", chat.name, chat.peer_id);
+        for (rule_name, msg_id) in &outcome.hits {
+            *totals.entry(rule_name.clone()).or_insert(0) += 1;
+            let rule = rules.rules().iter().find(|r| &r.name == rule_name);
+            let actions = rule.map(|r| r.actions.iter().map(|a| a.to_string()).collect::<Vec<_>>().join(", ")).unwrap_or_default();
+            match msg_id.and_then(|id| messages.iter().find(|m| m.msg_id == id)) {
+                Some(msg) => println!("  {:<16} #{} {} -> {}", rule_name, msg.msg_id, excerpt(&msg.text), actions),
+                None => println!("  {:<16} chat -> {}", rule_name, actions),
+            }
+        }
+    }
+    println!("\n{} chat(s) matched.", chats_matched);
+    for rule in rules.rules() {
+        println!("{:>6}  {}", totals.get(&rule.name).copied().unwrap_or(0), rule.name);
+    }
+    Ok(())
+}
+
+/// Helper: a one-line excerpt of a message for `rules test`.
+fn excerpt(text: &str) -> String {
+    let line = text.lines().next().unwrap_or("");
+    let mut short: String = line.chars().take(60).collect();
+    if short.len() < line.len() || text.lines().count() > 1 {
+        short.push('…');
+    }
+    format!("\"{}\"", short)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::taxonomy::Category;
+
+    fn taxonomy() -> Taxonomy {
+        Taxonomy::new(&[Category {
+            name: "Work".to_string(),
+            description: String::new(),
+            aliases: Vec::new(),
+            children: vec![Category {
+                name: "Clients".to_string(),
+                description: String::new(),
+                aliases: Vec::new(),
+                children: Vec::new(),
+            }],
+        }])
+    }
+
+    fn chat(name: &str, kind: &str) -> RuleChat {
+        RuleChat {
+            peer_id: "group:1".to_string(),
+            kind: kind.to_string(),
+            name: name.to_string(),
+            category: None,
+            tags: Vec::new(),
+            last_message_at: None,
+        }
+    }
+
+    fn message(msg_id: i32, text: &str, sender_id: Option<i64>) -> MessageInfo {
+        MessageInfo {
+            msg_id,
+            date: 0,
+            text: text.to_string(),
+            sender_id,
+            outgoing: false,
+            reply_to: None,
+            mentions_me: false,
+        }
+    }
+
+    /// Helper: whether a single chat rule matches the chat.
+    fn chat_matches(condition: &str, chat: &RuleChat) -> bool {
+        let rules = RuleSet::parse(&format!("r: if {} then tag \"hit\"", condition), &taxonomy()).unwrap();
+        !rules.evaluate(chat, &[], 0).hits.is_empty()
+    }
+
+    /// Helper: the error for a rules file, which must not parse.
+    fn parse_error(content: &str) -> String {
+        match RuleSet::parse(content, &taxonomy()) {
+            Ok(_) => panic!("rules should not parse: {}", content),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let group = chat("x", "Group");
+        assert!(chat_matches(r#"title = "x" or title = "y" and type = "Channel""#, &group));
+        assert!(!chat_matches(r#"(title = "x" or title = "y") and type = "Channel""#, &group));
+        assert!(chat_matches(r#"type = "Channel" and title = "y" or title = "x""#, &group));
+    }
+
+    #[test]
+    fn not_applies_to_the_next_condition_only() {
+        assert!(!chat_matches(r#"not title contains "spam" and type = "Group""#, &chat("spam talk", "Group")));
+        assert!(chat_matches(r#"not title contains "spam" and type = "Group""#, &chat("ok", "Group")));
+        assert!(chat_matches(r#"not title contains "spam" or type = "Group""#, &chat("spam talk", "Group")));
+        assert!(!chat_matches(r#"not (title contains "spam" or type = "Group")"#, &chat("ok", "Group")));
+        assert!(chat_matches(r#"not not type = "Group""#, &chat("ok", "Group")));
+        assert!(chat_matches(r#"title != "spam""#, &chat("ok", "Group")));
+    }
+
+    #[test]
+    fn quoted_strings_and_regexes() {
+        assert!(chat_matches(r#"title = "say \"hi\"""#, &chat("Say \"Hi\"", "Group")));
+        assert!(chat_matches(r#"title contains "and or not""#, &chat("this and or not that", "Group")));
+        assert!(chat_matches(r#"title ~ /^a\/b$/"#, &chat("a/b", "Group")));
+        assert!(!chat_matches(r#"title ~ /^acme/"#, &chat("ACME corp", "Group")));
+        assert!(chat_matches(r#"title ~ /^acme/i"#, &chat("ACME corp", "Group")));
+    }
+
+    #[test]
+    fn message_rules_flag_matching_messages() {
+        let rules = RuleSet::parse(r#"inv: if text ~ /INV-\d+/ and not sender = 7 then urgent, tag "invoice""#, &taxonomy()).unwrap();
+        assert!(rules.rules()[0].per_message);
+        let messages = [message(1, "INV-42 is due", Some(5)), message(2, "INV-43", Some(7)), message(3, "hello", Some(5))];
+        let outcome = rules.evaluate(&chat("x", "Group"), &messages, 0);
+        assert_eq!(outcome.urgent, vec![1]);
+        assert_eq!(outcome.message_tags, vec![(1, "invoice".to_string())]);
+    }
+
+    #[test]
+    fn unresolved_usernames_match_no_sender() {
+        let rules = RuleSet::parse(
+            "a: if sender = @ghost then urgent\nb: if sender != @ghost then urgent\nc: if not sender = @ghost then urgent\nd: if not sender != @ghost then urgent",
+            &taxonomy(),
+        ).unwrap();
+        let outcome = rules.evaluate(&chat("x", "Group"), &[message(1, "hi", Some(5))], 0);
+        assert!(outcome.hits.is_empty());
+    }
+
+    #[test]
+    fn unresolved_usernames_only_decide_with_the_other_side() {
+        let rules = RuleSet::parse(
+            "either: if sender = @ghost or text contains \"hi\" then tag \"or\"\nneither: if not (sender = @ghost and text contains \"bye\") then tag \"and\"",
+            &taxonomy(),
+        ).unwrap();
+        let outcome = rules.evaluate(&chat("x", "Group"), &[message(1, "hi", Some(5))], 0);
+        assert_eq!(outcome.message_tags, vec![(1, "or".to_string()), (1, "and".to_string())]);
+    }
+
+    #[test]
+    fn categories_come_from_the_taxonomy() {
+        let rules = RuleSet::parse(r#"w: if title contains "acme" then category = "work/clients", skip_ai"#, &taxonomy()).unwrap();
+        assert_eq!(rules.rules()[0].actions, vec![Action::Category("Work/Clients".to_string()), Action::SkipAi]);
+        assert!(parse_error(r#"w: if title contains "acme" then category = "Gardening""#).contains("not in the taxonomy"));
+    }
+
+    #[test]
+    fn comments_and_blank_lines_are_skipped() {
+        let rules = RuleSet::parse("# comment\n\n  a: if type = \"Group\" then tag \"g\"\n", &taxonomy()).unwrap();
+        assert_eq!(rules.rules().len(), 1);
+    }
+
+    #[test]
+    fn parse_errors_name_the_line_and_problem() {
+        assert!(parse_error("a: if title = \"open then tag \"t\"").contains("unterminated string"));
+        assert!(parse_error("a: if title ~ /open then tag \"t\"").contains("unterminated regex"));
+        assert!(parse_error("a: if colour = \"red\" then tag \"t\"").contains("unknown field \"colour\""));
+        assert!(parse_error("a: if (title = \"x\" then tag \"t\"").contains("missing ')'"));
+        assert!(parse_error("a: if title = \"x\" tag \"t\"").contains("expected \"then\""));
+        assert!(parse_error("a: if title = \"x\" then").contains("expected an action"));
+        assert!(parse_error("a: if title = \"x\" then urgent").contains("urgent needs a condition"));
+        assert!(parse_error("a: if text contains \"x\" then skip_ai").contains("can't depend on text or sender"));
+        assert!(parse_error("a: if inactive_days > \"x\" then tag \"t\"").contains("inactive_days must be compared"));
+        assert!(parse_error("a: if title ~ /x/g then tag \"t\"").contains("unknown regex flag"));
+        assert!(parse_error("a: if title = \"x\" then tag \"t\" tag \"u\"").contains("after the actions"));
+        assert!(parse_error("if title = \"x\" then tag \"t\"").contains("expected \"<name>: if ... then ...\""));
+        assert!(parse_error("my rule: if title = \"x\" then tag \"t\"").contains("invalid rule name"));
+        let duplicate = parse_error("a: if type = \"Group\" then tag \"t\"\n\na: if type = \"Channel\" then tag \"t\"");
+        assert!(duplicate.starts_with("line 3:") && duplicate.contains("duplicate rule name"));
+    }
+}
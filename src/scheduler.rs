@@ -1,130 +1,626 @@
 use tokio::time::{sleep, Duration};
-use log::{info, error};
+use log::{info, error, warn};
 use grammers_client::Client;
-use crate::{telegram, database, ai};
+use libsql::Connection;
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt, stream};
+use crate::{telegram, database, ai, digest, rules, watch, mentions, people};
+use crate::notify::Notifiers;
+use crate::shutdown::Shutdown;
+use crate::taxonomy::{self, Taxonomy};
+use crate::rules::RuleSet;
+use crate::watch::Watchlists;
+use crate::mentions::Owner;
+use crate::telegram::{ChatInfo, MessageInfo};
 use std::collections::HashMap;
-use chrono::{DateTime, Utc};
-use textwrap;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use chrono::Utc;
+
+/// Default number of chats fetched from Telegram concurrently.
+const DEFAULT_FETCH_CONCURRENCY: usize = 4;
+/// Default number of chats embedded concurrently.
+const DEFAULT_EMBED_CONCURRENCY: usize = 2;
+/// Default number of concurrent LLM analyses (kept low so Ollama is never flooded).
+const DEFAULT_ANALYZE_CONCURRENCY: usize = 1;
+/// Default capacity of the channels connecting pipeline stages.
+const DEFAULT_CHANNEL_CAPACITY: usize = 8;
+/// Number of messages given to the model per analysis call.
+const ANALYSIS_WINDOW: usize = 20;
+/// Number of recent user corrections shown to the model as examples.
+const FEEDBACK_EXAMPLES: i64 = 10;
+/// Default minimum confidence for storing a tag proposed by the model (TAG_MIN_CONFIDENCE).
+const DEFAULT_TAG_MIN_CONFIDENCE: f64 = 0.5;
+/// Number of analysis windows above which `reanalyze` warns how many model calls a chat takes.
+const REANALYZE_WARN_WINDOWS: usize = 50;
+/// Most messages stored without an embedding that are embedded per cycle.
+const EMBED_BACKLOG_PER_CYCLE: i64 = 500;
+/// Delay before the first retry of a failed analysis; doubled on each further failure.
+const RETRY_BASE_DELAY_SECS: i64 = 60;
+/// Upper bound for the retry delay of a failed analysis.
+const RETRY_MAX_DELAY_SECS: i64 = 6 * 3600;
+
+/// Concurrency limits for the fetch -> embed -> analyze pipeline.
+/// Each value can be overridden via the matching environment variable.
+#[derive(Debug, Clone)]
+pub struct PipelineConfig {
+    pub fetch_concurrency: usize,    // FETCH_CONCURRENCY
+    pub embed_concurrency: usize,    // EMBED_CONCURRENCY
+    pub analyze_concurrency: usize,  // ANALYZE_CONCURRENCY
+    pub channel_capacity: usize,     // PIPELINE_CHANNEL_CAPACITY
+}
+
+impl PipelineConfig {
+    /// Build the pipeline configuration from environment variables, falling back to defaults.
+    pub fn from_env() -> Self {
+        PipelineConfig {
+            fetch_concurrency: env_usize("FETCH_CONCURRENCY", DEFAULT_FETCH_CONCURRENCY),
+            embed_concurrency: env_usize("EMBED_CONCURRENCY", DEFAULT_EMBED_CONCURRENCY),
+            analyze_concurrency: env_usize("ANALYZE_CONCURRENCY", DEFAULT_ANALYZE_CONCURRENCY),
+            channel_capacity: env_usize("PIPELINE_CHANNEL_CAPACITY", DEFAULT_CHANNEL_CAPACITY),
+        }
+    }
+}
+
+/// Helper: read a positive integer from the environment, or use the default.
+fn env_usize(key: &str, default: usize) -> usize {
+    std::env::var(key)
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(default)
+}
+
+/// How the pipeline sorts what it ingests: messages addressed to the account `owner` are flagged
+/// on fetch, the rules and watchlists run on each chat as soon as its new messages are stored,
+/// then the AI analysis places chats in the taxonomy.
+struct Processing<'a> {
+    owner: &'a Owner,
+    taxonomy: &'a Taxonomy,
+    rules: &'a RuleSet,
+    watchlists: &'a Watchlists,
+}
+
+/// What the analysis works with: the model, the taxonomy it places chats in, and the sinks
+/// newly flagged urgent messages go to.
+struct Analyst<'a> {
+    model: &'a str,
+    taxonomy: &'a Taxonomy,
+    notifiers: &'a Notifiers,
+}
+
+/// How far one chat's analysis got.
+struct AnalysisProgress {
+    category: Option<String>,  // latest category, if a window was analyzed
+    analyzed_up_to: i32,       // last message covered
+    caught_up: bool,           // false if it stopped on a failure or shutdown
+}
+
+/// What one pass of the pipeline produced.
+struct PipelineOutcome {
+    categories: Vec<(String, String)>,  // (category, peer_id) for duplicate detection
+    messages_stored: usize,
+    chats_analyzed: usize,
+}
 
 /// Run the periodic data fetch and analysis cycle every 30 minutes.
-pub async fn run_schedule(client: &Client, conn: &mut rusqlite::Connection) -> Result<(), Box<dyn std::error::Error>> {
+/// Returns once `shutdown` fires, after in-flight database writes have finished.
+pub async fn run_schedule(client: &Client, conn: &mut Connection, mut shutdown: Shutdown) -> Result<(), Box<dyn std::error::Error>> {
     let interval = Duration::from_secs(1800);  // 30 minutes
+    let config = PipelineConfig::from_env();
+    let digest_period = digest::DigestPeriod::from_env();
+    let notifiers = Notifiers::from_env(Some(client));
+    let taxonomy = Taxonomy::from_env()?;
+    let mut rules = RuleSet::from_env(&taxonomy)?;
+    let watchlists = Watchlists::from_env()?;
+    let owner = Owner::fetch(client).await?;
+    let profiles = people::ProfileConfig::from_env();
+    info!("Pipeline concurrency: fetch={}, embed={}, analyze={}, buffer={}",
+        config.fetch_concurrency, config.embed_concurrency, config.analyze_concurrency, config.channel_capacity);
     loop {
+        // Every cycle is recorded in the run history
+        let run_id = match database::start_run(conn).await {
+            Ok(id) => Some(id),
+            Err(e) => {
+                error!("Failed to record run start: {}", e);
+                None
+            }
+        };
         // 1. Fetch all current chats (dialogs) from Telegram
         let chat_list = match telegram::fetch_dialogs(client).await {
             Ok(list) => list,
             Err(e) => {
                 error!("Failed to fetch dialogs: {}", e);
+                finish_run(conn, run_id, 0, 0, 0, "failed").await;
                 // Wait and retry on next cycle
-                sleep(interval).await;
-                continue;
+                tokio::select! {
+                    _ = sleep(interval) => continue,
+                    _ = shutdown.wait() => break,
+                }
             }
         };
         // 2. Save/update chats in database (preserve existing AI insights)
         for chat in &chat_list {
-            if let Err(e) = database::save_chat(conn, chat) {
+            if let Err(e) = database::save_chat(conn, chat).await {
                 error!("DB error saving chat {}: {}", chat.title, e);
             }
-            
-            // Fetch and save member information
-            match telegram::fetch_chat_members(client, chat).await {
-                Ok(members) => {
-                    for (user_id, name, username, bio) in members {
-                        if let Err(e) = database::save_member(conn, &chat.peer_id, user_id, &name, username.as_deref(), bio.as_deref(), 0) {
-                            error!("Failed to save member {} for chat {}: {}", name, chat.title, e);
+        }
+        // Rules naming users or groups see this cycle's members
+        if let Err(e) = rules.refresh(conn).await {
+            error!("Failed to resolve users and groups for rules: {}", e);
+        }
+        // 3. Fetch, embed and analyze every chat through the staged pipeline.
+        // We'll collect categories for duplicate detection
+        let processing = Processing { owner: &owner, taxonomy: &taxonomy, rules: &rules, watchlists: &watchlists };
+        let mut outcome = run_pipeline(client, conn, &chat_list, &config, &processing, &notifiers, &shutdown).await;
+        // Embed messages stored while the embedding model was unavailable
+        if let Err(e) = fill_missing_embeddings(conn, EMBED_BACKLOG_PER_CYCLE, &shutdown).await {
+            error!("Failed to embed messages stored without embeddings: {}", e);
+        }
+        // Look up bios and full profiles of members, within the per-cycle budget
+        if let Err(e) = people::refresh_profiles(client, conn, &profiles, &shutdown).await {
+            error!("Failed to refresh user profiles: {}", e);
+        }
+        // 4. Detect duplicate chats by category similarity
+        if let Err(e) = mark_duplicates(conn, &mut outcome.categories).await {
+            error!("Failed to mark duplicate chats: {}", e);
+        }
+        // Keep the daily activity aggregates current for `stats`
+        if let Err(e) = database::refresh_daily_stats(conn).await {
+            error!("Failed to refresh daily statistics: {}", e);
+        }
+        let status = if shutdown.is_requested() { "interrupted" } else { "completed" };
+        finish_run(conn, run_id, chat_list.len(), outcome.messages_stored, outcome.chats_analyzed, status).await;
+        if shutdown.is_requested() {
+            break;
+        }
+        // 5. Deliver notifications held back by quiet hours, rate limits or failed sends
+        notifiers.dispatch(conn).await;
+        // 6. Send the periodic digest once per period, if configured
+        if let Some(period) = &digest_period {
+            if let Err(e) = digest::send_digest(client, conn, period, false).await {
+                error!("Failed to send digest: {}", e);
+            }
+        }
+        // Sleep until next cycle
+        info!("Cycle complete. Next check in 30 minutes.");
+        tokio::select! {
+            _ = sleep(interval) => {}
+            _ = shutdown.wait() => break,
+        }
+    }
+    info!("Monitoring loop stopped.");
+    Ok(())
+}
+
+/// Embed up to `limit` messages that were stored without an embedding. Stops at the first
+/// failure, since the model is most likely still unavailable; the rest waits for the next cycle.
+/// Returns the number of messages embedded.
+async fn fill_missing_embeddings(conn: &Connection, limit: i64, shutdown: &Shutdown) -> Result<usize, Box<dyn std::error::Error>> {
+    let missing = database::get_messages_without_embedding(conn, limit).await?;
+    let mut filled = 0;
+    for (chat_peer, msg_id, text) in &missing {
+        if shutdown.is_requested() {
+            break;
+        }
+        match ai::generate_embedding(text).await {
+            Ok(embedding) => {
+                database::set_message_embedding(conn, chat_peer, *msg_id, &embedding).await?;
+                filled += 1;
+            }
+            Err(e) => {
+                warn!("Embedding still unavailable, {} message(s) left without one: {}", missing.len() - filled, e);
+                break;
+            }
+        }
+    }
+    if filled > 0 {
+        info!("Embedded {} message(s) stored without an embedding", filled);
+    }
+    Ok(filled)
+}
+
+/// Process one cycle's chats as three stages connected by bounded channels:
+/// Telegram fetching, embedding + storage, and LLM analysis. Each stage runs
+/// with its own concurrency limit; a full channel makes the upstream stage wait,
+/// so a slow Ollama call throttles fetching instead of piling up work.
+/// Once `shutdown` fires, stages stop picking up new chats but let in-flight ones finish.
+/// The rules and watchlists run on every chat once its new messages are stored, whether or not
+/// they could be embedded, so their actions and alerts never depend on the model; chats the rules
+/// mark `skip_ai` never reach the analysis stage.
+/// Returns the (category, peer_id) pairs produced by the analysis stage and the cycle's counts.
+async fn run_pipeline(client: &Client, conn: &Connection, chat_list: &[ChatInfo], config: &PipelineConfig, processing: &Processing<'_>,
+    notifiers: &Notifiers, shutdown: &Shutdown)
+    -> PipelineOutcome
+{
+    let (embed_tx, embed_rx) = mpsc::channel::<(&ChatInfo, Vec<MessageInfo>)>(config.channel_capacity);
+    let (analyze_tx, analyze_rx) = mpsc::channel::<&ChatInfo>(config.channel_capacity);
+    let categories: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+    let messages_stored = AtomicUsize::new(0);
+    let chats_analyzed = AtomicUsize::new(0);
+    let messages_stored_ref = &messages_stored;
+
+    // Stage 1: fetch members and new messages from Telegram, flagging those addressed to us.
+    let fetch_stage = async move {
+        stream::iter(chat_list)
+            .for_each_concurrent(config.fetch_concurrency, |chat| {
+                let mut tx = embed_tx.clone();
+                let mut conn = conn.clone();
+                async move {
+                    if shutdown.is_requested() {
+                        return;
+                    }
+                    sync_members(client, &mut conn, chat).await;
+                    // Resume from the last ingested message recorded in the chat's checkpoint
+                    let last_id = match database::get_checkpoint(&conn, &chat.peer_id).await {
+                        Ok(checkpoint) => checkpoint.map(|c| c.last_ingested_msg_id).unwrap_or(0),
+                        Err(e) => {
+                            error!("Failed to read checkpoint for {}: {}", chat.title, e);
+                            return;
+                        }
+                    };
+                    match telegram::fetch_new_messages(client, chat, if last_id > 0 { Some(last_id) } else { None }).await {
+                        Ok(mut new_msgs) => {
+                            if !new_msgs.is_empty() {
+                                info!("{} new messages in chat \"{}\"", new_msgs.len(), chat.title);
+                            }
+                            if let Err(e) = mentions::detect(&conn, processing.owner, &chat.peer_id, &mut new_msgs).await {
+                                error!("Failed to detect mentions in {}: {}", chat.title, e);
+                            }
+                            if tx.send((chat, new_msgs)).await.is_err() {
+                                error!("Embedding stage closed; dropping chat {}", chat.title);
+                            }
+                        }
+                        Err(e) => {
+                            error!("Error fetching messages for chat {}: {}", chat.title, e);
                         }
                     }
                 }
-                Err(e) => {
-                    error!("Failed to fetch members for chat {}: {}", chat.title, e);
-                }
-            }
-        }
-        // 3. For each chat, fetch new messages and process AI analysis
-        // We'll collect categories for duplicate detection
-        let mut categories: Vec<(String, String)> = Vec::new();  // (category, peer_id)
-        for chat in &chat_list {
-            // Get last processed message ID for this chat from the database
-            let last_id = get_last_message_id(conn, &chat.peer_id).unwrap_or(0);
-            match telegram::fetch_new_messages(client, chat, if last_id > 0 { Some(last_id) } else { None }).await {
-                Ok(new_msgs) => {
-                    if !new_msgs.is_empty() {
-                        info!("{} new messages in chat \"{}\"", new_msgs.len(), chat.title);
+            })
+            .await;
+        // Dropping the last sender closes the channel so the next stage can finish.
+        drop(embed_tx);
+    };
+
+    // Stage 2: embed new messages, store them, apply the rules and watchlists, and queue chats needing analysis.
+    let embed_stage = async move {
+        embed_rx
+            .for_each_concurrent(config.embed_concurrency, |(chat, new_msgs)| {
+                let mut tx = analyze_tx.clone();
+                let mut conn = conn.clone();
+                async move {
+                    if shutdown.is_requested() {
+                        // Nothing is stored, so these messages are fetched again on the next start.
+                        return;
                     }
-                    // Save new messages to database
-                    if let Err(e) = database::save_messages(conn, &chat.peer_id, &new_msgs) {
+                    // Messages the embedding model couldn't handle are stored without an embedding
+                    // and embedded on a later cycle, so an unavailable model never holds them back
+                    let embedded = ai::embed_messages(new_msgs).await;
+                    if let Err(e) = database::save_messages(&mut conn, &chat.peer_id, &embedded).await {
+                        // Nothing is stored, so these messages are fetched again next cycle.
                         error!("DB error saving messages for {}: {}", chat.title, e);
+                        return;
+                    }
+                    messages_stored_ref.fetch_add(embedded.len(), Ordering::Relaxed);
+                    let stored: Vec<MessageInfo> = embedded.into_iter().map(|(msg, _)| msg).collect();
+                    // Watchlists and rules come first; their alerts are sent right away
+                    let mut alerts = match watch::scan_messages(&mut conn, processing.watchlists, &chat.peer_id, &stored).await {
+                        Ok(hits) => hits,
+                        Err(e) => {
+                            error!("Failed to match watchlists for {}: {}", chat.title, e);
+                            0
+                        }
+                    };
+                    let skip_ai = match rules::apply_to_chat(&mut conn, processing.rules, &chat.peer_id, &stored).await {
+                        Ok(outcome) => {
+                            alerts += outcome.urgent.len();
+                            outcome.skip_ai
+                        }
+                        Err(e) => {
+                            error!("Failed to apply rules to {}: {}", chat.title, e);
+                            false
+                        }
+                    };
+                    if alerts > 0 {
+                        notifiers.dispatch(&mut conn).await;
+                    }
+                    if skip_ai {
+                        return;
                     }
                     // Determine if we should run AI analysis:
-                    // If chat has no category yet, or new messages arrived (which might change urgency or context).
-                    let chat_category = get_chat_category(conn, &chat.peer_id);
-                    if chat_category.is_none() || !new_msgs.is_empty() {
-                        // Prepare message history for context: fetch last 20 messages from DB (including newly added).
-                        let recent_msgs = get_recent_messages(conn, &chat.peer_id, 20)?;
-                        // Run AI analysis on this chat's content
-                        match ai::analyze_chat(&chat.title, &recent_msgs).await {
-                            Ok((category, suggested_name, urgent_ids)) => {
-                                info!("Chat \"{}\": category=\"{}\", suggested_name=\"{}\"", chat.title, category, suggested_name);
-                                // Mark urgent messages in DB
-                                if let Err(e) = database::mark_urgent(conn, &chat.peer_id, &urgent_ids) {
-                                    error!("Failed to mark urgent messages for {}: {}", chat.title, e);
-                                }
-                                // We don't decide duplicate here; just store category and suggestion
-                                if let Err(e) = database::update_chat_analysis(conn, &chat.peer_id, &category, &suggested_name, false) {
-                                    error!("Failed to update analysis for {}: {}", chat.title, e);
-                                }
-                                categories.push((category, chat.peer_id.clone()));
-                            }
-                            Err(e) => {
-                                error!("AI analysis failed for chat {}: {}", chat.title, e);
-                            }
+                    // If chat has no category yet, or stored messages are newer than the analyzed checkpoint,
+                    // unless an earlier failure put the chat on a retry backoff that hasn't elapsed yet.
+                    let chat_category = get_chat_category(&conn, &chat.peer_id).await;
+                    let pending = match database::get_checkpoint(&conn, &chat.peer_id).await {
+                        Ok(checkpoint) => checkpoint.map(|c| c.analysis_pending()).unwrap_or(false),
+                        Err(e) => {
+                            error!("Failed to read checkpoint for {}: {}", chat.title, e);
+                            false
                         }
+                    };
+                    if (chat_category.is_none() || pending) && retry_due(&conn, &chat.peer_id).await {
+                        if tx.send(chat).await.is_err() {
+                            error!("Analysis stage closed; skipping chat {}", chat.title);
+                        }
+                    }
+                }
+            })
+            .await;
+        drop(analyze_tx);
+    };
+
+    // Stage 3: run the LLM analysis and store its results.
+    let model = ai::analysis_model();
+    let analyst = Analyst { model: &model, taxonomy: processing.taxonomy, notifiers };
+    let analyze_stage = analyze_rx.for_each_concurrent(config.analyze_concurrency, |chat| {
+        let mut conn = conn.clone();
+        let categories = &categories;
+        let chats_analyzed = &chats_analyzed;
+        let analyst = &analyst;
+        async move {
+            if shutdown.is_requested() {
+                return;
+            }
+            if let Some(category) = analyze_and_store(&mut conn, &chat.peer_id, &chat.title, analyst, None, shutdown).await.category {
+                categories.lock().unwrap().push((category, chat.peer_id.clone()));
+                chats_analyzed.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    });
+
+    futures::join!(fetch_stage, embed_stage, analyze_stage);
+    PipelineOutcome {
+        categories: categories.into_inner().unwrap(),
+        messages_stored: messages_stored.into_inner(),
+        chats_analyzed: chats_analyzed.into_inner(),
+    }
+}
+
+/// Helper: record the outcome of a cycle in the run history, logging failures.
+async fn finish_run(conn: &mut Connection, run_id: Option<i64>, chats: usize, messages_stored: usize, chats_analyzed: usize, status: &str) {
+    let Some(run_id) = run_id else {
+        return;
+    };
+    if let Err(e) = database::finish_run(conn, run_id, chats as i64, messages_stored as i64, chats_analyzed as i64, status).await {
+        error!("Failed to record run {}: {}", run_id, e);
+    }
+}
+
+/// Fetch and save member information for a chat, logging failures.
+async fn sync_members(client: &Client, conn: &mut Connection, chat: &ChatInfo) {
+    match telegram::fetch_chat_members(client, chat).await {
+        Ok(members) => {
+            for member in &members {
+                if let Err(e) = database::save_member(conn, &chat.peer_id, member, 0).await {
+                    error!("Failed to save member {} for chat {}: {}", member.name, chat.title, e);
+                }
+            }
+        }
+        Err(e) => {
+            error!("Failed to fetch members for chat {}: {}", chat.title, e);
+        }
+    }
+}
+
+/// Run AI analysis on a chat's unanalyzed messages and persist the results.
+/// Works through the backlog in windows of `ANALYSIS_WINDOW` messages starting at the
+/// analyzed checkpoint, advancing the checkpoint only after each window's results are stored.
+/// A failed window puts the chat on the retry queue with exponential backoff.
+/// `from` re-analyzes the messages after that id instead (0 for all of them); the checkpoint
+/// then only moves once the re-analysis passes it, and a failure is left to the caller.
+/// Newly flagged urgent messages are handed to the notification sinks right away.
+/// Recent user corrections are passed to the model as examples. Proposed tags below
+/// TAG_MIN_CONFIDENCE are dropped.
+/// Returns the latest category, used for duplicate detection, and how far the analysis got.
+async fn analyze_and_store(conn: &mut Connection, peer_id: &str, title: &str, analyst: &Analyst<'_>, from: Option<i32>, shutdown: &Shutdown)
+    -> AnalysisProgress
+{
+    let Analyst { model, taxonomy, notifiers } = *analyst;
+    let analyzed_up_to = match from {
+        Some(msg_id) => msg_id,
+        None => match database::get_checkpoint(conn, peer_id).await {
+            Ok(checkpoint) => checkpoint.unwrap_or_default().last_analyzed_msg_id,
+            Err(e) => {
+                error!("Failed to read checkpoint for {}: {}", title, e);
+                return AnalysisProgress { category: None, analyzed_up_to: 0, caught_up: false };
+            }
+        },
+    };
+    let mut progress = AnalysisProgress { category: None, analyzed_up_to, caught_up: false };
+    let corrections = database::get_recent_feedback(conn, FEEDBACK_EXAMPLES).await.unwrap_or_else(|e| {
+        error!("Failed to load feedback for {}: {}", title, e);
+        Vec::new()
+    });
+    let min_confidence = std::env::var("TAG_MIN_CONFIDENCE")
+        .ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .unwrap_or(DEFAULT_TAG_MIN_CONFIDENCE);
+    while !shutdown.is_requested() {
+        // A chat that was never analyzed starts from its most recent window;
+        // otherwise resume right after the last analyzed message (a re-analysis
+        // starts before the first one).
+        let window = if progress.analyzed_up_to == 0 && from.is_none() {
+            get_recent_messages(conn, peer_id, ANALYSIS_WINDOW).await
+        } else {
+            database::get_messages_after(conn, peer_id, progress.analyzed_up_to, ANALYSIS_WINDOW).await
+        };
+        let window = match window {
+            Ok(msgs) => msgs,
+            Err(e) => {
+                error!("Failed to load messages for {}: {}", title, e);
+                break;
+            }
+        };
+        let Some(window_end) = window.iter().map(|m| m.msg_id).max() else {
+            progress.caught_up = true;
+            break;
+        };
+        // Run AI analysis on this chat's content
+        match ai::analyze_chat(model, title, &window, taxonomy, &corrections).await {
+            Ok(analysis) => {
+                let ai::ChatAnalysis { category, suggested_name, urgent_ids, tags, message_tags } = analysis;
+                info!("Chat \"{}\": category=\"{}\", suggested_name=\"{}\"", title, category, suggested_name);
+                // Mark urgent messages in DB
+                match database::mark_urgent(conn, peer_id, &urgent_ids, "ai").await {
+                    Ok(newly_flagged) if !newly_flagged.is_empty() => notifiers.dispatch(conn).await,
+                    Ok(_) => {}
+                    Err(e) => {
+                        error!("Failed to mark urgent messages for {}: {}", title, e);
+                        break;
                     }
                 }
-                Err(e) => {
-                    error!("Error fetching messages for chat {}: {}", chat.title, e);
+                // When re-analyzing, unreviewed flags the model no longer raises are dropped
+                let window_ids: Vec<i32> = window.iter().map(|m| m.msg_id).collect();
+                match database::clear_stale_urgent(conn, peer_id, &window_ids, &urgent_ids).await {
+                    Ok(0) => {}
+                    Ok(cleared) => info!("Chat \"{}\": {} message(s) no longer urgent", title, cleared),
+                    Err(e) => {
+                        error!("Failed to clear stale urgent flags for {}: {}", title, e);
+                        break;
+                    }
+                }
+                // We don't decide duplicate here; just store category and suggestion.
+                // A category overridden by the user is kept, so continue with the stored one.
+                let category = match database::update_chat_analysis(conn, peer_id, &category, &suggested_name, false).await {
+                    Ok(stored) => stored,
+                    Err(e) => {
+                        error!("Failed to update analysis for {}: {}", title, e);
+                        break;
+                    }
+                };
+                let chat_tags: Vec<(String, f64)> = tags.into_iter()
+                    .filter(|t| t.confidence >= min_confidence)
+                    .map(|t| (t.name, t.confidence))
+                    .collect();
+                let message_tags: Vec<(i32, String, f64)> = message_tags.into_iter()
+                    .filter(|(_, t)| t.confidence >= min_confidence)
+                    .map(|(msg_id, t)| (msg_id, t.name, t.confidence))
+                    .collect();
+                if let Err(e) = database::store_ai_tags(conn, peer_id, &chat_tags, &message_tags).await {
+                    error!("Failed to store tags for {}: {}", title, e);
+                    break;
+                }
+                // Results are stored; only now is it safe to move the checkpoint forward
+                // (it never moves back, so a re-analysis leaves it alone until it passes it)
+                if let Err(e) = database::set_analyzed_checkpoint(conn, peer_id, window_end).await {
+                    error!("Failed to save analysis checkpoint for {}: {}", title, e);
+                    break;
+                }
+                progress.analyzed_up_to = window_end;
+                progress.category = Some(category);
+                if let Err(e) = database::clear_analysis_retry(conn, peer_id).await {
+                    error!("Failed to clear retry state for {}: {}", title, e);
                 }
             }
+            Err(e) => {
+                let message = e.to_string();
+                error!("AI analysis failed for chat {}: {}", title, message);
+                if from.is_none() {
+                    schedule_retry(conn, peer_id, title, &message).await;
+                }
+                return progress;
+            }
         }
-        // 4. Detect duplicate chats by category similarity
-        mark_duplicates(conn, &mut categories)?;
-        // Sleep until next cycle
-        info!("Cycle complete. Next check in 30 minutes.");
-        sleep(interval).await;
     }
+    progress
 }
 
-/// Helper: get the last processed message ID for a chat from the DB.
-fn get_last_message_id(conn: &rusqlite::Connection, chat_peer: &str) -> Option<i32> {
-    let mut stmt = conn.prepare("SELECT MAX(msg_id) FROM messages WHERE chat_peer = ?1;").ok()?;
-    let result = stmt.query_row([chat_peer], |row| row.get::<_, Option<i32>>(0));
-    match result {
-        Ok(opt) => opt,
-        Err(_) => None,
+/// Helper: true unless the chat is waiting out a retry backoff after a failed analysis.
+async fn retry_due(conn: &Connection, chat_peer: &str) -> bool {
+    match database::get_analysis_retry(conn, chat_peer).await {
+        Ok(Some((_attempts, next_attempt_at))) => next_attempt_at <= Utc::now().timestamp(),
+        Ok(None) => true,
+        Err(e) => {
+            error!("Failed to read retry state for {}: {}", chat_peer, e);
+            true
+        }
     }
 }
 
+/// Put a chat on the retry queue, doubling the delay for each consecutive failure.
+async fn schedule_retry(conn: &mut Connection, peer_id: &str, title: &str, error_msg: &str) {
+    let attempts = match database::get_analysis_retry(conn, peer_id).await {
+        Ok(state) => state.map(|(a, _)| a).unwrap_or(0) + 1,
+        Err(e) => {
+            error!("Failed to read retry state for {}: {}", title, e);
+            1
+        }
+    };
+    let delay = retry_delay_secs(attempts);
+    let next_attempt_at = Utc::now().timestamp() + delay;
+    match database::record_analysis_failure(conn, peer_id, attempts, next_attempt_at, error_msg).await {
+        Ok(()) => info!("Analysis of \"{}\" will be retried in {}s (attempt {})", title, delay, attempts + 1),
+        Err(e) => error!("Failed to record retry state for {}: {}", title, e),
+    }
+}
+
+/// Helper: exponential backoff delay for the given number of consecutive failures.
+fn retry_delay_secs(attempts: i32) -> i64 {
+    let exponent = (attempts.max(1) - 1).min(20) as u32;
+    (RETRY_BASE_DELAY_SECS << exponent).min(RETRY_MAX_DELAY_SECS)
+}
+
+/// Re-run analysis for one chat (by peer id or name) or, with `None`, every stored chat,
+/// using `model` and `taxonomy`. All stored messages are analyzed again window by window,
+/// oldest first, and urgent flags the model no longer raises are cleared unless they were
+/// reviewed. The analyzed checkpoint is left alone, so a re-analysis that fails or is
+/// interrupted is not picked up by the monitor loop (which would use its own model); it is
+/// reported and can be run again.
+pub async fn reanalyze(conn: &mut Connection, target: Option<&str>, model: &str, taxonomy: &Taxonomy, notifiers: &Notifiers, shutdown: &Shutdown)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let chats: Vec<(String, String)> = database::get_chats(conn).await?
+        .into_iter()
+        .filter(|(peer_id, name)| target.map_or(true, |t| peer_id == t || name.eq_ignore_ascii_case(t)))
+        .collect();
+    if chats.is_empty() {
+        return Err(format!("No chat matches \"{}\"", target.unwrap_or("")).into());
+    }
+    info!("Re-analyzing {} chat(s) with model {}", chats.len(), model);
+    let analyst = Analyst { model, taxonomy, notifiers };
+    let mut categories = Vec::new();
+    for (peer_id, name) in &chats {
+        if shutdown.is_requested() {
+            break;
+        }
+        let windows = (database::count_messages(conn, peer_id).await? as usize).div_ceil(ANALYSIS_WINDOW);
+        if windows > REANALYZE_WARN_WINDOWS {
+            warn!("Re-analyzing \"{}\" takes {} model calls ({} messages per call)", name, windows, ANALYSIS_WINDOW);
+        }
+        let progress = analyze_and_store(conn, peer_id, name, &analyst, Some(0), shutdown).await;
+        if let Some(category) = progress.category {
+            categories.push((category, peer_id.clone()));
+        }
+        if !progress.caught_up {
+            error!("Re-analysis of \"{}\" stopped after message #{}; run reanalyze again to redo it.", name, progress.analyzed_up_to);
+        }
+    }
+    mark_duplicates(conn, &mut categories).await?;
+    Ok(())
+}
+
 /// Helper: get recent messages for a chat from DB, up to `limit` count, sorted by ascending date.
-fn get_recent_messages(conn: &rusqlite::Connection, chat_peer: &str, limit: usize) 
-    -> Result<Vec<telegram::MessageInfo>, Box<dyn std::error::Error>> 
+async fn get_recent_messages(conn: &Connection, chat_peer: &str, limit: usize)
+    -> Result<Vec<telegram::MessageInfo>, Box<dyn std::error::Error>>
 {
     let mut stmt = conn.prepare(
-        "SELECT msg_id, date, text FROM messages 
-         WHERE chat_peer = ?1 
-         ORDER BY msg_id DESC 
+        "SELECT msg_id, date, text, sender_id, outgoing, reply_to, mentions_me FROM chat_messages
+         WHERE chat_peer = ?1
+         ORDER BY msg_id DESC
          LIMIT ?2;"
-    )?;
-    let limit_str = limit.to_string();
-    let rows = stmt.query_map([chat_peer, &limit_str], |row| {
-        Ok(telegram::MessageInfo {
+    ).await?;
+    let mut rows = stmt.query(&[&chat_peer, &(limit as i64)]).await?;
+    let mut messages = Vec::new();
+    while let Some(row) = rows.next().await? {
+        messages.push(telegram::MessageInfo {
             msg_id: row.get(0)?,
             date: row.get(1)?,
             text: row.get(2)?,
-        })
-    })?;
-    let mut messages = Vec::new();
-    for result in rows {
-        messages.push(result?);
+            sender_id: row.get(3)?,
+            outgoing: row.get::<i32>(4)? != 0,
+            reply_to: row.get(5)?,
+            mentions_me: row.get::<i32>(6)? != 0,
+        });
     }
     // The query gave descending by msg_id, reverse to ascending chronological order
     messages.reverse();
@@ -132,19 +628,17 @@ fn get_recent_messages(conn: &rusqlite::Connection, chat_peer: &str, limit: usiz
 }
 
 /// Helper: get the current category for a chat from DB.
-fn get_chat_category(conn: &rusqlite::Connection, chat_peer: &str) -> Option<String> {
-    let mut stmt = conn.prepare("SELECT category FROM chats WHERE peer_id = ?1;").ok()?;
-    let result = stmt.query_row([chat_peer], |row| row.get::<_, Option<String>>(0));
-    match result {
-        Ok(opt) => opt,
-        Err(_) => None,
-    }
+async fn get_chat_category(conn: &Connection, chat_peer: &str) -> Option<String> {
+    let mut stmt = conn.prepare("SELECT category FROM chats WHERE peer_id = ?1;").await.ok()?;
+    let mut rows = stmt.query(&[&chat_peer]).await.ok()?;
+    let row = rows.next().await.ok()??;
+    row.get::<Option<String>>(0).ok()?
 }
 
-/// Determine duplicate-topic chats based on categories. 
+/// Determine duplicate-topic chats based on categories.
 /// If multiple chats share the same category label (case-insensitive), mark them as duplicates.
-fn mark_duplicates(conn: &mut rusqlite::Connection, categories: &mut Vec<(String, String)>) 
-    -> Result<(), Box<dyn std::error::Error>> 
+async fn mark_duplicates(conn: &mut Connection, categories: &mut Vec<(String, String)>)
+    -> Result<(), Box<dyn std::error::Error>>
 {
     if categories.is_empty() {
         return Ok(());
@@ -152,102 +646,44 @@ fn mark_duplicates(conn: &mut rusqlite::Connection, categories: &mut Vec<(String
 
     // First, collect all categories (including existing ones) into a HashMap
     let mut cat_map: HashMap<String, Vec<String>> = HashMap::new();
-    
+
     // Add categories from current cycle
     for (cat, pid) in categories.drain(..) {
         let key = cat.to_lowercase();
         cat_map.entry(key).or_default().push(pid);
     }
-    
+
     // Add existing categories from database
     {
-        let mut stmt = conn.prepare("SELECT category, peer_id FROM chats WHERE category IS NOT NULL;")?;
-        let rows = stmt.query_map([], |row| {
-            Ok((row.get::<_, String>(0)?.to_lowercase(), row.get::<_, String>(1)?))
-        })?;
-        
-        for res in rows {
-            let (cat, pid) = res?;
-            cat_map.entry(cat).or_default().push(pid);
+        let mut stmt = conn.prepare("SELECT category, peer_id FROM chats WHERE category IS NOT NULL;").await?;
+        let mut rows = stmt.query(&[]).await?;
+        while let Some(row) = rows.next().await? {
+            let cat: String = row.get(0)?;
+            let pid: String = row.get(1)?;
+            let peers = cat_map.entry(cat.to_lowercase()).or_default();
+            // A chat analyzed this cycle is already present under its new category
+            if !peers.contains(&pid) {
+                peers.push(pid);
+            }
         }
     }
 
     // Now update duplicate flags
-    let tx = conn.transaction()?;
-    
+    let mut tx = conn.transaction().await?;
+
     // Reset all flags first
-    tx.execute("UPDATE chats SET duplicate = 0;", [])?;
-    
+    tx.execute("UPDATE chats SET duplicate = 0;", &[]).await?;
+
     // Set duplicate flag for chats in categories with multiple entries
-    for (_cat, peers) in cat_map {
-        if peers.len() > 1 {
+    // (the catch-all bucket says nothing about chats being alike)
+    for (cat, peers) in cat_map {
+        if peers.len() > 1 && cat != taxonomy::OTHER.to_lowercase() {
             for peer_id in peers {
-                tx.execute("UPDATE chats SET duplicate = 1 WHERE peer_id = ?1;", [&peer_id])?;
+                tx.execute("UPDATE chats SET duplicate = 1 WHERE peer_id = ?1;", &[&peer_id]).await?;
             }
         }
     }
-    
-    tx.commit()?;
-    Ok(())
-}
 
-/// Generate a report of all chats and any urgent messages, printing to stdout.
-pub fn print_report(conn: &rusqlite::Connection) -> Result<(), Box<dyn std::error::Error>> {
-    println!("=== Telegram Chats Report ===");
-    let mut stmt = conn.prepare(
-        "SELECT peer_id, name, category, suggested_name, duplicate FROM chats ORDER BY name COLLATE NOCASE;"
-    )?;
-    let chat_rows = stmt.query_map([], |row| {
-        Ok((
-            row.get::<_, String>(0)?, // peer_id
-            row.get::<_, String>(1)?, // name
-            row.get::<_, Option<String>>(2)?, // category (nullable)
-            row.get::<_, Option<String>>(3)?, // suggested_name (nullable)
-            row.get::<_, i32>(4)?      // duplicate flag as 0/1
-        ))
-    })?;
-    for chat_result in chat_rows {
-        let (peer_id, name, category, suggested, duplicate_flag) = chat_result?;
-        let category_str = category.unwrap_or_else(|| "Uncategorized".into());
-        let suggested_str = suggested.unwrap_or_else(|| "-".into());
-        let duplicate_str = if duplicate_flag != 0 { "Yes" } else { "No" };
-        println!("\nChat: {}{}", name, if duplicate_flag != 0 { " (Duplicate Topic)" } else { "" });
-        println!(" - Category: {}", category_str);
-        println!(" - Suggested Name: {}", suggested_str);
-        println!(" - Duplicate: {}", duplicate_str);
-        
-        // Print member information
-        println!("\n Members:");
-        if let Ok(members) = database::get_chat_members(conn, &peer_id) {
-            for (_user_id, name, username, bio) in members {
-                println!("   * {} (@{})", name, username.unwrap_or_else(|| "-".to_string()));
-                if let Some(bio_text) = bio {
-                    // Indent and wrap bio text for better readability
-                    for line in textwrap::wrap(&bio_text, 60) {
-                        println!("     Bio: {}", line);
-                    }
-                }
-            }
-        }
-        
-        // Fetch urgent messages for this chat
-        let mut msg_stmt = conn.prepare(
-            "SELECT date, text FROM messages 
-             WHERE chat_peer = ?1 AND urgent = 1 ORDER BY date ASC;"
-        )?;
-        let msg_iter = msg_stmt.query_map([peer_id], |row| {
-            Ok((row.get::<_, i32>(0)?, row.get::<_, String>(1)?))
-        })?;
-        for msg in msg_iter {
-            let (ts, text) = msg?;
-            // Format timestamp to human-readable using chrono
-            let dt = DateTime::<Utc>::from_timestamp(ts as i64, 0)
-                .expect("Invalid timestamp");
-            let datetime_str = dt.format("%Y-%m-%d %H:%M:%S").to_string();
-            let snippet = if text.len() > 50 { &text[0..50] } else { &text };
-            println!("   * [URGENT @ {}] {}", datetime_str, snippet);
-        }
-    }
-    println!("\nEnd of report.");
+    tx.commit().await?;
     Ok(())
-} 
\ No newline at end of file
+}
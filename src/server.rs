@@ -0,0 +1,489 @@
+use std::sync::Arc;
+use log::{info, warn};
+use libsql::Connection;
+use serde::{Deserialize, Serialize};
+use axum::{Json, Router};
+use axum::extract::{Path, Query, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use crate::{database, ai, dashboard};
+use crate::database::{ChatActivity, ChatFilter, MessageHit, MessageRecord, ReportQuery, ReportSort, RunRecord, TagCount, TagRecord, UrgentState};
+use crate::report::Member;
+use crate::shutdown::Shutdown;
+
+/// Address the API listens on unless SERVE_ADDR or `--addr` says otherwise.
+pub const DEFAULT_ADDR: &str = "127.0.0.1:8787";
+/// Page size used when a request doesn't give `limit`.
+const DEFAULT_PAGE_SIZE: i64 = 50;
+/// Largest page a single request may ask for.
+const MAX_PAGE_SIZE: i64 = 500;
+
+/// Shared state of the HTTP handlers.
+#[derive(Clone)]
+pub struct AppState {
+    pub conn: Connection,
+    pub token: Arc<String>,
+}
+
+/// Serve the JSON API and the web dashboard on `addr` until `shutdown` fires. Every API
+/// endpoint except the OpenAPI description requires `Authorization: Bearer <token>`;
+/// the dashboard asks for the same token once and keeps it in a cookie.
+pub async fn serve(conn: Connection, addr: &str, token: String, mut shutdown: Shutdown) -> Result<(), Box<dyn std::error::Error>> {
+    if token.trim().is_empty() {
+        return Err("API_TOKEN must not be empty".into());
+    }
+    let state = AppState { conn, token: Arc::new(token) };
+
+    let api = Router::new()
+        .route("/api/chats", get(list_chats))
+        .route("/api/chats/:peer", get(get_chat))
+        .route("/api/chats/:peer/messages", get(list_messages))
+        .route("/api/chats/:peer/members", get(list_members))
+        .route("/api/chats/:peer/tags", get(list_chat_tags))
+        .route("/api/tags", get(list_tags))
+        .route("/api/search", get(search))
+        .route("/api/urgent", get(list_urgent))
+        .route("/api/urgent/state", post(change_urgent_state))
+        .route("/api/duplicates", get(list_duplicates))
+        .route("/api/runs", get(list_runs))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_token));
+    let app = Router::new()
+        .route("/api/openapi.json", get(openapi))
+        .merge(api)
+        .merge(dashboard::routes(state.clone()))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    let local = listener.local_addr()?;
+    if !local.ip().is_loopback() {
+        warn!("API server is listening on {}, which is reachable from other machines.", local);
+    }
+    info!("Dashboard at http://{}/ (API description at /api/openapi.json)", local);
+    axum::serve(listener, app)
+        .with_graceful_shutdown(async move { shutdown.wait().await })
+        .await?;
+    info!("API server stopped.");
+    Ok(())
+}
+
+/// Middleware: reject requests without the configured bearer token.
+async fn require_token(State(state): State<AppState>, request: Request, next: Next) -> Response {
+    let presented = request.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    match presented {
+        Some(token) if token_matches(token, &state.token) => next.run(request).await,
+        _ => ApiError(StatusCode::UNAUTHORIZED, "Missing or invalid bearer token".into()).into_response(),
+    }
+}
+
+/// Helper: compare tokens without bailing out at the first differing byte.
+pub fn token_matches(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len()
+        && presented.bytes().zip(expected.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+/// An error answered as `{"error": "..."}` with the given status.
+struct ApiError(StatusCode, String);
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(serde_json::json!({ "error": self.1 }))).into_response()
+    }
+}
+
+impl From<Box<dyn std::error::Error>> for ApiError {
+    fn from(e: Box<dyn std::error::Error>) -> Self {
+        ApiError(StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+    }
+}
+
+type ApiResult<T> = Result<Json<T>, ApiError>;
+
+/// Query parameters shared by the list endpoints. Times are UNIX timestamps.
+#[derive(Debug, Default, Deserialize)]
+struct ListParams {
+    chat: Option<String>,
+    chat_exact: Option<String>,
+    category: Option<String>,
+    tag: Option<String>,
+    since: Option<i64>,
+    until: Option<i64>,
+    sort: Option<String>,
+    #[serde(default)]
+    only_urgent: bool,
+    state: Option<String>,  // urgent states, comma-separated, or all
+    limit: Option<i64>,
+}
+
+impl ListParams {
+    fn filter(&self) -> ChatFilter {
+        ChatFilter {
+            chat: self.chat.clone(),
+            chat_exact: self.chat_exact.clone(),
+            category: self.category.clone(),
+            tag: self.tag.clone(),
+            since: self.since,
+            until: self.until,
+        }
+    }
+
+    fn limit(&self) -> i64 {
+        page_size(self.limit)
+    }
+}
+
+/// Helper: clamp a requested page size.
+fn page_size(limit: Option<i64>) -> i64 {
+    limit.unwrap_or(DEFAULT_PAGE_SIZE).clamp(1, MAX_PAGE_SIZE)
+}
+
+/// Helper: parse the `sort` parameter of chat listings.
+fn parse_sort(sort: Option<&str>) -> Result<ReportSort, ApiError> {
+    match sort {
+        None | Some("name") => Ok(ReportSort::Name),
+        Some("activity") => Ok(ReportSort::Activity),
+        Some("urgency") => Ok(ReportSort::Urgency),
+        Some(other) => Err(ApiError(StatusCode::BAD_REQUEST, format!("Unknown sort \"{}\" (expected name, activity or urgency)", other))),
+    }
+}
+
+/// GET /api/chats — chats with category, suggested name, duplicate flag and activity counts.
+async fn list_chats(State(state): State<AppState>, Query(params): Query<ListParams>) -> ApiResult<Vec<ChatActivity>> {
+    let query = ReportQuery {
+        filter: params.filter(),
+        only_urgent: params.only_urgent,
+        sort: parse_sort(params.sort.as_deref())?,
+        ..Default::default()
+    };
+    Ok(Json(database::get_report_chats(&state.conn, &query).await?))
+}
+
+/// GET /api/chats/{peer} — one chat by peer id.
+async fn get_chat(State(state): State<AppState>, Path(peer): Path<String>) -> ApiResult<ChatActivity> {
+    let query = ReportQuery {
+        filter: ChatFilter { chat_exact: Some(peer.clone()), ..Default::default() },
+        ..Default::default()
+    };
+    database::get_report_chats(&state.conn, &query).await?
+        .into_iter()
+        .find(|c| c.chat.peer_id == peer)
+        .map(Json)
+        .ok_or_else(|| ApiError(StatusCode::NOT_FOUND, format!("No chat with peer id \"{}\"", peer)))
+}
+
+#[derive(Debug, Deserialize)]
+struct PageParams {
+    before: Option<i32>,
+    limit: Option<i64>,
+}
+
+/// One page of messages; pass `next_before` as `before` to get the next (older) page.
+#[derive(Serialize)]
+struct MessagePage {
+    messages: Vec<MessageRecord>,
+    next_before: Option<i32>,
+}
+
+/// GET /api/chats/{peer}/messages — a chat's messages, newest first, paged by message id.
+async fn list_messages(State(state): State<AppState>, Path(peer): Path<String>, Query(params): Query<PageParams>) -> ApiResult<MessagePage> {
+    let limit = page_size(params.limit);
+    let messages = database::get_message_page(&state.conn, &peer, params.before, limit).await?;
+    let next_before = if messages.len() as i64 == limit { messages.last().map(|m| m.msg_id) } else { None };
+    Ok(Json(MessagePage { messages, next_before }))
+}
+
+/// GET /api/chats/{peer}/members — known members of a chat.
+async fn list_members(State(state): State<AppState>, Path(peer): Path<String>) -> ApiResult<Vec<Member>> {
+    let members = database::get_chat_members(&state.conn, &peer).await?
+        .into_iter()
+        .map(|(user_id, name, username, bio)| Member { user_id, name, username, bio })
+        .collect();
+    Ok(Json(members))
+}
+
+/// GET /api/chats/{peer}/tags — tags of a chat and of its messages.
+async fn list_chat_tags(State(state): State<AppState>, Path(peer): Path<String>) -> ApiResult<Vec<TagRecord>> {
+    Ok(Json(database::get_tags_for_chat(&state.conn, &peer).await?))
+}
+
+/// GET /api/tags — every tag in use with chat and message counts.
+async fn list_tags(State(state): State<AppState>) -> ApiResult<Vec<TagCount>> {
+    Ok(Json(database::get_tag_counts(&state.conn).await?))
+}
+
+// Not flattened from ListParams: serde_urlencoded can't parse numbers inside flattened structs
+#[derive(Debug, Deserialize)]
+struct SearchParams {
+    q: String,
+    mode: Option<String>,
+    chat: Option<String>,
+    chat_exact: Option<String>,
+    category: Option<String>,
+    tag: Option<String>,
+    since: Option<i64>,
+    until: Option<i64>,
+    limit: Option<i64>,
+}
+
+/// GET /api/search — keyword (substring) or semantic (embedding similarity) message search.
+async fn search(State(state): State<AppState>, Query(params): Query<SearchParams>) -> ApiResult<Vec<MessageHit>> {
+    if params.q.trim().is_empty() {
+        return Err(ApiError(StatusCode::BAD_REQUEST, "Query parameter q must not be empty".into()));
+    }
+    let filter = ChatFilter {
+        chat: params.chat.clone(),
+        chat_exact: params.chat_exact.clone(),
+        category: params.category.clone(),
+        tag: params.tag.clone(),
+        since: params.since,
+        until: params.until,
+    };
+    let limit = page_size(params.limit);
+    let hits = match params.mode.as_deref() {
+        None | Some("keyword") => database::search_messages(&state.conn, &params.q, &filter, limit).await?,
+        Some("semantic") => {
+            let embedding = ai::generate_embedding(&params.q).await
+                .map_err(|e| ApiError(StatusCode::BAD_GATEWAY, format!("Embedding the query failed: {}", e)))?;
+            database::semantic_search(&state.conn, &embedding, &filter, limit).await?
+        }
+        Some(other) => return Err(ApiError(StatusCode::BAD_REQUEST, format!("Unknown search mode \"{}\" (expected keyword or semantic)", other))),
+    };
+    Ok(Json(hits))
+}
+
+/// GET /api/urgent — urgent messages across chats, newest first; open ones unless `state` says otherwise.
+async fn list_urgent(State(state): State<AppState>, Query(params): Query<ListParams>) -> ApiResult<Vec<MessageHit>> {
+    let states = match params.state.as_deref() {
+        None => UrgentState::OPEN.to_vec(),
+        Some("all") => UrgentState::ALL.to_vec(),
+        Some(list) => list.split(',')
+            .map(UrgentState::parse)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| ApiError(StatusCode::BAD_REQUEST, e.to_string()))?,
+    };
+    Ok(Json(database::get_urgent_messages(&state.conn, &params.filter(), &states, params.limit()).await?))
+}
+
+#[derive(Debug, Deserialize)]
+struct StateChange {
+    chat_peer: String,
+    msg_id: i32,
+    state: String,
+    by: String,
+}
+
+/// POST /api/urgent/state — change the review state of an urgent message.
+async fn change_urgent_state(State(state): State<AppState>, Json(change): Json<StateChange>) -> Result<StatusCode, ApiError> {
+    let target = UrgentState::parse(&change.state).map_err(|e| ApiError(StatusCode::BAD_REQUEST, e.to_string()))?;
+    let mut conn = state.conn.clone();
+    if !database::set_urgent_state(&mut conn, &change.chat_peer, change.msg_id, target, &change.by).await? {
+        return Err(ApiError(StatusCode::NOT_FOUND, format!("No urgent message #{} in {}", change.msg_id, change.chat_peer)));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// GET /api/duplicates — chats flagged as duplicate topics.
+async fn list_duplicates(State(state): State<AppState>) -> ApiResult<Vec<ChatActivity>> {
+    let query = ReportQuery { only_duplicates: true, ..Default::default() };
+    Ok(Json(database::get_report_chats(&state.conn, &query).await?))
+}
+
+/// GET /api/runs — monitoring cycle history, newest first.
+async fn list_runs(State(state): State<AppState>, Query(params): Query<ListParams>) -> ApiResult<Vec<RunRecord>> {
+    Ok(Json(database::get_runs(&state.conn, params.limit()).await?))
+}
+
+/// GET /api/openapi.json — OpenAPI 3 description of the endpoints above.
+async fn openapi() -> Json<serde_json::Value> {
+    Json(openapi_spec())
+}
+
+/// Build the OpenAPI description.
+fn openapi_spec() -> serde_json::Value {
+    use serde_json::json;
+    let filter_params = json!([
+        { "name": "chat", "in": "query", "schema": { "type": "string" }, "description": "Chat peer id or name pattern (* and ? wildcards)" },
+        { "name": "chat_exact", "in": "query", "schema": { "type": "string" }, "description": "Chat peer id or exact name" },
+        { "name": "category", "in": "query", "schema": { "type": "string" } },
+        { "name": "tag", "in": "query", "schema": { "type": "string" }, "description": "Tag on the chat or the message" },
+        { "name": "since", "in": "query", "schema": { "type": "integer" }, "description": "UNIX timestamp; messages at or after" },
+        { "name": "until", "in": "query", "schema": { "type": "integer" }, "description": "UNIX timestamp; messages before" },
+    ]);
+    let limit_param = json!({ "name": "limit", "in": "query", "schema": { "type": "integer", "default": DEFAULT_PAGE_SIZE, "maximum": MAX_PAGE_SIZE } });
+    let peer_param = json!({ "name": "peer", "in": "path", "required": true, "schema": { "type": "string" }, "description": "Chat peer id, e.g. channel:123" });
+    let with = |extra: Vec<serde_json::Value>| {
+        let mut params = filter_params.as_array().cloned().unwrap_or_default();
+        params.extend(extra);
+        serde_json::Value::Array(params)
+    };
+    let ok = |schema: serde_json::Value| json!({
+        "200": { "description": "OK", "content": { "application/json": { "schema": schema } } },
+        "401": { "$ref": "#/components/responses/Unauthorized" },
+    });
+    let list_of = |name: &str| json!({ "type": "array", "items": { "$ref": format!("#/components/schemas/{}", name) } });
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Telegram Chat Organizer API",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Read-only access to the organized chats, messages and analysis results.",
+        },
+        "security": [{ "bearerAuth": [] }],
+        "paths": {
+            "/api/chats": { "get": {
+                "summary": "List chats with analysis results and activity counts",
+                "parameters": with(vec![
+                    json!({ "name": "sort", "in": "query", "schema": { "type": "string", "enum": ["name", "activity", "urgency"] } }),
+                    json!({ "name": "only_urgent", "in": "query", "schema": { "type": "boolean" } }),
+                ]),
+                "responses": ok(list_of("Chat")),
+            }},
+            "/api/chats/{peer}": { "get": {
+                "summary": "Get one chat",
+                "parameters": [peer_param.clone()],
+                "responses": ok(json!({ "$ref": "#/components/schemas/Chat" })),
+            }},
+            "/api/chats/{peer}/messages": { "get": {
+                "summary": "Page through a chat's messages, newest first",
+                "parameters": [
+                    peer_param.clone(),
+                    { "name": "before", "in": "query", "schema": { "type": "integer" }, "description": "Only messages with a smaller id (next_before of the previous page)" },
+                    limit_param.clone(),
+                ],
+                "responses": ok(json!({
+                    "type": "object",
+                    "properties": {
+                        "messages": list_of("Message"),
+                        "next_before": { "type": "integer", "nullable": true },
+                    },
+                })),
+            }},
+            "/api/chats/{peer}/members": { "get": {
+                "summary": "List a chat's members",
+                "parameters": [peer_param.clone()],
+                "responses": ok(list_of("Member")),
+            }},
+            "/api/chats/{peer}/tags": { "get": {
+                "summary": "List the tags of a chat and its messages",
+                "parameters": [peer_param],
+                "responses": ok(list_of("Tag")),
+            }},
+            "/api/tags": { "get": {
+                "summary": "List tags in use with chat and message counts",
+                "responses": ok(json!({ "type": "array", "items": { "type": "object", "properties": {
+                    "name": { "type": "string" },
+                    "chats": { "type": "integer" },
+                    "messages": { "type": "integer" },
+                }}})),
+            }},
+            "/api/search": { "get": {
+                "summary": "Search messages by keyword or by meaning",
+                "parameters": with(vec![
+                    json!({ "name": "q", "in": "query", "required": true, "schema": { "type": "string" } }),
+                    json!({ "name": "mode", "in": "query", "schema": { "type": "string", "enum": ["keyword", "semantic"], "default": "keyword" } }),
+                    limit_param.clone(),
+                ]),
+                "responses": ok(list_of("MessageHit")),
+            }},
+            "/api/urgent": { "get": {
+                "summary": "List urgent messages, newest first",
+                "parameters": with(vec![
+                    json!({ "name": "state", "in": "query", "schema": { "type": "string" },
+                        "description": "Comma-separated states (new, acknowledged, resolved, false_positive) or all; default new,acknowledged" }),
+                    limit_param.clone(),
+                ]),
+                "responses": ok(list_of("MessageHit")),
+            }},
+            "/api/urgent/state": { "post": {
+                "summary": "Change the review state of an urgent message",
+                "requestBody": { "required": true, "content": { "application/json": { "schema": {
+                    "type": "object",
+                    "required": ["chat_peer", "msg_id", "state", "by"],
+                    "properties": {
+                        "chat_peer": { "type": "string" },
+                        "msg_id": { "type": "integer" },
+                        "state": { "type": "string", "enum": ["new", "acknowledged", "resolved", "false_positive"] },
+                        "by": { "type": "string", "description": "Who made the change" },
+                    },
+                }}}},
+                "responses": {
+                    "204": { "description": "State changed" },
+                    "401": { "$ref": "#/components/responses/Unauthorized" },
+                    "404": { "description": "No such urgent message" },
+                },
+            }},
+            "/api/duplicates": { "get": {
+                "summary": "List chats flagged as duplicate topics",
+                "responses": ok(list_of("Chat")),
+            }},
+            "/api/runs": { "get": {
+                "summary": "Monitoring cycle history, newest first",
+                "parameters": [limit_param],
+                "responses": ok(list_of("Run")),
+            }},
+        },
+        "components": {
+            "securitySchemes": { "bearerAuth": { "type": "http", "scheme": "bearer" } },
+            "responses": {
+                "Unauthorized": { "description": "Missing or invalid bearer token" },
+            },
+            "schemas": {
+                "Chat": { "type": "object", "properties": {
+                    "peer_id": { "type": "string" },
+                    "kind": { "type": "string" },
+                    "name": { "type": "string" },
+                    "category": { "type": "string", "nullable": true },
+                    "suggested_name": { "type": "string", "nullable": true },
+                    "duplicate": { "type": "boolean" },
+                    "tags": { "type": "array", "items": { "type": "string" } },
+                    "message_count": { "type": "integer" },
+                    "urgent_count": { "type": "integer" },
+                    "last_activity": { "type": "integer", "nullable": true },
+                }},
+                "Message": { "type": "object", "properties": {
+                    "chat_peer": { "type": "string" },
+                    "msg_id": { "type": "integer" },
+                    "date": { "type": "integer" },
+                    "text": { "type": "string" },
+                    "urgent": { "type": "boolean" },
+                }},
+                "MessageHit": { "allOf": [
+                    { "$ref": "#/components/schemas/Message" },
+                    { "type": "object", "properties": {
+                        "chat_name": { "type": "string" },
+                        "distance": { "type": "number", "description": "Cosine distance to the query (semantic search only)" },
+                        "urgent_state": { "type": "string", "enum": ["new", "acknowledged", "resolved", "false_positive"] },
+                        "urgent_state_by": { "type": "string" },
+                        "urgent_state_at": { "type": "integer" },
+                    }},
+                ]},
+                "Tag": { "type": "object", "properties": {
+                    "name": { "type": "string" },
+                    "msg_id": { "type": "integer", "description": "Set for tags on a single message" },
+                    "source": { "type": "string", "enum": ["ai", "manual"] },
+                    "confidence": { "type": "number", "nullable": true },
+                }},
+                "Member": { "type": "object", "properties": {
+                    "user_id": { "type": "integer" },
+                    "name": { "type": "string" },
+                    "username": { "type": "string", "nullable": true },
+                    "bio": { "type": "string", "nullable": true },
+                }},
+                "Run": { "type": "object", "properties": {
+                    "id": { "type": "integer" },
+                    "started_at": { "type": "integer" },
+                    "finished_at": { "type": "integer", "nullable": true },
+                    "chats": { "type": "integer" },
+                    "messages_stored": { "type": "integer" },
+                    "chats_analyzed": { "type": "integer" },
+                    "status": { "type": "string", "enum": ["running", "completed", "interrupted", "failed"] },
+                }},
+            },
+        },
+    })
+}
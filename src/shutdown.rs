@@ -0,0 +1,65 @@
+use log::{info, warn};
+use tokio::sync::watch;
+
+/// Handle used to observe a shutdown request (SIGINT / SIGTERM).
+/// Cloned into every place that needs to stop taking on new work.
+#[derive(Clone)]
+pub struct Shutdown {
+    rx: watch::Receiver<bool>,
+}
+
+impl Shutdown {
+    /// True once a shutdown signal has been received.
+    pub fn is_requested(&self) -> bool {
+        *self.rx.borrow()
+    }
+
+    /// Wait until a shutdown signal has been received.
+    pub async fn wait(&mut self) {
+        while !*self.rx.borrow() {
+            if self.rx.changed().await.is_err() {
+                // Sender gone: nothing will ever request shutdown
+                std::future::pending::<()>().await;
+            }
+        }
+    }
+}
+
+/// Install SIGINT/SIGTERM handlers and return a handle to observe them.
+/// The first signal asks the application to finish in-flight work and stop;
+/// a second signal exits immediately.
+pub fn install() -> Shutdown {
+    let (tx, rx) = watch::channel(false);
+    tokio::spawn(async move {
+        wait_for_signal().await;
+        info!("Shutdown requested; finishing in-flight work (press Ctrl+C again to force exit).");
+        let _ = tx.send(true);
+        wait_for_signal().await;
+        warn!("Second shutdown signal received; exiting immediately.");
+        std::process::exit(130);
+    });
+    Shutdown { rx }
+}
+
+/// Resolve on the next SIGINT (Ctrl+C) or, on Unix, SIGTERM.
+async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut term) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = term.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
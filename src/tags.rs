@@ -0,0 +1,53 @@
+use log::info;
+use libsql::Connection;
+use crate::database;
+use crate::database::TagSource;
+
+/// Print every tag in use with the number of chats and messages carrying it.
+pub async fn print_counts(conn: &Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let counts = database::get_tag_counts(conn).await?;
+    if counts.is_empty() {
+        println!("No tags yet.");
+        return Ok(());
+    }
+    println!("{:>6} {:>9}  Tag", "Chats", "Messages");
+    for tag in &counts {
+        println!("{:>6} {:>9}  {}", tag.chats, tag.messages, tag.name);
+    }
+    Ok(())
+}
+
+/// Print a chat's tags and the tags on its messages, with source and confidence.
+pub async fn show(conn: &Connection, chat_peer: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let tags = database::get_tags_for_chat(conn, chat_peer).await?;
+    if tags.is_empty() {
+        println!("No tags on {}.", chat_peer);
+        return Ok(());
+    }
+    for tag in &tags {
+        let target = tag.msg_id.map(|id| format!("#{}", id)).unwrap_or_else(|| "chat".to_string());
+        let confidence = tag.confidence.map(|c| format!(" {:.2}", c)).unwrap_or_default();
+        println!("{:<8} {} ({}{})", target, tag.name, tag.source, confidence);
+    }
+    Ok(())
+}
+
+/// Tag a chat, or one of its messages, by hand.
+pub async fn add(conn: &mut Connection, chat_peer: &str, msg_id: Option<i32>, tag: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let tag = tag.trim();
+    if tag.is_empty() {
+        return Err("Tag must not be empty".into());
+    }
+    database::set_tag(conn, chat_peer, msg_id, tag, TagSource::Manual).await?;
+    info!("Tagged {}{} with \"{}\"", chat_peer, msg_id.map(|id| format!(" #{}", id)).unwrap_or_default(), tag);
+    Ok(())
+}
+
+/// Remove a tag from a chat or message. The analyzer won't propose it there again.
+pub async fn remove(conn: &mut Connection, chat_peer: &str, msg_id: Option<i32>, tag: &str) -> Result<(), Box<dyn std::error::Error>> {
+    if !database::set_tag(conn, chat_peer, msg_id, tag.trim(), TagSource::Removed).await? {
+        return Err(format!("{}{} has no tag \"{}\"", chat_peer, msg_id.map(|id| format!(" #{}", id)).unwrap_or_default(), tag).into());
+    }
+    info!("Removed tag \"{}\" from {}{}", tag, chat_peer, msg_id.map(|id| format!(" #{}", id)).unwrap_or_default());
+    Ok(())
+}
@@ -0,0 +1,272 @@
+use std::collections::BTreeMap;
+use log::info;
+use libsql::Connection;
+use serde::Deserialize;
+use crate::database;
+
+/// Default location of the taxonomy file (overridable via TAXONOMY_FILE).
+const DEFAULT_TAXONOMY_FILE: &str = "taxonomy.json";
+/// Catch-all category for chats that fit nothing in the taxonomy.
+pub const OTHER: &str = "Other";
+/// Separator between levels of a category path, e.g. `Finance/Crypto`.
+const PATH_SEPARATOR: char = '/';
+/// Shortest label that may match a category by containment rather than exactly.
+const MIN_FUZZY_LEN: usize = 4;
+
+/// One node of the taxonomy, as written in the taxonomy file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Category {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Other labels that mean this category (e.g. `cryptocurrency` for `Crypto`).
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub children: Vec<Category>,
+}
+
+/// A category flattened to its full path.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub path: String,
+    pub description: String,
+    pub depth: usize,
+    aliases: Vec<String>,
+}
+
+/// The controlled list of categories chats are sorted into.
+/// Loaded from a JSON file (TAXONOMY_FILE, default `taxonomy.json`) holding a list of
+/// categories with `name`, `description`, optional `aliases` and nested `children`;
+/// without the file a built-in taxonomy is used. `Other` is always available.
+pub struct Taxonomy {
+    entries: Vec<Entry>,
+}
+
+impl Taxonomy {
+    /// Load the taxonomy file, or the built-in taxonomy if there is none.
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let path = std::env::var("TAXONOMY_FILE").unwrap_or_else(|_| DEFAULT_TAXONOMY_FILE.to_string());
+        let roots: Vec<Category> = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("Invalid taxonomy file {}: {}", path, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => builtin(),
+            Err(e) => return Err(format!("Failed to read taxonomy file {}: {}", path, e).into()),
+        };
+        Ok(Self::new(&roots))
+    }
+
+    /// Build a taxonomy from its top-level categories.
+    pub fn new(roots: &[Category]) -> Self {
+        let mut entries = Vec::new();
+        flatten(roots, "", 0, &mut entries);
+        if !entries.iter().any(|e| e.path.eq_ignore_ascii_case(OTHER)) {
+            entries.push(Entry {
+                path: OTHER.to_string(),
+                description: "Anything that fits none of the categories above".to_string(),
+                depth: 0,
+                aliases: Vec::new(),
+            });
+        }
+        Taxonomy { entries }
+    }
+
+    /// All categories in file order, parents before their children.
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    /// True if `category` is exactly one of the taxonomy's paths.
+    pub fn contains(&self, category: &str) -> bool {
+        self.entries.iter().any(|e| e.path == category)
+    }
+
+    /// Lines describing the taxonomy for the analysis prompt.
+    pub fn prompt_section(&self) -> String {
+        let mut text = String::new();
+        for entry in &self.entries {
+            let indent = "  ".repeat(entry.depth);
+            if entry.description.is_empty() {
+                text += &format!("{}- {}\n", indent, entry.path);
+            } else {
+                text += &format!("{}- {}: {}\n", indent, entry.path, entry.description);
+            }
+        }
+        text
+    }
+
+    /// Find the category a label names: its full path, its last level or one of its aliases,
+    /// ignoring case and punctuation. Returns None if nothing matches exactly.
+    pub fn find(&self, label: &str) -> Option<&str> {
+        let key = normalize(label);
+        if key.is_empty() {
+            return None;
+        }
+        let leaf = label.rsplit(PATH_SEPARATOR).next().map(normalize).unwrap_or_default();
+        self.entries.iter()
+            .find(|e| normalize(&e.path) == key)
+            .or_else(|| self.entries.iter().find(|e| e.names().any(|n| n == key)))
+            .or_else(|| self.entries.iter().find(|e| e.names().any(|n| n == leaf)))
+            .map(|e| e.path.as_str())
+    }
+
+    /// Map a free-form label onto the taxonomy: an exact match if there is one, otherwise the
+    /// most specific category whose name or alias appears in the label (or vice versa),
+    /// otherwise `Other`.
+    pub fn resolve(&self, label: &str) -> String {
+        if let Some(path) = self.find(label) {
+            return path.to_string();
+        }
+        let key = normalize(label);
+        let mut best: Option<(&Entry, usize)> = None;
+        for entry in &self.entries {
+            for name in entry.names() {
+                let matched = if key.contains(&name) {
+                    name.len()
+                } else if name.contains(&key) {
+                    key.len()
+                } else {
+                    continue;
+                };
+                if matched < MIN_FUZZY_LEN {
+                    continue;
+                }
+                // Prefer the longest match, then the deeper (more specific) category
+                if best.map_or(true, |(b, len)| matched > len || (matched == len && entry.depth > b.depth)) {
+                    best = Some((entry, matched));
+                }
+            }
+        }
+        best.map(|(e, _)| e.path.clone()).unwrap_or_else(|| OTHER.to_string())
+    }
+}
+
+impl Entry {
+    /// Normalized names this category answers to: its last level and its aliases.
+    fn names(&self) -> impl Iterator<Item = String> + '_ {
+        let leaf = self.path.rsplit(PATH_SEPARATOR).next().unwrap_or(&self.path);
+        std::iter::once(normalize(leaf)).chain(self.aliases.iter().map(|a| normalize(a)))
+    }
+}
+
+/// Helper: flatten nested categories into entries with full paths.
+fn flatten(categories: &[Category], prefix: &str, depth: usize, entries: &mut Vec<Entry>) {
+    for category in categories {
+        let path = if prefix.is_empty() {
+            category.name.trim().to_string()
+        } else {
+            format!("{}{}{}", prefix, PATH_SEPARATOR, category.name.trim())
+        };
+        entries.push(Entry {
+            path: path.clone(),
+            description: category.description.clone(),
+            depth,
+            aliases: category.aliases.clone(),
+        });
+        flatten(&category.children, &path, depth + 1, entries);
+    }
+}
+
+/// Helper: lowercase a label and drop everything but letters and digits.
+fn normalize(label: &str) -> String {
+    label.chars().filter(|c| c.is_alphanumeric()).flat_map(|c| c.to_lowercase()).collect()
+}
+
+/// Helper: shorthand for a built-in category.
+fn category(name: &str, description: &str, aliases: &[&str], children: Vec<Category>) -> Category {
+    Category {
+        name: name.to_string(),
+        description: description.to_string(),
+        aliases: aliases.iter().map(|a| a.to_string()).collect(),
+        children,
+    }
+}
+
+/// The taxonomy used when no taxonomy file exists.
+fn builtin() -> Vec<Category> {
+    vec![
+        category("Work", "Jobs, colleagues and professional contacts", &["professional", "business"], vec![
+            category("Projects", "Coordination of a specific project or product", &["project"], vec![]),
+            category("Clients", "Conversations with customers or partners", &["customers", "partners"], vec![]),
+            category("Jobs", "Job offers, recruiting and careers", &["hiring", "recruiting", "careers"], vec![]),
+        ]),
+        category("Technology", "Software, hardware and IT", &["tech", "it"], vec![
+            category("Programming", "Programming languages, libraries and development", &["development", "coding", "software"], vec![]),
+            category("AI", "Machine learning and AI tools", &["machine learning", "artificial intelligence", "llm"], vec![]),
+        ]),
+        category("Finance", "Money, markets and payments", &["money"], vec![
+            category("Crypto", "Cryptocurrencies, tokens and trading them", &["cryptocurrency", "bitcoin", "blockchain", "web3"], vec![]),
+            category("Investing", "Stocks, funds and personal investing", &["investment", "stocks", "shares"], vec![]),
+        ]),
+        category("News", "News channels and current events", &["media", "politics"], vec![]),
+        category("Community", "Interest groups, local groups and events", &["group"], vec![
+            category("Hobbies", "Games, sports, music and other pastimes", &["hobby", "gaming", "sports", "music"], vec![]),
+            category("Local", "Neighbourhood, city and housing groups", &["neighbourhood", "neighborhood", "city"], vec![]),
+            category("Education", "Courses, study groups and schools", &["study", "learning", "university", "school"], vec![]),
+        ]),
+        category("Personal", "Family, friends and private conversations", &["private"], vec![
+            category("Family", "Family members and relatives", &[], vec![]),
+            category("Friends", "Friends and private social chats", &["social"], vec![]),
+        ]),
+        category("Shopping", "Buying, selling, deals and marketplaces", &["marketplace", "deals", "sales"], vec![]),
+        category("Bots & Services", "Bots, notifications and service accounts", &["bot", "bots", "service", "notifications"], vec![]),
+    ]
+}
+
+/// Print the taxonomy with the number of chats in each category. Parents show their
+/// own chats plus their children's; categories outside the taxonomy are listed separately.
+pub async fn print_counts(conn: &Connection, taxonomy: &Taxonomy) -> Result<(), Box<dyn std::error::Error>> {
+    let counts: BTreeMap<Option<String>, i64> = database::get_category_counts(conn).await?.into_iter().collect();
+    for entry in taxonomy.entries() {
+        let prefix = format!("{}{}", entry.path, PATH_SEPARATOR);
+        let own = counts.get(&Some(entry.path.clone())).copied().unwrap_or(0);
+        let total: i64 = counts.iter()
+            .filter(|(c, _)| c.as_deref().map_or(false, |c| c == entry.path || c.starts_with(&prefix)))
+            .map(|(_, n)| n)
+            .sum();
+        let name = entry.path.rsplit(PATH_SEPARATOR).next().unwrap_or(&entry.path);
+        if total == own {
+            println!("{:>5}  {}{}", total, "  ".repeat(entry.depth), name);
+        } else {
+            println!("{:>5}  {}{} ({} directly)", total, "  ".repeat(entry.depth), name, own);
+        }
+    }
+    if let Some(n) = counts.get(&None) {
+        println!("{:>5}  (not analyzed yet)", n);
+    }
+    let unmapped: Vec<_> = counts.iter()
+        .filter_map(|(c, n)| c.as_deref().filter(|c| !taxonomy.contains(c)).map(|c| (c, n)))
+        .collect();
+    if !unmapped.is_empty() {
+        println!("\nOutside the taxonomy (run `categories remap` to move them):");
+        for (category, n) in unmapped {
+            println!("{:>5}  {} -> {}", n, category, taxonomy.resolve(category));
+        }
+    }
+    Ok(())
+}
+
+/// Move chats whose category (or category override) is not in the taxonomy onto the
+/// closest taxonomy category. With `dry_run`, only print what would change.
+pub async fn remap(conn: &mut Connection, taxonomy: &Taxonomy, dry_run: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let mut changes = 0;
+    for (category, chats) in database::get_category_counts(conn).await? {
+        let Some(category) = category.filter(|c| !taxonomy.contains(c)) else {
+            continue;
+        };
+        let target = taxonomy.resolve(&category);
+        println!("{} -> {} ({} chat(s))", category, target, chats);
+        if !dry_run {
+            database::rename_category(conn, &category, &target).await?;
+        }
+        changes += 1;
+    }
+    if changes == 0 {
+        println!("All categories are already in the taxonomy.");
+    } else if dry_run {
+        println!("Dry run: {} categor{} would be remapped.", changes, if changes == 1 { "y" } else { "ies" });
+    } else {
+        info!("Remapped {} free-form categor{} onto the taxonomy", changes, if changes == 1 { "y" } else { "ies" });
+    }
+    Ok(())
+}
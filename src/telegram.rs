@@ -1,4 +1,4 @@
-use grammers_client::{Client, Config, SignInError};
+use grammers_client::{Client, Config, InvocationError, SignInError};
 use grammers_client::types::Chat;  // Chat enum (Private, Group, Channel, etc.)
 use grammers_client::grammers_tl_types as tl;  // Telegram TL types (for InputPeer and requests)
 use std::io::{self, Write, BufRead};
@@ -6,11 +6,16 @@ use tokio;
 
 /// Holds minimal info about a chat for our monitoring purposes.
 pub struct ChatInfo {
-    pub peer_id: String,       // Unique identifier string (e.g. "group:123456")
+    pub peer_id: String,       // Unique identifier string (e.g. "group:123456"; supergroups are "channel:<id>")
     pub title: String,         // Chat title
     pub kind: ChatKind,
     pub tg_id: i64,            // Telegram's numeric ID for the chat
     pub access_hash: Option<i64>,  // Access hash for channels/private chats (None for basic groups)
+    pub can_change_info: bool,     // We created the chat or are an admin allowed to change its info
+    pub last_message_at: Option<i32>,  // UNIX timestamp of the dialog's top message, if known
+    pub archived: bool,            // In the Archive folder
+    pub muted: bool,               // Notifications are muted
+    pub member_count: Option<i32>, // Participants according to Telegram, if it says
 }
 
 /// Enum to distinguish chat type.
@@ -22,6 +27,32 @@ pub struct MessageInfo {
     pub msg_id: i32,       // Message ID within the chat
     pub date: i32,         // UNIX timestamp of the message (UTC)
     pub text: String,
+    pub sender_id: Option<i64>,  // Telegram user id of the author (None for channel posts)
+    pub outgoing: bool,          // Sent by the account owner
+    pub reply_to: Option<i32>,   // ID of the message this one replies to
+    pub mentions_me: bool,       // Mentions the account owner or replies to one of their messages
+}
+
+/// A chat member as listed with the chat's participants.
+pub struct MemberInfo {
+    pub user_id: i64,
+    pub access_hash: Option<i64>,  // Needed to look up the full profile (None for "min" users)
+    pub name: String,
+    pub username: Option<String>,
+    pub bot: bool,
+    pub verified: bool,
+    pub premium: bool,
+}
+
+/// A user's full profile, as returned by `users.getFullUser`.
+pub struct UserProfile {
+    pub name: String,
+    pub username: Option<String>,
+    pub bio: Option<String>,
+    pub bot: bool,
+    pub verified: bool,
+    pub premium: bool,
+    pub common_chats_count: i32,   // Chats we share with the user
 }
 
 /// Connect to Telegram and ensure authorization. Saves session to `session_file`.
@@ -68,6 +99,12 @@ pub async fn connect(api_id: u32, api_hash: &str, session_file: &str)
     Ok(client)
 }
 
+/// Save the client's session (auth key and update state) to `session_file`.
+pub fn save_session(client: &Client, session_file: &str) -> Result<(), Box<dyn std::error::Error>> {
+    client.session().save_to_file(session_file)?;
+    Ok(())
+}
+
 /// Prompt user for input on the console.
 fn prompt(message: &str) -> Result<String, Box<dyn std::error::Error>> {
     let mut stdout = io::stdout();
@@ -84,22 +121,49 @@ pub async fn fetch_dialogs(client: &Client) -> Result<Vec<ChatInfo>, Box<dyn std
     let mut dialog_iter = client.iter_dialogs();
     let mut chats = Vec::new();
     while let Some(dialog) = dialog_iter.next().await? {
+        let last_message_at = dialog.last_message.as_ref().map(|m| m.date().timestamp() as i32);
+        let (archived, muted) = dialog_state(&dialog.raw);
         let chat = dialog.chat();  // grammers_client::types::Chat
         // Filter only group chats and channels (skip private chats)
         let info = match chat {
             Chat::User(_) => {
                 continue; // Skip direct user conversations
             }
-            Chat::Group(group) => {
+            Chat::Group(group) => match &group.raw {
+                // Supergroups (megagroups) are channels to the API: they need the channel id
+                // and access hash for InputPeerChannel and the channels.* methods
+                tl::enums::Chat::Channel(c) => ChatInfo {
+                    peer_id: format!("channel:{}", c.id),
+                    title: group.title().to_string(),
+                    kind: ChatKind::Channel,
+                    tg_id: c.id,
+                    access_hash: c.access_hash,
+                    can_change_info: can_change_info(c.creator, &c.admin_rights),
+                    last_message_at,
+                    archived,
+                    muted,
+                    member_count: c.participants_count,
+                },
                 // Basic group chats (legacy groups)
-                ChatInfo {
+                raw => ChatInfo {
                     peer_id: format!("group:{}", group.id()),
                     title: group.title().to_string(),
                     kind: ChatKind::Group,
                     tg_id: group.id() as i64,
                     access_hash: None,  // not needed for InputPeerChat
-                }
-            }
+                    can_change_info: match raw {
+                        tl::enums::Chat::Chat(c) => can_change_info(c.creator, &c.admin_rights),
+                        _ => false,
+                    },
+                    last_message_at,
+                    archived,
+                    muted,
+                    member_count: match raw {
+                        tl::enums::Chat::Chat(c) => Some(c.participants_count),
+                        _ => None,
+                    },
+                },
+            },
             Chat::Channel(channel) => {
                 // Channels or supergroups
                 ChatInfo {
@@ -108,6 +172,11 @@ pub async fn fetch_dialogs(client: &Client) -> Result<Vec<ChatInfo>, Box<dyn std
                     kind: ChatKind::Channel,
                     tg_id: channel.id() as i64,
                     access_hash: channel.raw.access_hash,  // Already an Option<i64>
+                    can_change_info: can_change_info(channel.raw.creator, &channel.raw.admin_rights),
+                    last_message_at,
+                    archived,
+                    muted,
+                    member_count: channel.raw.participants_count,
                 }
             }
         };
@@ -116,29 +185,55 @@ pub async fn fetch_dialogs(client: &Client) -> Result<Vec<ChatInfo>, Box<dyn std
     Ok(chats)
 }
 
-/// Fetch new messages for a given chat since the last seen message ID. 
-/// Returns a list of new MessageInfo (empty if no new messages).
-pub async fn fetch_new_messages(client: &Client, chat: &ChatInfo, last_seen_id: Option<i32>) 
-    -> Result<Vec<MessageInfo>, Box<dyn std::error::Error>> 
-{
-    let mut new_messages = Vec::new();
-    // Determine InputPeer for the chat based on type
-    let peer = match chat.kind {
+/// Telegram's id of the Archive folder.
+const ARCHIVE_FOLDER_ID: i32 = 1;
+
+/// Helper: whether a dialog is archived and whether its notifications are muted right now.
+fn dialog_state(dialog: &tl::enums::Dialog) -> (bool, bool) {
+    match dialog {
+        tl::enums::Dialog::Dialog(d) => {
+            let tl::enums::PeerNotifySettings::Settings(settings) = &d.notify_settings;
+            let now = chrono::Utc::now().timestamp();
+            let muted = settings.mute_until.map_or(false, |until| until as i64 > now);
+            (d.folder_id == Some(ARCHIVE_FOLDER_ID), muted)
+        }
+        tl::enums::Dialog::Folder(_) => (false, false),
+    }
+}
+
+/// Helper: true for the creator or an admin with the right to change chat info.
+fn can_change_info(creator: bool, admin_rights: &Option<tl::enums::ChatAdminRights>) -> bool {
+    creator || matches!(admin_rights, Some(tl::enums::ChatAdminRights::Rights(rights)) if rights.change_info)
+}
+
+/// Build the InputPeer for a chat based on its type.
+pub fn input_peer(chat: &ChatInfo) -> tl::enums::InputPeer {
+    match chat.kind {
         ChatKind::Group => {
-            let chat_id = chat.tg_id as i32;
+            let chat_id = chat.tg_id;
             tl::enums::InputPeer::Chat(tl::types::InputPeerChat { 
                 chat_id: chat_id.into() 
             })
         }
         ChatKind::Channel => {
-            let channel_id = chat.tg_id as i32;
+            let channel_id = chat.tg_id;
             let access_hash = chat.access_hash.unwrap_or(0);
             tl::enums::InputPeer::Channel(tl::types::InputPeerChannel { 
                 channel_id: channel_id.into(), 
                 access_hash 
             })
         }
-    };
+    }
+}
+
+/// Fetch new messages for a given chat since the last seen message ID. 
+/// Returns a list of new MessageInfo (empty if no new messages).
+pub async fn fetch_new_messages(client: &Client, chat: &ChatInfo, last_seen_id: Option<i32>) 
+    -> Result<Vec<MessageInfo>, Box<dyn std::error::Error>> 
+{
+    let mut new_messages = Vec::new();
+    // Determine InputPeer for the chat based on type
+    let peer = input_peer(chat);
 
     // Use last_seen_id or 0 if none (0 will fetch latest messages).
     let last_id = last_seen_id.unwrap_or(0);
@@ -192,6 +287,10 @@ pub async fn fetch_new_messages(client: &Client, chat: &ChatInfo, last_seen_id:
                     msg_id: m.id,
                     date: m.date, 
                     text,
+                    sender_id: sender_id(m),
+                    outgoing: m.out,
+                    reply_to: reply_to_id(m),
+                    mentions_me: m.mentioned,
                 });
             }
         }
@@ -213,14 +312,14 @@ pub async fn fetch_new_messages(client: &Client, chat: &ChatInfo, last_seen_id:
 
 /// Fetch members of a chat and return their information.
 pub async fn fetch_chat_members(client: &Client, chat: &ChatInfo) 
-    -> Result<Vec<(i64, String, Option<String>, Option<String>)>, Box<dyn std::error::Error>> 
+    -> Result<Vec<MemberInfo>, Box<dyn std::error::Error>> 
 {
     let mut members = Vec::new();
     
     // Different API calls needed for different chat types
     match chat.kind {
         ChatKind::Group => {
-            let chat_id = chat.tg_id as i32;
+            let chat_id = chat.tg_id;
             let req = tl::functions::messages::GetFullChat {
                 chat_id: chat_id.into(),
             };
@@ -243,15 +342,7 @@ pub async fn fetch_chat_members(client: &Client, chat: &ChatInfo)
                                     }
                                 }) {
                                     if let tl::enums::User::User(user) = user {
-                                        // Add delay between user info requests to avoid rate limits
-                                        tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-                                        
-                                        // Get basic user info from the user object
-                                        let name = format!("{} {}", 
-                                            user.first_name.as_deref().unwrap_or(""),
-                                            user.last_name.as_deref().unwrap_or("")).trim().to_string();
-                                        
-                                        members.push((user.id as i64, name, user.username.clone(), None));
+                                        members.push(member_info(user));
                                     }
                                 }
                             }
@@ -261,7 +352,7 @@ pub async fn fetch_chat_members(client: &Client, chat: &ChatInfo)
             }
         }
         ChatKind::Channel => {
-            let channel_id = chat.tg_id as i32;
+            let channel_id = chat.tg_id;
             let access_hash = chat.access_hash.unwrap_or(0);
             let req = tl::functions::channels::GetFullChannel {
                 channel: tl::types::InputChannel {
@@ -290,15 +381,7 @@ pub async fn fetch_chat_members(client: &Client, chat: &ChatInfo)
                     if let tl::enums::channels::ChannelParticipants::Participants(data) = participants {
                         for user in data.users {
                             if let tl::enums::User::User(user) = user {
-                                // Add delay between user info requests
-                                tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-                                
-                                // Get basic user info from the user object
-                                let name = format!("{} {}", 
-                                    user.first_name.as_deref().unwrap_or(""),
-                                    user.last_name.as_deref().unwrap_or("")).trim().to_string();
-                                
-                                members.push((user.id as i64, name, user.username.clone(), None));
+                                members.push(member_info(&user));
                             }
                         }
                     }
@@ -309,51 +392,323 @@ pub async fn fetch_chat_members(client: &Client, chat: &ChatInfo)
     Ok(members)
 }
 
-/// Helper function to get user information with proper error handling
-async fn get_user_info(client: &Client, user_id: i32, access_hash: i64) -> Result<(String, Option<String>, Option<String>), Box<dyn std::error::Error>> {
+/// Helper: a user's display name from their first and last name.
+fn user_name(user: &tl::types::User) -> String {
+    format!("{} {}",
+        user.first_name.as_deref().unwrap_or(""),
+        user.last_name.as_deref().unwrap_or("")).trim().to_string()
+}
+
+/// Helper: the member details carried by a user object.
+fn member_info(user: &tl::types::User) -> MemberInfo {
+    MemberInfo {
+        user_id: user.id,
+        // A min user's access hash is only valid in the context it was seen in
+        access_hash: if user.min { None } else { user.access_hash },
+        name: user_name(user),
+        username: user.username.clone(),
+        bot: user.bot,
+        verified: user.verified,
+        premium: user.premium,
+    }
+}
+
+/// Fetch a user's full profile (bio, flags and the number of chats we share).
+/// FLOOD_WAIT errors are honoured by sleeping and retrying.
+pub async fn get_user_info(client: &Client, user_id: i64, access_hash: i64) -> Result<UserProfile, Box<dyn std::error::Error>> {
     let req = tl::functions::users::GetFullUser {
         id: tl::enums::InputUser::User(tl::types::InputUser {
-            user_id: user_id.into(),
+            user_id,
             access_hash,
         }),
     };
-    
-    // Try up to 3 times with exponential backoff
-    let mut retry_delay = 2;
-    for _ in 0..3 {
+    let tl::enums::users::UserFull::Full(full) = invoke_with_flood_wait(client, &req, "fetching a user profile").await?;
+    let tl::enums::UserFull::Full(full_user) = full.full_user;
+    let user = full.users.iter().find_map(|u| match u {
+        tl::enums::User::User(u) if u.id == user_id => Some(u),
+        _ => None,
+    }).ok_or_else(|| format!("Telegram returned no user {}", user_id))?;
+    Ok(UserProfile {
+        name: user_name(user),
+        username: user.username.clone(),
+        bio: full_user.about,
+        bot: user.bot,
+        verified: user.verified,
+        premium: user.premium,
+        common_chats_count: full_user.common_chats_count,
+    })
+}
+
+/// Maximum number of FLOOD_WAIT errors tolerated for a single history page.
+const MAX_FLOOD_RETRIES: u32 = 5;
+
+/// One page of chat history, newest first.
+pub struct HistoryPage {
+    pub messages: Vec<MessageInfo>,  // Text messages in the page
+    pub oldest_id: Option<i32>,      // Oldest id in the raw page, service messages included (None at the start of history)
+}
+
+/// Fetch one page of history older than `offset_id` (0 = start from the newest message).
+/// Telegram FLOOD_WAIT errors are honoured by sleeping for the requested
+/// number of seconds and retrying.
+pub async fn fetch_history_page(client: &Client, chat: &ChatInfo, offset_id: i32, limit: i32)
+    -> Result<HistoryPage, Box<dyn std::error::Error>>
+{
+    let req = tl::functions::messages::GetHistory {
+        peer: input_peer(chat),
+        offset_id,
+        offset_date: 0,
+        add_offset: 0,
+        limit,
+        max_id: 0,
+        min_id: 0,
+        hash: 0,
+    };
+    let mut flood_retries = 0;
+    let history = loop {
         match client.invoke(&req).await {
-            Ok(full_user) => {
-                let full = match full_user {
-                    tl::enums::users::UserFull::Full(f) => f,
-                };
-                
-                if let Some(user) = full.users.first() {
-                    if let tl::enums::User::User(user) = user {
-                        let name = format!("{} {}", 
-                            user.first_name.as_deref().unwrap_or(""),
-                            user.last_name.as_deref().unwrap_or("")).trim().to_string();
-                        
-                        // Get bio from full user info
-                        let full_user = match full.full_user {
-                            tl::enums::UserFull::Full(f) => f,
-                        };
-                        
-                        return Ok((name, user.username.clone(), full_user.about));
-                    }
-                }
-                return Ok((format!("User {}", user_id), None, None));
+            Ok(history) => break history,
+            Err(InvocationError::Rpc(rpc)) if rpc.name.starts_with("FLOOD_WAIT") && flood_retries < MAX_FLOOD_RETRIES => {
+                let wait = rpc.value.unwrap_or(5) as u64;
+                eprintln!("Flood wait while fetching history for {}: sleeping {}s", chat.title, wait);
+                tokio::time::sleep(tokio::time::Duration::from_secs(wait)).await;
+                flood_retries += 1;
             }
-            Err(e) => {
-                let error_str = e.to_string().to_lowercase();
-                if error_str.contains("flood") {
-                    // If we hit flood wait, sleep and retry with exponential backoff
-                    tokio::time::sleep(tokio::time::Duration::from_secs(retry_delay)).await;
-                    retry_delay *= 2; // Exponential backoff
-                    continue;
-                }
-                return Err(e.into());
+            Err(e) => return Err(e.into()),
+        }
+    };
+    let messages = match history {
+        tl::enums::messages::Messages::Messages(messages) => messages.messages,
+        tl::enums::messages::Messages::Slice(slice) => slice.messages,
+        tl::enums::messages::Messages::ChannelMessages(channel) => channel.messages,
+        tl::enums::messages::Messages::NotModified(_) => Vec::new(),
+    };
+    let mut page = HistoryPage {
+        messages: Vec::new(),
+        oldest_id: messages.iter().map(raw_message_id).min(),
+    };
+    for msg in &messages {
+        if let tl::enums::Message::Message(m) = msg {
+            page.messages.push(MessageInfo {
+                msg_id: m.id,
+                date: m.date,
+                text: m.message.clone(),
+                sender_id: sender_id(m),
+                outgoing: m.out,
+                reply_to: reply_to_id(m),
+                mentions_me: m.mentioned,
+            });
+        }
+    }
+    Ok(page)
+}
+
+/// Helper: the user who sent a message, if it was sent by a user.
+fn sender_id(msg: &tl::types::Message) -> Option<i64> {
+    match &msg.from_id {
+        Some(tl::enums::Peer::User(user)) => Some(user.user_id),
+        _ => None,
+    }
+}
+
+/// Helper: the message a message replies to, if any.
+fn reply_to_id(msg: &tl::types::Message) -> Option<i32> {
+    match &msg.reply_to {
+        Some(tl::enums::MessageReplyHeader::Header(header)) => header.reply_to_msg_id,
+        _ => None,
+    }
+}
+
+/// Get the logged-in account's user id and username.
+pub async fn fetch_me(client: &Client) -> Result<(i64, Option<String>), Box<dyn std::error::Error>> {
+    let me = client.get_me().await?;
+    Ok((me.id(), me.username().map(String::from)))
+}
+
+/// Helper: the id of any kind of TL message.
+fn raw_message_id(msg: &tl::enums::Message) -> i32 {
+    match msg {
+        tl::enums::Message::Empty(m) => m.id,
+        tl::enums::Message::Message(m) => m.id,
+        tl::enums::Message::Service(m) => m.id,
+    }
+}
+
+/// Maximum length of a single Telegram text message.
+const MAX_MESSAGE_LEN: usize = 4096;
+
+/// Send a Markdown-formatted text to the account's own Saved Messages (`InputPeerSelf`).
+/// Markdown is converted to Telegram message entities; texts longer than Telegram's
+/// limit are split on line boundaries into several messages.
+pub async fn send_to_saved_messages(client: &Client, markdown: &str) -> Result<(), Box<dyn std::error::Error>> {
+    for chunk in split_message(markdown, MAX_MESSAGE_LEN) {
+        let (text, entities) = grammers_client::parsers::parse_markdown_message(&chunk);
+        let req = tl::functions::messages::SendMessage {
+            no_webpage: true,
+            silent: false,
+            background: false,
+            clear_draft: false,
+            noforwards: false,
+            update_stickersets_order: false,
+            invert_media: false,
+            peer: tl::enums::InputPeer::PeerSelf,
+            reply_to: None,
+            message: text,
+            random_id: random_id(),
+            reply_markup: None,
+            entities: if entities.is_empty() { None } else { Some(entities) },
+            schedule_date: None,
+            send_as: None,
+            quick_reply_shortcut: None,
+            effect: None,
+        };
+        client.invoke(&req).await?;
+    }
+    Ok(())
+}
+
+/// Helper: split text into chunks of at most `max_len` bytes, preferring line boundaries.
+fn split_message(text: &str, max_len: usize) -> Vec<String> {
+    let mut chunks = Vec::new();
+    let mut current = String::new();
+    for line in text.split_inclusive('\n') {
+        if current.len() + line.len() > max_len && !current.is_empty() {
+            chunks.push(std::mem::take(&mut current));
+        }
+        let mut line = line;
+        // A single line longer than the limit is cut at character boundaries
+        while line.len() > max_len {
+            let mut cut = max_len;
+            while !line.is_char_boundary(cut) {
+                cut -= 1;
+            }
+            chunks.push(line[..cut].to_string());
+            line = &line[cut..];
+        }
+        current.push_str(line);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Helper: random id required by Telegram to deduplicate sent messages.
+fn random_id() -> i64 {
+    use std::collections::hash_map::RandomState;
+    use std::hash::{BuildHasher, Hasher};
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u128(std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default());
+    hasher.finish() as i64
+}
+
+/// Helper: invoke a request, honouring FLOOD_WAIT errors like `fetch_history_page` does.
+async fn invoke_with_flood_wait<R: tl::RemoteCall>(client: &Client, req: &R, what: &str)
+    -> Result<R::Return, Box<dyn std::error::Error>>
+{
+    let mut flood_retries = 0;
+    loop {
+        match client.invoke(req).await {
+            Ok(result) => return Ok(result),
+            Err(InvocationError::Rpc(rpc)) if rpc.name.starts_with("FLOOD_WAIT") && flood_retries < MAX_FLOOD_RETRIES => {
+                let wait = rpc.value.unwrap_or(5) as u64;
+                eprintln!("Flood wait while {}: sleeping {}s", what, wait);
+                tokio::time::sleep(tokio::time::Duration::from_secs(wait)).await;
+                flood_retries += 1;
             }
+            Err(e) => return Err(e.into()),
         }
     }
-    Ok((format!("User {}", user_id), None, None))
-} 
\ No newline at end of file
+}
+
+/// The account's dialog filters (chat folders), including the default "All chats" entry.
+pub async fn get_dialog_filters(client: &Client) -> Result<Vec<tl::enums::DialogFilter>, Box<dyn std::error::Error>> {
+    let result = invoke_with_flood_wait(client, &tl::functions::messages::GetDialogFilters {}, "reading chat folders").await?;
+    let tl::enums::messages::DialogFilters::Filters(filters) = result;
+    Ok(filters.filters)
+}
+
+/// Create or replace the dialog filter (folder) with `id`; `None` deletes it.
+pub async fn update_dialog_filter(client: &Client, id: i32, filter: Option<tl::enums::DialogFilter>)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let req = tl::functions::messages::UpdateDialogFilter { id, filter };
+    invoke_with_flood_wait(client, &req, "updating a chat folder").await?;
+    Ok(())
+}
+
+/// Change a chat's title (requires the right to change chat info).
+pub async fn rename_chat(client: &Client, chat: &ChatInfo, title: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let what = format!("renaming {}", chat.title);
+    match chat.kind {
+        ChatKind::Group => {
+            let req = tl::functions::messages::EditChatTitle { chat_id: chat.tg_id, title: title.to_string() };
+            invoke_with_flood_wait(client, &req, &what).await?;
+        }
+        ChatKind::Channel => {
+            let req = tl::functions::channels::EditTitle {
+                channel: tl::types::InputChannel {
+                    channel_id: chat.tg_id,
+                    access_hash: chat.access_hash.unwrap_or(0),
+                }.into(),
+                title: title.to_string(),
+            };
+            invoke_with_flood_wait(client, &req, &what).await?;
+        }
+    }
+    Ok(())
+}
+
+/// Move a chat into the Archive folder, or back to the main list.
+pub async fn set_archived(client: &Client, chat: &ChatInfo, archived: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let req = tl::functions::folders::EditPeerFolders {
+        folder_peers: vec![tl::types::InputFolderPeer {
+            peer: input_peer(chat),
+            folder_id: if archived { ARCHIVE_FOLDER_ID } else { 0 },
+        }.into()],
+    };
+    invoke_with_flood_wait(client, &req, &format!("archiving {}", chat.title)).await?;
+    Ok(())
+}
+
+/// Mute a chat's notifications for good, or unmute them.
+pub async fn set_muted(client: &Client, chat: &ChatInfo, muted: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let req = tl::functions::account::UpdateNotifySettings {
+        peer: tl::types::InputNotifyPeer { peer: input_peer(chat) }.into(),
+        settings: tl::types::InputPeerNotifySettings {
+            show_previews: None,
+            silent: None,
+            mute_until: Some(if muted { i32::MAX } else { 0 }),
+            sound: None,
+            stories_muted: None,
+            stories_hide_sender: None,
+            stories_sound: None,
+        }.into(),
+    };
+    invoke_with_flood_wait(client, &req, &format!("muting {}", chat.title)).await?;
+    Ok(())
+}
+
+/// Our peer id ("group:<id>" / "channel:<id>") for an InputPeer, if it is a group or channel.
+pub fn peer_id_of(peer: &tl::enums::InputPeer) -> Option<String> {
+    match peer {
+        tl::enums::InputPeer::Chat(c) => Some(format!("group:{}", c.chat_id)),
+        tl::enums::InputPeer::Channel(c) => Some(format!("channel:{}", c.channel_id)),
+        _ => None,
+    }
+}
+
+/// Serialize a dialog filter (for the undo log).
+pub fn encode_dialog_filter(filter: &tl::enums::DialogFilter) -> Vec<u8> {
+    use grammers_client::grammers_tl_types::Serializable;
+    filter.to_bytes()
+}
+
+/// Restore a dialog filter serialized by `encode_dialog_filter`.
+pub fn decode_dialog_filter(bytes: &[u8]) -> Result<tl::enums::DialogFilter, Box<dyn std::error::Error>> {
+    use grammers_client::grammers_tl_types::Deserializable;
+    tl::enums::DialogFilter::from_bytes(bytes).map_err(|e| format!("Corrupt folder in undo log: {}", e).into())
+}
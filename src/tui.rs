@@ -0,0 +1,476 @@
+use std::time::Duration;
+use libsql::Connection;
+use ratatui::DefaultTerminal;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::Frame;
+use crate::{database, urgent};
+use crate::database::{ChatActivity, ChatFilter, MessageHit, MessageRecord, ReportQuery, Suggestion, UrgentState};
+use crate::report::format_timestamp;
+
+/// Messages loaded into the timeline pane.
+const TIMELINE_LIMIT: i64 = 200;
+/// Urgent messages loaded into the urgent queue.
+const URGENT_LIMIT: i64 = 200;
+/// Results loaded per search.
+const SEARCH_LIMIT: i64 = 100;
+/// How long to wait for a key before redrawing.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Key bindings shown in the footer.
+const HELP: &str = "Tab pane  ↑↓ move  c/C category  Enter open  a/x/f ack/resolve/false-positive  n/N name ok/reject  g/G category ok/reject  / search  r reload  q quit";
+
+/// Pane that receives movement keys.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pane {
+    Chats,
+    Timeline,
+    Urgent,
+    Members,
+}
+
+impl Pane {
+    fn next(self) -> Self {
+        match self {
+            Pane::Chats => Pane::Timeline,
+            Pane::Timeline => Pane::Urgent,
+            Pane::Urgent => Pane::Members,
+            Pane::Members => Pane::Chats,
+        }
+    }
+
+    fn previous(self) -> Self {
+        match self {
+            Pane::Chats => Pane::Members,
+            Pane::Timeline => Pane::Chats,
+            Pane::Urgent => Pane::Timeline,
+            Pane::Members => Pane::Urgent,
+        }
+    }
+}
+
+/// State of the terminal UI.
+struct App {
+    conn: Connection,
+    categories: Vec<String>,  // known categories; the filter cycles through "all" and these
+    category: Option<usize>,  // index into `categories`, None = all
+    chats: Vec<ChatActivity>,
+    chat_state: ListState,
+    timeline: Vec<MessageRecord>,
+    timeline_state: ListState,
+    search: Option<(String, Vec<MessageHit>)>,  // query and results shown instead of the timeline
+    urgent: Vec<MessageHit>,
+    urgent_state: ListState,
+    members: Vec<(i64, String, Option<String>, Option<String>)>,
+    members_state: ListState,
+    focus: Pane,
+    input: Option<String>,  // search query being typed
+    status: String,
+    quit: bool,
+}
+
+/// Run the interactive terminal UI until the user quits.
+pub async fn run(conn: Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let mut terminal = ratatui::init();
+    let result = run_app(&mut terminal, conn).await;
+    // Restore the terminal even if the UI failed
+    ratatui::restore();
+    result
+}
+
+async fn run_app(terminal: &mut DefaultTerminal, conn: Connection) -> Result<(), Box<dyn std::error::Error>> {
+    let mut app = App {
+        conn,
+        categories: Vec::new(),
+        category: None,
+        chats: Vec::new(),
+        chat_state: ListState::default(),
+        timeline: Vec::new(),
+        timeline_state: ListState::default(),
+        search: None,
+        urgent: Vec::new(),
+        urgent_state: ListState::default(),
+        members: Vec::new(),
+        members_state: ListState::default(),
+        focus: Pane::Chats,
+        input: None,
+        status: String::new(),
+        quit: false,
+    };
+    app.reload().await?;
+    while !app.quit {
+        terminal.draw(|frame| draw(frame, &mut app))?;
+        if !event::poll(POLL_INTERVAL)? {
+            continue;
+        }
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press {
+                if let Err(e) = app.handle_key(key).await {
+                    app.status = format!("Error: {}", e);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+impl App {
+    /// Reload categories, chats and the urgent queue, keeping the selected chat if possible.
+    async fn reload(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let selected_peer = self.selected_chat().map(|c| c.chat.peer_id.clone());
+        let all = database::get_report_chats(&self.conn, &ReportQuery::default()).await?;
+        let mut categories: Vec<String> = all.iter().filter_map(|c| c.chat.category.clone()).collect();
+        categories.sort_by_key(|c| c.to_lowercase());
+        categories.dedup_by(|a, b| a.eq_ignore_ascii_case(b));
+        let category_name = self.category.and_then(|i| self.categories.get(i).cloned());
+        self.category = category_name.and_then(|name| categories.iter().position(|c| *c == name));
+        self.categories = categories;
+
+        let query = ReportQuery {
+            filter: ChatFilter { category: self.category.map(|i| self.categories[i].clone()), ..Default::default() },
+            ..Default::default()
+        };
+        self.chats = database::get_report_chats(&self.conn, &query).await?;
+        let index = selected_peer
+            .and_then(|peer| self.chats.iter().position(|c| c.chat.peer_id == peer))
+            .or(if self.chats.is_empty() { None } else { Some(0) });
+        self.chat_state.select(index);
+
+        self.urgent = database::get_urgent_messages(&self.conn, &ChatFilter::default(), &UrgentState::OPEN, URGENT_LIMIT).await?;
+        clamp_selection(&mut self.urgent_state, self.urgent.len());
+        self.load_chat().await
+    }
+
+    /// Load timeline and members of the selected chat.
+    async fn load_chat(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        match self.selected_chat().map(|c| c.chat.peer_id.clone()) {
+            Some(peer) => {
+                self.timeline = database::get_message_page(&self.conn, &peer, None, TIMELINE_LIMIT).await?;
+                self.members = database::get_chat_members(&self.conn, &peer).await?;
+            }
+            None => {
+                self.timeline.clear();
+                self.members.clear();
+            }
+        }
+        self.timeline_state.select(if self.timeline.is_empty() { None } else { Some(0) });
+        self.members_state.select(if self.members.is_empty() { None } else { Some(0) });
+        Ok(())
+    }
+
+    fn selected_chat(&self) -> Option<&ChatActivity> {
+        self.chat_state.selected().and_then(|i| self.chats.get(i))
+    }
+
+    async fn handle_key(&mut self, key: KeyEvent) -> Result<(), Box<dyn std::error::Error>> {
+        if let Some(input) = &mut self.input {
+            match key.code {
+                KeyCode::Enter => {
+                    let query = input.trim().to_string();
+                    self.input = None;
+                    if !query.is_empty() {
+                        let hits = database::search_messages(&self.conn, &query, &ChatFilter::default(), SEARCH_LIMIT).await?;
+                        self.status = format!("{} results for \"{}\" (Esc to close)", hits.len(), query);
+                        self.timeline_state.select(if hits.is_empty() { None } else { Some(0) });
+                        self.search = Some((query, hits));
+                        self.focus = Pane::Timeline;
+                    }
+                }
+                KeyCode::Esc => self.input = None,
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                _ => {}
+            }
+            return Ok(());
+        }
+
+        match key.code {
+            KeyCode::Char('q') => self.quit = true,
+            KeyCode::Esc => {
+                if self.search.take().is_some() {
+                    self.timeline_state.select(if self.timeline.is_empty() { None } else { Some(0) });
+                    self.status.clear();
+                } else {
+                    self.quit = true;
+                }
+            }
+            KeyCode::Tab => self.focus = self.focus.next(),
+            KeyCode::BackTab => self.focus = self.focus.previous(),
+            KeyCode::Down | KeyCode::Char('j') => self.move_selection(1).await?,
+            KeyCode::Up | KeyCode::Char('k') => self.move_selection(-1).await?,
+            KeyCode::Char('c') => self.cycle_category(1).await?,
+            KeyCode::Char('C') => self.cycle_category(-1).await?,
+            KeyCode::Char('/') => self.input = Some(String::new()),
+            KeyCode::Char('r') => {
+                self.reload().await?;
+                self.status = "Reloaded.".into();
+            }
+            KeyCode::Enter => self.open_selected().await?,
+            KeyCode::Char('a') => self.set_urgent_state(UrgentState::Acknowledged).await?,
+            KeyCode::Char('x') => self.set_urgent_state(UrgentState::Resolved).await?,
+            KeyCode::Char('f') => self.set_urgent_state(UrgentState::FalsePositive).await?,
+            KeyCode::Char('n') => self.decide(Suggestion::Name, "accepted").await?,
+            KeyCode::Char('N') => self.decide(Suggestion::Name, "rejected").await?,
+            KeyCode::Char('g') => self.decide(Suggestion::Category, "accepted").await?,
+            KeyCode::Char('G') => self.decide(Suggestion::Category, "rejected").await?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    async fn move_selection(&mut self, delta: isize) -> Result<(), Box<dyn std::error::Error>> {
+        match self.focus {
+            Pane::Chats => {
+                step(&mut self.chat_state, self.chats.len(), delta);
+                self.search = None;
+                self.load_chat().await?;
+            }
+            Pane::Timeline => {
+                let len = self.search.as_ref().map_or(self.timeline.len(), |(_, hits)| hits.len());
+                step(&mut self.timeline_state, len, delta);
+            }
+            Pane::Urgent => step(&mut self.urgent_state, self.urgent.len(), delta),
+            Pane::Members => step(&mut self.members_state, self.members.len(), delta),
+        }
+        Ok(())
+    }
+
+    async fn cycle_category(&mut self, delta: isize) -> Result<(), Box<dyn std::error::Error>> {
+        // Positions: 0 = all, 1..=n = categories
+        let count = self.categories.len() as isize + 1;
+        let current = self.category.map_or(0, |i| i as isize + 1);
+        let next = (current + delta).rem_euclid(count);
+        self.category = if next == 0 { None } else { Some(next as usize - 1) };
+        self.chat_state.select(None);
+        self.reload().await?;
+        self.status = format!("Category: {}", self.category.map_or("all", |i| self.categories[i].as_str()));
+        Ok(())
+    }
+
+    /// Enter: open the chat of the selected urgent message or search result.
+    async fn open_selected(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        let peer = match self.focus {
+            Pane::Urgent => self.urgent_state.selected().and_then(|i| self.urgent.get(i)).map(|h| h.message.chat_peer.clone()),
+            Pane::Timeline => self.search.as_ref()
+                .and_then(|(_, hits)| self.timeline_state.selected().and_then(|i| hits.get(i)))
+                .map(|h| h.message.chat_peer.clone()),
+            _ => None,
+        };
+        let Some(peer) = peer else {
+            return Ok(());
+        };
+        if !self.chats.iter().any(|c| c.chat.peer_id == peer) {
+            // The chat is hidden by the category filter
+            self.category = None;
+            self.reload().await?;
+        }
+        let index = self.chats.iter().position(|c| c.chat.peer_id == peer);
+        self.chat_state.select(index);
+        self.search = None;
+        self.load_chat().await?;
+        self.focus = Pane::Timeline;
+        Ok(())
+    }
+
+    /// Move the selected urgent message to `state`. Resolved messages and false positives
+    /// leave the queue; acknowledged ones stay until resolved.
+    async fn set_urgent_state(&mut self, state: UrgentState) -> Result<(), Box<dyn std::error::Error>> {
+        if self.focus != Pane::Urgent {
+            self.status = "Select an urgent message first (Tab to the urgent pane).".into();
+            return Ok(());
+        }
+        let Some(item) = self.urgent_state.selected().and_then(|i| self.urgent.get(i)) else {
+            return Ok(());
+        };
+        let (peer, msg_id, chat_name) = (item.message.chat_peer.clone(), item.message.msg_id, item.chat_name.clone());
+        database::set_urgent_state(&mut self.conn, &peer, msg_id, state, &urgent::current_user()).await?;
+        self.urgent = database::get_urgent_messages(&self.conn, &ChatFilter::default(), &UrgentState::OPEN, URGENT_LIMIT).await?;
+        clamp_selection(&mut self.urgent_state, self.urgent.len());
+        self.status = format!("Message {} in {} marked {}.", msg_id, chat_name, state.as_str());
+        Ok(())
+    }
+
+    /// Accept or reject the selected chat's suggested name or category.
+    async fn decide(&mut self, suggestion: Suggestion, decision: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(chat) = self.selected_chat() else {
+            return Ok(());
+        };
+        let (peer, name) = (chat.chat.peer_id.clone(), chat.chat.name.clone());
+        let value = match suggestion {
+            Suggestion::Name => chat.chat.suggested_name.clone(),
+            Suggestion::Category => chat.chat.category.clone(),
+        };
+        if value.is_none() {
+            self.status = format!("{} has no suggestion to review yet.", name);
+            return Ok(());
+        }
+        database::set_suggestion_decision(&mut self.conn, &peer, suggestion, Some(decision)).await?;
+        self.reload().await?;
+        let what = if suggestion == Suggestion::Name { "name" } else { "category" };
+        self.status = format!("Suggested {} \"{}\" {} for {}.", what, value.unwrap_or_default(), decision, name);
+        Ok(())
+    }
+}
+
+/// Helper: move a list selection by `delta`, staying within `len` items.
+fn step(state: &mut ListState, len: usize, delta: isize) {
+    if len == 0 {
+        state.select(None);
+        return;
+    }
+    let current = state.selected().unwrap_or(0) as isize;
+    state.select(Some((current + delta).clamp(0, len as isize - 1) as usize));
+}
+
+/// Helper: keep a selection valid after the list changed.
+fn clamp_selection(state: &mut ListState, len: usize) {
+    state.select(match (state.selected(), len) {
+        (_, 0) => None,
+        (Some(i), len) => Some(i.min(len - 1)),
+        (None, _) => Some(0),
+    });
+}
+
+fn draw(frame: &mut Frame, app: &mut App) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(5), Constraint::Length(3)])
+        .split(frame.area());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(30), Constraint::Percentage(45), Constraint::Percentage(25)])
+        .split(rows[0]);
+    let right = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+        .split(columns[2]);
+
+    draw_chats(frame, app, columns[0]);
+    draw_timeline(frame, app, columns[1]);
+    draw_urgent(frame, app, right[0]);
+    draw_members(frame, app, right[1]);
+
+    let footer = match &app.input {
+        Some(input) => Line::from(vec![Span::styled("Search: ", Style::default().add_modifier(Modifier::BOLD)), Span::raw(input.as_str())]),
+        None if !app.status.is_empty() => Line::from(app.status.as_str()),
+        None => Line::from(Span::styled(HELP, Style::default().fg(Color::DarkGray))),
+    };
+    frame.render_widget(Paragraph::new(footer).block(Block::default().borders(Borders::ALL)), rows[1]);
+}
+
+/// Helper: bordered block whose border is highlighted when the pane has focus.
+fn pane_block(title: String, focused: bool) -> Block<'static> {
+    let style = if focused { Style::default().fg(Color::Cyan) } else { Style::default() };
+    Block::default().borders(Borders::ALL).border_style(style).title(title)
+}
+
+fn highlight() -> Style {
+    Style::default().add_modifier(Modifier::REVERSED)
+}
+
+fn draw_chats(frame: &mut Frame, app: &mut App, area: Rect) {
+    let items: Vec<ListItem> = app.chats.iter().map(|c| {
+        let mut spans = vec![Span::raw(c.chat.name.clone())];
+        if c.chat.duplicate {
+            spans.push(Span::styled(" [dup]", Style::default().fg(Color::Yellow)));
+        }
+        if c.urgent_count > 0 {
+            spans.push(Span::styled(format!(" [{} urgent]", c.urgent_count), Style::default().fg(Color::Red)));
+        }
+        let mut lines = vec![Line::from(spans)];
+        lines.push(Line::from(Span::styled(
+            format!("  {}{}  →  {}{}",
+                c.chat.category.as_deref().unwrap_or("Uncategorized"),
+                decision_mark(c.chat.category_decision.as_deref()),
+                c.chat.suggested_name.as_deref().unwrap_or("-"),
+                decision_mark(c.chat.name_decision.as_deref())),
+            Style::default().fg(Color::DarkGray),
+        )));
+        ListItem::new(lines)
+    }).collect();
+    let title = format!("Chats ({}) — {}", app.chats.len(), app.category.map_or("all", |i| app.categories[i].as_str()));
+    let list = List::new(items).block(pane_block(title, app.focus == Pane::Chats)).highlight_style(highlight());
+    frame.render_stateful_widget(list, area, &mut app.chat_state);
+}
+
+/// Helper: marker for a reviewed suggestion.
+fn decision_mark(decision: Option<&str>) -> &'static str {
+    match decision {
+        Some("accepted") => " ✓",
+        Some("rejected") => " ✗",
+        _ => "",
+    }
+}
+
+fn draw_timeline(frame: &mut Frame, app: &mut App, area: Rect) {
+    let focused = app.focus == Pane::Timeline;
+    if let Some((query, hits)) = &app.search {
+        let items: Vec<ListItem> = hits.iter().map(|h| {
+            ListItem::new(vec![
+                Line::from(Span::styled(format!("{}  {}", format_timestamp(h.message.date), h.chat_name), Style::default().fg(Color::DarkGray))),
+                Line::from(h.message.text.replace('\n', " ")),
+            ])
+        }).collect();
+        let list = List::new(items)
+            .block(pane_block(format!("Search: {}", query), focused))
+            .highlight_style(highlight());
+        frame.render_stateful_widget(list, area, &mut app.timeline_state);
+        return;
+    }
+    let Some(chat) = app.selected_chat() else {
+        frame.render_widget(Paragraph::new("No chat selected.").block(pane_block("Timeline".into(), focused)), area);
+        return;
+    };
+    let title = format!("Timeline — {}", chat.chat.name);
+    let items: Vec<ListItem> = app.timeline.iter().map(|m| {
+        let style = if m.urgent { Style::default().fg(Color::Red) } else { Style::default() };
+        ListItem::new(vec![
+            Line::from(Span::styled(format_timestamp(m.date), Style::default().fg(Color::DarkGray))),
+            Line::from(Span::styled(m.text.replace('\n', " "), style)),
+        ])
+    }).collect();
+    let list = List::new(items).block(pane_block(title, focused)).highlight_style(highlight());
+    frame.render_stateful_widget(list, area, &mut app.timeline_state);
+}
+
+fn draw_urgent(frame: &mut Frame, app: &mut App, area: Rect) {
+    let items: Vec<ListItem> = app.urgent.iter().map(|h| {
+        let mut header = vec![Span::styled(h.chat_name.clone(), Style::default().add_modifier(Modifier::BOLD))];
+        if h.mentions_me {
+            header.push(Span::styled(" (to you)", Style::default().fg(Color::Yellow)));
+        }
+        if h.urgent_state.as_deref() == Some("acknowledged") {
+            header.push(Span::styled(" (ack)", Style::default().fg(Color::DarkGray)));
+        }
+        ListItem::new(vec![
+            Line::from(header),
+            Line::from(h.message.text.replace('\n', " ")),
+        ])
+    }).collect();
+    let title = format!("Urgent ({})", app.urgent.len());
+    let list = List::new(items).block(pane_block(title, app.focus == Pane::Urgent)).highlight_style(highlight());
+    frame.render_stateful_widget(list, area, &mut app.urgent_state);
+}
+
+fn draw_members(frame: &mut Frame, app: &mut App, area: Rect) {
+    let focused = app.focus == Pane::Members;
+    if app.members.is_empty() {
+        let text = Paragraph::new("No members known.").wrap(Wrap { trim: true });
+        frame.render_widget(text.block(pane_block("Members".into(), focused)), area);
+        return;
+    }
+    let items: Vec<ListItem> = app.members.iter().map(|(_id, name, username, _bio)| {
+        match username {
+            Some(username) => ListItem::new(format!("{} @{}", name, username)),
+            None => ListItem::new(name.clone()),
+        }
+    }).collect();
+    let title = format!("Members ({})", app.members.len());
+    let list = List::new(items).block(pane_block(title, focused)).highlight_style(highlight());
+    frame.render_stateful_widget(list, area, &mut app.members_state);
+}
@@ -0,0 +1,47 @@
+use log::info;
+use libsql::Connection;
+use crate::database;
+use crate::database::{ChatFilter, UrgentState};
+use crate::report::format_timestamp;
+
+/// Urgent messages listed by `urgent list` unless `--limit` says otherwise.
+const DEFAULT_LIST_LIMIT: i64 = 50;
+
+/// Name recorded as the actor of CLI and TUI state changes: the OS user.
+pub fn current_user() -> String {
+    std::env::var("USER")
+        .or_else(|_| std::env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".to_string())
+}
+
+/// Print urgent messages in the given states, those addressed to us first, then newest first.
+pub async fn list(conn: &Connection, filter: &ChatFilter, states: &[UrgentState], limit: Option<i64>)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let items = database::get_urgent_messages(conn, filter, states, limit.unwrap_or(DEFAULT_LIST_LIMIT)).await?;
+    if items.is_empty() {
+        println!("No urgent messages.");
+        return Ok(());
+    }
+    for item in &items {
+        let state = item.urgent_state.as_deref().unwrap_or("new");
+        println!("{}  #{}  [{}] {}{} — {}", item.message.chat_peer, item.message.msg_id, state, item.chat_name,
+            if item.mentions_me { " (to you)" } else { "" }, format_timestamp(item.message.date));
+        if let (Some(by), Some(at)) = (&item.urgent_state_by, item.urgent_state_at) {
+            println!("    {} by {} at {}", state, by, format_timestamp(at as i32));
+        }
+        println!("    {}", item.message.text.replace('\n', " "));
+    }
+    Ok(())
+}
+
+/// Move an urgent message to `state` on behalf of `by`.
+pub async fn set_state(conn: &mut Connection, chat_peer: &str, msg_id: i32, state: UrgentState, by: &str)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    if !database::set_urgent_state(conn, chat_peer, msg_id, state, by).await? {
+        return Err(format!("No urgent message #{} in {}", msg_id, chat_peer).into());
+    }
+    info!("Urgent message #{} in {} marked {} by {}", msg_id, chat_peer, state.as_str(), by);
+    Ok(())
+}
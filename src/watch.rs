@@ -0,0 +1,292 @@
+use std::collections::HashMap;
+use libsql::Connection;
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+use crate::database::{self, ChatFilter};
+use crate::report::format_timestamp;
+use crate::telegram::MessageInfo;
+
+/// Default location of the watchlist file (overridable via WATCHLIST_FILE).
+const DEFAULT_WATCHLIST_FILE: &str = "watchlists.json";
+/// Default number of matches shown by `watch hits`.
+const DEFAULT_HITS_LIMIT: i64 = 50;
+/// Longest message excerpt shown by `watch hits`.
+const EXCERPT_CHARS: usize = 80;
+
+/// One watchlist as written in the watchlist file.
+#[derive(Debug, Clone, Deserialize)]
+struct WatchlistConfig {
+    name: String,
+    /// Words or phrases matched literally.
+    #[serde(default)]
+    terms: Vec<String>,
+    /// Regular expressions, e.g. a wallet address format.
+    #[serde(default)]
+    patterns: Vec<String>,
+    #[serde(default)]
+    case_sensitive: bool,
+    /// Match accented letters exactly instead of ignoring diacritics.
+    #[serde(default)]
+    keep_diacritics: bool,
+    /// Match terms only as whole words (so `down` doesn't match `download`).
+    #[serde(default = "default_true")]
+    whole_words: bool,
+    /// Send matches to the notification sinks; otherwise they are only recorded.
+    #[serde(default = "default_true")]
+    notify: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// A compiled term or pattern.
+struct Matcher {
+    term: String,  // as configured
+    regex: Regex,
+}
+
+/// A compiled watchlist.
+pub struct Watchlist {
+    pub name: String,
+    matchers: Vec<Matcher>,
+    fold_diacritics: bool,
+    pub notify: bool,
+}
+
+/// A watchlist term found in a message.
+#[derive(Debug, Clone)]
+pub struct WatchMatch {
+    pub watchlist: String,
+    pub term: String,
+    pub matched: String,
+    pub notify: bool,
+}
+
+/// Terms and patterns that are matched on every stored message, independently of the model.
+/// Loaded from a JSON file (WATCHLIST_FILE, default `watchlists.json`) holding a list of
+/// watchlists with a `name`, literal `terms` and regex `patterns`; matching ignores case and
+/// diacritics unless `case_sensitive` or `keep_diacritics` is set. Without the file nothing is watched.
+pub struct Watchlists {
+    lists: Vec<Watchlist>,
+}
+
+impl Watchlists {
+    /// Load the watchlist file; without one there are no watchlists.
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let path = std::env::var("WATCHLIST_FILE").unwrap_or_else(|_| DEFAULT_WATCHLIST_FILE.to_string());
+        let configs: Vec<WatchlistConfig> = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)
+                .map_err(|e| format!("Invalid watchlist file {}: {}", path, e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(format!("Failed to read watchlist file {}: {}", path, e).into()),
+        };
+        let lists = configs.iter()
+            .map(compile)
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid watchlist file {}: {}", path, e))?;
+        Ok(Watchlists { lists })
+    }
+
+    pub fn lists(&self) -> &[Watchlist] {
+        &self.lists
+    }
+
+    /// Every watchlist term found in a text, once per term.
+    pub fn scan(&self, text: &str) -> Vec<WatchMatch> {
+        let mut folded: Option<String> = None;
+        let mut matches = Vec::new();
+        for list in &self.lists {
+            let haystack = if list.fold_diacritics {
+                folded.get_or_insert_with(|| fold_diacritics(text)).as_str()
+            } else {
+                text
+            };
+            for matcher in &list.matchers {
+                if let Some(m) = matcher.regex.find(haystack) {
+                    matches.push(WatchMatch {
+                        watchlist: list.name.clone(),
+                        term: matcher.term.clone(),
+                        matched: m.as_str().trim().to_string(),
+                        notify: list.notify,
+                    });
+                }
+            }
+        }
+        matches
+    }
+}
+
+/// Helper: compile one watchlist's terms and patterns.
+fn compile(config: &WatchlistConfig) -> Result<Watchlist, String> {
+    let name = config.name.trim();
+    if name.is_empty() {
+        return Err("a watchlist has no name".to_string());
+    }
+    let fold = !config.keep_diacritics;
+    let mut matchers = Vec::new();
+    for term in config.terms.iter().map(|t| t.trim()).filter(|t| !t.is_empty()) {
+        let literal = if fold { fold_diacritics(term) } else { term.to_string() };
+        let mut pattern = regex::escape(&literal);
+        if config.whole_words {
+            // Only anchor ends that are word characters, so terms like `@name` or `$ACME` still match
+            if literal.chars().next().map_or(false, is_word_char) {
+                pattern = format!(r"\b{}", pattern);
+            }
+            if literal.chars().last().map_or(false, is_word_char) {
+                pattern = format!(r"{}\b", pattern);
+            }
+        }
+        matchers.push(Matcher { term: term.to_string(), regex: build(&pattern, config.case_sensitive, term)? });
+    }
+    for pattern in config.patterns.iter().filter(|p| !p.is_empty()) {
+        // Patterns run on the folded text too, so their accented letters are folded alike
+        let source = if fold { fold_diacritics(pattern) } else { pattern.clone() };
+        matchers.push(Matcher { term: pattern.clone(), regex: build(&source, config.case_sensitive, pattern)? });
+    }
+    if matchers.is_empty() {
+        return Err(format!("watchlist \"{}\" has no terms or patterns", name));
+    }
+    Ok(Watchlist { name: name.to_string(), matchers, fold_diacritics: fold, notify: config.notify })
+}
+
+/// Helper: compile a regex, naming the configured term on error.
+fn build(pattern: &str, case_sensitive: bool, term: &str) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(!case_sensitive)
+        .build()
+        .map_err(|e| format!("invalid pattern \"{}\": {}", term, e))
+}
+
+/// Helper: characters that `\b` treats as part of a word.
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Helper: strip diacritics, e.g. `Café` becomes `Cafe`.
+fn fold_diacritics(text: &str) -> String {
+    text.nfd().filter(|c| !is_combining_mark(*c)).nfc().collect()
+}
+
+/// Match freshly stored messages against the watchlists and store the matches.
+/// Returns the number of new matches the notification sinks should deliver.
+pub async fn scan_messages(conn: &mut Connection, watchlists: &Watchlists, chat_peer: &str, messages: &[MessageInfo])
+    -> Result<usize, Box<dyn std::error::Error>>
+{
+    if watchlists.lists.is_empty() {
+        return Ok(0);
+    }
+    let mut hits = Vec::new();
+    for msg in messages {
+        for m in watchlists.scan(&msg.text) {
+            hits.push((msg.msg_id, m.watchlist, m.term, m.matched, m.notify));
+        }
+    }
+    database::store_watch_hits(conn, chat_peer, &hits).await
+}
+
+/// Print the watchlists with the number of matches per term.
+pub async fn print_lists(conn: &Connection, watchlists: &Watchlists) -> Result<(), Box<dyn std::error::Error>> {
+    if watchlists.lists.is_empty() {
+        println!("No watchlists. Add some to {} (see README).",
+            std::env::var("WATCHLIST_FILE").unwrap_or_else(|_| DEFAULT_WATCHLIST_FILE.to_string()));
+        return Ok(());
+    }
+    let counts: HashMap<(String, String), (i64, i64)> = database::get_watch_counts(conn).await?
+        .into_iter()
+        .map(|(list, term, hits, last)| ((list, term), (hits, last)))
+        .collect();
+    for list in &watchlists.lists {
+        println!("{}{}", list.name, if list.notify { "" } else { " (recorded only)" });
+        for matcher in &list.matchers {
+            match counts.get(&(list.name.clone(), matcher.term.clone())) {
+                Some((hits, last)) => println!("{:>6}  {} (last {})", hits, matcher.term, format_timestamp(*last as i32)),
+                None => println!("{:>6}  {}", 0, matcher.term),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Print the latest stored matches, newest first.
+pub async fn print_hits(conn: &Connection, filter: &ChatFilter, watchlist: Option<&str>, limit: Option<i64>)
+    -> Result<(), Box<dyn std::error::Error>>
+{
+    let hits = database::get_watch_hits(conn, filter, watchlist, limit.unwrap_or(DEFAULT_HITS_LIMIT)).await?;
+    if hits.is_empty() {
+        println!("No watchlist matches.");
+        return Ok(());
+    }
+    for hit in &hits {
+        let text: String = hit.text.replace('\n', " ").chars().take(EXCERPT_CHARS).collect();
+        println!("{}  {} #{}  [{}: {}] \"{}\"", format_timestamp(hit.date), hit.chat_name, hit.msg_id, hit.watchlist, hit.matched, text);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(terms: &[&str], patterns: &[&str]) -> WatchlistConfig {
+        WatchlistConfig {
+            name: "w".to_string(),
+            terms: terms.iter().map(|t| t.to_string()).collect(),
+            patterns: patterns.iter().map(|p| p.to_string()).collect(),
+            case_sensitive: false,
+            keep_diacritics: false,
+            whole_words: true,
+            notify: true,
+        }
+    }
+
+    /// Helper: the terms of a single watchlist found in a text.
+    fn found(config: WatchlistConfig, text: &str) -> Vec<String> {
+        let watchlists = Watchlists { lists: vec![compile(&config).unwrap()] };
+        watchlists.scan(text).into_iter().map(|m| m.term).collect()
+    }
+
+    #[test]
+    fn fold_diacritics_strips_accents_only() {
+        assert_eq!(fold_diacritics("Café Zürich"), "Cafe Zurich");
+        assert_eq!(fold_diacritics("naïve résumé"), "naive resume");
+        assert_eq!(fold_diacritics("plain $ACME @name"), "plain $ACME @name");
+    }
+
+    #[test]
+    fn terms_ignore_case_and_diacritics() {
+        assert_eq!(found(config(&["cafe"], &[]), "Meet at the CAFÉ"), vec!["cafe"]);
+        assert_eq!(found(config(&["café"], &[]), "meet at the cafe"), vec!["café"]);
+        let exact = WatchlistConfig { keep_diacritics: true, ..config(&["café"], &[]) };
+        assert!(found(exact, "meet at the cafe").is_empty());
+    }
+
+    #[test]
+    fn whole_words_only_anchor_word_ends() {
+        assert!(found(config(&["down"], &[]), "download the file").is_empty());
+        assert_eq!(found(config(&["down"], &[]), "server is down!"), vec!["down"]);
+        assert_eq!(found(config(&["@name"], &[]), "ping @name please"), vec!["@name"]);
+        assert!(found(config(&["@name"], &[]), "ping @names please").is_empty());
+        assert_eq!(found(config(&["$ACME"], &[]), "bought $acme today"), vec!["$ACME"]);
+        assert!(found(config(&["$ACME"], &[]), "bought $ACMEX today").is_empty());
+        let anywhere = WatchlistConfig { whole_words: false, ..config(&["down"], &[]) };
+        assert_eq!(found(anywhere, "download the file"), vec!["down"]);
+    }
+
+    #[test]
+    fn patterns_are_folded_like_the_text() {
+        assert_eq!(found(config(&[], &["café|caffè"]), "Caffè tomorrow?"), vec!["café|caffè"]);
+        assert_eq!(found(config(&[], &["café|caffè"]), "cafe tomorrow?"), vec!["café|caffè"]);
+        let exact = WatchlistConfig { keep_diacritics: true, ..config(&[], &["café"]) };
+        assert_eq!(found(exact, "Café tomorrow?"), vec!["café"]);
+    }
+
+    #[test]
+    fn invalid_patterns_name_the_pattern() {
+        let error = compile(&config(&[], &["(unclosed"])).err().unwrap();
+        assert!(error.contains("(unclosed"), "{}", error);
+        assert!(compile(&config(&[" "], &[])).is_err());
+    }
+}